@@ -0,0 +1,50 @@
+#!/bin/sh
+# Compares two builds of the compiler for a change that mustn't change the
+# code generated, like the hashed symbol and register tables:
+#
+#  - the assembly of every program in bench/samples must be identical at
+#    -O0, -O1 and -O2
+#  - the time the lower pass takes on a function with N locals, written by
+#    gen_locals.sh, is reported for both builds, the best of three runs
+#
+#   bench/compare.sh <old compile> <new compile> [N ...]
+#
+# N defaults to 1000 4000 16000. Exits with 1 if any assembly differs.
+old=${1:?usage: compare.sh <old compile> <new compile> [N ...]}
+new=${2:?usage: compare.sh <old compile> <new compile> [N ...]}
+shift 2
+sizes=${*:-1000 4000 16000}
+
+bench=$(cd "$(dirname "$0")" && pwd)
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+status=0
+
+echo "identical assembly:"
+for sample in "$bench"/samples/*.c; do
+  for level in -O0 -O1 -O2; do
+    "$old" $level -S "$sample" -o "$work/old.s" >/dev/null 2>&1 || { echo "  $old fails on $sample"; exit 2; }
+    "$new" $level -S "$sample" -o "$work/new.s" >/dev/null 2>&1 || { echo "  $new fails on $sample"; exit 2; }
+    if cmp -s "$work/old.s" "$work/new.s"; then
+      echo "  $(basename "$sample") $level: same"
+    else
+      echo "  $(basename "$sample") $level: DIFFERENT"
+      status=1
+    fi
+  done
+done
+
+# The lower pass time of f in ms, the best of three runs
+lower_time() {
+  for run in 1 2 3; do
+    "$1" -O0 -S --time-passes "$2" -o "$work/time.s" 2>&1 >/dev/null | awk '$2 == "lower" && $3 == "f" { print $4 }'
+  done | sort -n | head -1
+}
+
+echo "lower pass on f with N locals (-O0):"
+for n in $sizes; do
+  "$bench/gen_locals.sh" "$n" > "$work/locals.c"
+  echo "  N = $n: $(lower_time "$old" "$work/locals.c") ms -> $(lower_time "$new" "$work/locals.c") ms"
+done
+
+exit $status
@@ -0,0 +1,21 @@
+#!/bin/sh
+# Writes a program whose function f declares N locals in a straight line,
+# each computed from the one before it, a parameter and a constant.
+#
+#   bench/gen_locals.sh 4000 > locals.c
+n=${1:?usage: gen_locals.sh <number of locals>}
+
+echo "long f(long a) {"
+echo "  long v0 = a;"
+i=1
+while [ "$i" -lt "$n" ]; do
+  echo "  long v$i = v$((i - 1)) + a * $i;"
+  i=$((i + 1))
+done
+echo "  return v$((n - 1));"
+echo "}"
+echo
+echo "int main() {"
+echo "  long r = f(1);"
+echo "  return 0;"
+echo "}"
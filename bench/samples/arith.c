@@ -0,0 +1,20 @@
+// Arithmetic on parameters, locals and constants, including reassignments
+long mix(long a, long b, long c) {
+  long x = a * b + c;
+  long y = x - a / 2;
+  x = y * 3 - b;
+  y = x + y + 7;
+  return x * y - c * 11;
+}
+
+long big(long a) {
+  long k = 4294967296;
+  long m = a * 100000 + k;
+  return m - k / 2;
+}
+
+int main() {
+  long r = mix(6, 7, 8);
+  long s = big(r);
+  return s - r;
+}
@@ -0,0 +1,32 @@
+// Calls with register and stack arguments, small callees that get inlined
+// and calls in tail position
+long add(long a, long b) {
+  return a + b;
+}
+
+long eight(long a, long b, long c, long d, long e, long f, long g, long h) {
+  long low = a + b + c + d;
+  long high = e + f + g + h;
+  return low * 2 + high;
+}
+
+long countdown(long n, long acc) {
+  long m = n - 1;
+  return countdown_step(m, acc + n);
+}
+
+long countdown_step(long n, long acc) {
+  return add(n, acc);
+}
+
+long twice(long x) {
+  long once = add(x, x);
+  long r = eight(once, 1, 2, 3, 4, 5, 6, x);
+  return add(r, once);
+}
+
+int main() {
+  long a = twice(5);
+  long b = countdown(4, a);
+  return b;
+}
@@ -0,0 +1,25 @@
+// Nested blocks that shadow and release locals, whose slots are reused
+long scopes(long a) {
+  long x = a + 1;
+  {
+    long x = a * 2;
+    long y = x + 3;
+    {
+      long z = x * y;
+      a = a + z;
+    }
+    long w = y - 1;
+    a = a + w;
+  }
+  {
+    long y = x + a;
+    a = y * 2;
+  }
+  long y = a - x;
+  return y + x;
+}
+
+int main() {
+  long r = scopes(3);
+  return r;
+}
@@ -4,117 +4,279 @@
 ************************************************************************
 */
 #![allow(non_camel_case_types)]
+#![allow(clippy::upper_case_acronyms)]
 #![allow(non_snake_case)]
 #![allow(dead_code)]
+#![allow(clippy::while_let_loop)]
 extern crate libc;
 use crate::expression::*;
+use std::cell::Cell;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
-pub const INVAL: i64 = -999;
+// Bytes below %rsp that the System V ABI guarantees are not clobbered.
+pub const RED_ZONE_SIZE: i64 = 128;
+
+// Set from CodegenOptions::verbose by each codegen run, the [DEBUG] lines
+// are only written (to stderr) when it is.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if VERBOSE.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        }
+    };
+}
 
 /*
 *************************************************************************************
  USE A STRUCTURE TO STORE GLOBAL VARIABLES
 *************************************************************************************
 */
-struct globals {
-    // The last used offset will help determine the last used stack location
-    pub last_used_offset: i64,
+struct globals<'a> {
+    // Bytes of stack slots handed out so far, the frame is sized from this
+    // once the whole function has been lowered.
+    pub frame_size: i64,
+    // Offsets of slots that were released and can be handed out again.
+    pub free_slots: Vec<i64>,
+    // Offsets of the temporaries holding intermediate results, innermost last.
+    pub temp_slots: Vec<i64>,
     pub last_offset_used: String,
     // The arg counter is used to iterate through the arg list.
     pub arg_counter: i64,
+    // Name of the function being generated, self tail calls jump back into it.
+    pub func_name: String,
+    // Register that stack slots are addressed from, %rbp unless the frame
+    // pointer is omitted in which case it is %rsp.
+    pub frame_base: String,
+    // Set for leaf functions whose locals fit below %rsp in the red zone.
+    pub uses_red_zone: bool,
+    // Numbers the local labels of the function so they are unique.
+    pub label_counter: i64,
+    // Variables of the function whose address is taken, they must always
+    // live in memory.
+    pub address_taken: Vec<String>,
+    // Options of this codegen run and every function declared in the file,
+    // needed to decide whether a call site can be inlined.
+    pub opts: &'a CodegenOptions,
+    pub functions: &'a [&'a RNode],
+    // Global variables declared in the file, visible in every function.
+    pub variables: &'a [&'a RNode],
+    // String and double literals of the file, placed in .rodata.
+    pub literals: &'a literalPool,
+    // Asm statements of the file lowered so far, the number %= stands for.
+    pub asm_count: &'a Cell<usize>,
+    // Slot holding the address a struct returned in memory is copied to,
+    // passed by the caller in %rdi. Empty for other functions.
+    pub return_address: String,
+    // Code of the landing pads the checked arithmetic jumps to, placed after
+    // the function. Each loads the location of its check and jumps to the
+    // function's trap stub.
+    pub trap_sites: Vec<String>,
+    // Callees being inlined, innermost last. A trap reports the function
+    // the failing operator was written in.
+    pub inlined: Vec<String>,
+    // Callee saved registers the asm statements of the function change and
+    // the slots the caller's values are kept in until the function returns.
+    pub saved_registers: Vec<(String, String)>,
 }
 
-impl globals {
-    fn new() -> Self {
+impl<'a> globals<'a> {
+    fn new(
+        opts: &'a CodegenOptions,
+        functions: &'a [&'a RNode],
+        variables: &'a [&'a RNode],
+        literals: &'a literalPool,
+        asm_count: &'a Cell<usize>,
+    ) -> Self {
         globals {
-            last_used_offset: 0,
+            frame_size: 0,
+            free_slots: vec![],
+            temp_slots: vec![],
             last_offset_used: "".to_string(),
             arg_counter: 0,
+            func_name: "".to_string(),
+            frame_base: "%rbp".to_string(),
+            uses_red_zone: false,
+            label_counter: 0,
+            address_taken: vec![],
+            opts,
+            functions,
+            variables,
+            literals,
+            asm_count,
+            return_address: "".to_string(),
+            trap_sites: vec![],
+            inlined: vec![],
+            saved_registers: vec![],
         }
     }
 }
+
 /*
 *************************************************************************************
-     THE REGINFO LIST TRACKS IF REGISTERS ARE AVAILABLE FOR USE
-**************************************************************************************
+ OPTIONS THAT CONTROL CODE GENERATION
+*************************************************************************************
 */
-
 #[derive(Clone)]
-struct regInfo {
-    name: String,
-    avail: i8,
-    next: LinkInfo,
+pub struct CodegenOptions {
+    // Optimization level the options below were derived from (0, 1 or 2).
+    pub opt_level: u8,
+    // Names of the passes run over each function, in order.
+    pub passes: Vec<String>,
+    // Callees whose body is at most this many AST nodes are inlined at their
+    // call sites. Zero disables inlining.
+    pub inline_threshold: usize,
+    // Lower calls in tail position to a jump instead of call + return.
+    pub tail_calls: bool,
+    // Skip the %rbp frame of red zone leaf functions and address their
+    // slots relative to %rsp.
+    pub omit_frame_pointer: bool,
+    // Passes after which the code of each function is dumped to stderr.
+    pub print_after: Vec<String>,
+    // Report how long every pass took on every function.
+    pub time_passes: bool,
+    // File the assembly is written to.
+    pub output_path: String,
+    // File the lowered function bodies are written to, before the peephole
+    // and prologue passes run, if set.
+    pub ir_path: Option<String>,
+    // Check every array index at run time and call bounds_trap with the
+    // index and the array length when it is out of range. The trap routine
+    // must not return.
+    pub bounds_check: bool,
+    pub bounds_trap: String,
+    // Check signed integer arithmetic for overflow and division for a zero
+    // divisor at run time, calling arith_trap with the function name, the
+    // line and column of the operator and the reason. The trap routine must
+    // not return.
+    pub checked_arith: bool,
+    pub arith_trap: String,
+    // Position independent code for shared libraries: calls go through the
+    // PLT and globals and function addresses are read from the GOT.
+    pub pic: bool,
+    // Visibility of the symbols defined in the file that don't have a
+    // visibility attribute.
+    pub visibility: Visibility,
+    // Trace what codegen does on stderr.
+    pub verbose: bool,
 }
 
-type LinkInfo = Option<Box<regInfo>>;
-
-impl Default for regInfo {
-    fn default() -> regInfo {
-        regInfo {
-            name: "".to_string(),
-            avail: 0,
-            next: None,
+impl CodegenOptions {
+    /*
+    ***********************************************************************
+      FUNCTION TO GET THE OPTIONS OF AN OPTIMIZATION LEVEL, LIKE -O0/-O1/-O2
+    ************************************************************************
+    */
+    pub fn with_level(level: u8) -> Self {
+        let passes: &[&str] = match level {
+            0 => &["lower", "prologue"],
+            _ => &["leaf-frame", "lower", "peephole", "prologue"],
+        };
+
+        CodegenOptions {
+            opt_level: level,
+            passes: passes.iter().map(|name| name.to_string()).collect(),
+            inline_threshold: if level >= 2 { 16 } else { 0 },
+            tail_calls: level >= 1,
+            omit_frame_pointer: false,
+            print_after: vec![],
+            time_passes: false,
+            output_path: "assembly.s".to_string(),
+            ir_path: None,
+            bounds_check: false,
+            bounds_trap: "__bounds_trap".to_string(),
+            checked_arith: false,
+            arith_trap: "__arith_trap".to_string(),
+            pic: false,
+            visibility: Visibility::DEFAULT,
+            verbose: false,
         }
     }
 }
 
+impl Default for CodegenOptions {
+    fn default() -> Self {
+        CodegenOptions::with_level(2)
+    }
+}
+/*
+*************************************************************************************
+     THE REGINFO TABLE TRACKS IF REGISTERS ARE AVAILABLE FOR USE. EVERY REGISTER
+     GETS A BIT IN THE FREE MASK, IN THE ORDER THE REGISTERS WERE ADDED, SO THE
+     NEXT AVAILABLE REGISTER IS THE LOWEST SET BIT.
+**************************************************************************************
+*/
+
+// The free mask is a u32
+const MAX_REGS: usize = 32;
+
 struct regList {
-    pub head: LinkInfo,
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    free: u32,
 }
 
 impl regList {
     fn new() -> Self {
-        regList { head: None }
+        regList {
+            names: vec![],
+            index: HashMap::new(),
+            free: 0,
+        }
+    }
+
+    fn set_avail(&mut self, bit: usize, avail: i8) {
+        if avail == 1 {
+            self.free |= 1 << bit;
+        } else {
+            self.free &= !(1 << bit);
+        }
+    }
+
+    // The free registers, without the accumulator when noAcc is set
+    fn avail_mask(&self, noAcc: bool) -> u32 {
+        let mut mask = self.free;
+        if noAcc {
+            if let Some(&bit) = self.index.get("%rax") {
+                mask &= !(1 << bit);
+            }
+        }
+        mask
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO ADD NEW REGISTER INFORMATION TO THE REGISTER INFO LIST
+      FUNCTION TO ADD NEW REGISTER INFORMATION TO THE REGISTER INFO TABLE
     ************************************************************************
     */
     fn add_reg(&mut self, name: &str, avail: i8) {
-        let new_node = Box::new(regInfo {
-            name: name.to_string(),
-            avail: avail,
-            next: None,
-        });
-
-        if !self.head.is_none() {
-            let mut current = &mut self.head;
-            loop {
-                if let Some(node) = current.as_mut() {
-                    if node.next.is_none() {
-                        node.next = Some(new_node);
-                        break;
-                    }
-                    current = &mut node.next;
-                } else {
-                    break;
+        let bit = match self.index.get(name) {
+            Some(&bit) => bit,
+            None => {
+                if self.names.len() == MAX_REGS {
+                    panic!("Error: more than {} registers in the register list", MAX_REGS);
                 }
+                self.names.push(name.to_string());
+                self.index.insert(name.to_string(), self.names.len() - 1);
+                self.names.len() - 1
             }
-        } else {
-            self.head = Some(new_node);
-        }
+        };
+        self.set_avail(bit, avail);
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO UPDATE THE AVAILIBILITY OF REGISTERS IN THE REG INFO LIST
+      FUNCTION TO UPDATE THE AVAILIBILITY OF REGISTERS IN THE REG INFO TABLE
     ************************************************************************
     */
     fn update_reg_info(&mut self, name: String, avail: i8) {
-        let mut current = &mut self.head;
-        loop {
-            if let Some(node) = current.as_mut() {
-                if node.name == name {
-                    node.avail = avail;
-                }
-                current = &mut node.next;
-            } else {
-                break;
-            }
+        if let Some(&bit) = self.index.get(&name) {
+            self.set_avail(bit, avail);
         }
     }
 
@@ -124,30 +286,15 @@ impl regList {
     ************************************************************************
     */
     fn get_next_avail_reg(&self, noAcc: bool) -> String {
-        let mut current = &self.head;
-
-        if current.is_none() {
+        if self.names.is_empty() {
             println!("List is empty");
         }
 
-        loop {
-            if let Some(node) = current.as_ref() {
-                if node.avail == 1 {
-                    if !noAcc {
-                        return node.name.clone();
-                    }
-                    // if not rax and dont return accumulator set to true, return the other reg
-                    // if rax and noAcc == true, skip to next avail
-                    if noAcc && !(node.name == "%rax") {
-                        return node.name.clone();
-                    }
-                }
-                current = &node.next;
-            } else {
-                break;
-            }
+        let mask = self.avail_mask(noAcc);
+        if mask == 0 {
+            return "NoReg".to_string();
         }
-        return "NoReg".to_string();
+        self.names[mask.trailing_zeros() as usize].clone()
     }
 
     /*
@@ -157,28 +304,15 @@ impl regList {
     ************************************************************************
     */
     fn if_avail_reg(&self, noAcc: bool) -> usize {
-        let mut current = &self.head;
-
-        if current.is_none() {
+        if self.names.is_empty() {
             println!("Empty reglist");
         }
 
-        loop {
-            if let Some(node) = current.as_ref() {
-                if node.avail == 1 {
-                    // registers available
-                    if !noAcc {
-                        return 1;
-                    } else if noAcc && !(node.name == "%rax") {
-                        return 1;
-                    }
-                }
-                current = &node.next;
-            } else {
-                break;
-            }
+        if self.avail_mask(noAcc) != 0 {
+            1
+        } else {
+            0
         }
-        return 0;
     }
 
     /*
@@ -187,55 +321,30 @@ impl regList {
     ************************************************************************
     */
     fn is_avail_reg(&self, name: String) -> bool {
-        let mut current = &self.head;
-
-        if current.is_none() {
-            println!("Empty reglist");
-        }
-
-        loop {
-            if let Some(node) = current.as_ref() {
-                if node.name == name {
-                    if node.avail == 1 {
-                        return true;
-                    }
-                }
-                current = &node.next;
-            } else {
-                break;
-            }
+        match self.index.get(&name) {
+            Some(&bit) => self.free & (1 << bit) != 0,
+            None => false,
         }
-        return false;
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO FREE REGISTER INFORMATION LIST
+      FUNCTION TO FREE REGISTER INFORMATION TABLE
     ************************************************************************
     */
     fn free_list(&mut self) {
-        while !self.head.is_none() {
-            self.head.take().map(|node| {
-                self.head = node.next;
-            });
-        }
+        self.names.clear();
+        self.index.clear();
+        self.free = 0;
     }
 
     fn print_list(&self) {
-        let mut current = &self.head;
-
-        if current.is_none() {
+        if self.names.is_empty() {
             println!("Empty reglist");
         }
 
-        loop {
-            if let Some(node) = current.as_ref() {
-                print!("\t {} : {} -> ", node.name, node.avail);
-
-                current = &node.next;
-            } else {
-                break;
-            }
+        for (bit, name) in self.names.iter().enumerate() {
+            print!("\t {} : {} -> ", name, (self.free >> bit) & 1);
         }
         println!();
     }
@@ -243,7 +352,10 @@ impl regList {
 
 /*
 *************************************************************************************
-     THE VARSTOREINFO LIST TRACKS A VARIABLE NAME, VALUE AND WHERE IT IS STORED
+     THE VARSTOREINFO TABLE TRACKS A VARIABLE NAME, VALUE AND WHERE IT IS STORED.
+     VARIABLES ARE KEPT IN ONE MAP PER OPEN SCOPE, INNERMOST LAST, AND A LOOKUP
+     WALKS OUTWARD THROUGH THE ENCLOSING SCOPES. CONSTANTS ARE KEPT IN A SEPARATE
+     POOL KEYED BY VALUE.
 **************************************************************************************
 */
 
@@ -252,83 +364,162 @@ struct varStoreInfo {
     name: String,
     // FLAG TO IDENTIFY IF A VARIABLE IS A CONSTANT OR NOT.
     is_const: bool,
-    value: i64,
+    // VALUE HELD, KNOWN FOR CONSTANTS ONLY
+    value: Option<i64>,
     // LOCATION COULD BE A STACK LOCATION OR A REGISTER
     // eg: -8(%rbp) or %rcx
     location: String,
-    next: LinkVar,
+    // TYPE OF A VARIABLE, AN ARRAY'S LOCATION IS ITS FIRST ELEMENT
+    dataType: DataType,
+    // ORDER THE ENTRY WAS ADDED IN, KEEPS THE OUTPUT INDEPENDENT OF HASHING
+    seq: u64,
 }
 
-type LinkVar = Option<Box<varStoreInfo>>;
-
 impl Default for varStoreInfo {
     fn default() -> varStoreInfo {
         varStoreInfo {
             name: "".to_string(),
             is_const: false,
-            value: 0,
+            value: None,
             location: "".to_string(),
-            next: None,
+            dataType: DataType::LONG,
+            seq: 0,
         }
     }
 }
 
+// Where the entry stored at a location can be found
+#[derive(Clone, PartialEq)]
+enum varKey {
+    Variable(usize, String),
+    Constant(i64),
+}
+
 struct varStList {
-    head: LinkVar,
+    scopes: Vec<HashMap<String, varStoreInfo>>,
+    // Several temporaries can hold the same value, the newest is found first
+    constants: HashMap<i64, Vec<varStoreInfo>>,
+    locations: HashMap<String, varKey>,
+    next_seq: u64,
 }
 
 impl varStList {
     fn new() -> Self {
-        varStList { head: None }
+        varStList {
+            scopes: vec![HashMap::new()],
+            constants: HashMap::new(),
+            locations: HashMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    // Depth of the innermost scope, 0 is the function body
+    fn depth(&self) -> usize {
+        self.scopes.len() - 1
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTIONS TO ENTER AND LEAVE A NESTED SCOPE. LEAVING DROPS EVERY
+      VARIABLE ADDED IN THE SCOPE AND RETURNS THEIR LOCATIONS AND TYPES,
+      NEWEST FIRST, SO THEIR STACK SLOTS CAN BE HANDED OUT AGAIN.
+    ************************************************************************
+    */
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn pop_scope(&mut self) -> Vec<(String, DataType)> {
+        if self.scopes.len() == 1 {
+            return vec![];
+        }
+        let depth = self.depth();
+
+        let mut entries: Vec<varStoreInfo> = match self.scopes.pop() {
+            Some(scope) => scope.into_values().collect(),
+            None => vec![],
+        };
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.seq));
+
+        let mut locations: Vec<(String, DataType)> = vec![];
+        for entry in entries {
+            let key = varKey::Variable(depth, entry.name.clone());
+            if self.locations.get(&entry.location) == Some(&key) {
+                self.locations.remove(&entry.location);
+            }
+            locations.push((entry.location, entry.dataType));
+        }
+        locations
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO ADD VARIABLE INFORMATION TO THE VARIABLE INFO LIST
+      FUNCTION TO ADD VARIABLE INFORMATION TO THE VARIABLE INFO TABLE
     ************************************************************************
     */
-    fn add_var_info(&mut self, varname: String, location: String, val: i64, is_const: bool) {
-        // push front like a stack
-        let new_node = Box::new(varStoreInfo {
-            name: varname,
-            is_const: is_const,
+    fn add_var_info(&mut self, varname: String, location: String, val: Option<i64>, is_const: bool) {
+        let info = varStoreInfo {
+            name: varname.clone(),
+            is_const,
             value: val,
             location: location.clone(),
-            next: self.head.take(),
-        });
-
-        self.head = Some(new_node);
+            dataType: DataType::LONG,
+            seq: self.next_seq,
+        };
+        self.next_seq += 1;
+
+        if let (true, Some(val)) = (is_const, val) {
+            self.constants.entry(val).or_default().push(info);
+            self.locations.insert(location, varKey::Constant(val));
+        } else {
+            let depth = self.depth();
+            self.scopes[depth].insert(varname.clone(), info);
+            self.locations.insert(location, varKey::Variable(depth, varname));
+        }
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO LOOKUP VARIABLE INFORMATION FROM THE VARINFO LIST
+      FUNCTIONS TO ADD A VARIABLE OF ANY TYPE AND TO LOOK UP ITS TYPE
     ************************************************************************
     */
-    fn lookup_var_info(&self, name: String, val: i64) -> String {
-        let mut current = &self.head;
+    fn add_typed_var_info(&mut self, varname: String, location: String, dataType: DataType) {
+        self.add_var_info(varname.clone(), location, None, false);
 
-        if current.is_none() {
-            println!("Empty varlist");
+        let depth = self.depth();
+        if let Some(info) = self.scopes[depth].get_mut(&varname) {
+            info.dataType = dataType;
         }
+    }
 
-        loop {
-            if let Some(node) = current.as_ref() {
-                if node.is_const == true {
-                    if node.value == val {
-                        return node.location.clone();
-                    }
-                } else {
-                    if node.name == name {
-                        return node.location.clone();
-                    }
-                }
-                current = &node.next;
-            } else {
-                break;
+    fn lookup_var_type(&self, name: &str) -> Option<DataType> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(info) = scope.get(name) {
+                return Some(info.dataType.clone());
+            }
+        }
+        None
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO LOOKUP VARIABLE INFORMATION FROM THE VARINFO TABLE
+    ************************************************************************
+    */
+    fn lookup_var_info(&self, name: String, val: Option<i64>) -> String {
+        for scope in self.scopes.iter().rev() {
+            if let Some(info) = scope.get(&name) {
+                return info.location.clone();
+            }
+        }
+
+        if let Some(pool) = val.and_then(|val| self.constants.get(&val)) {
+            if let Some(info) = pool.last() {
+                return info.location.clone();
             }
         }
-        return "".to_string();
+
+        "".to_string()
     }
 
     /*
@@ -336,63 +527,79 @@ impl varStList {
       FUNCTION TO UPDATE VARIABLE INFORMATION
     ************************************************************************
     */
-    fn update_var_info(&mut self, varName: String, location: String, val: i64, is_const: bool) {
-        if self.lookup_var_info(varName.clone(), val) == "".to_string() {
-            self.add_var_info(varName.clone(), location, val, is_const);
-        } else {
-            let mut current = &mut self.head;
+    fn update_var_info(&mut self, varName: String, location: String, val: Option<i64>, is_const: bool) {
+        let depth = (0..self.scopes.len()).rev().find(|&depth| self.scopes[depth].contains_key(&varName));
+
+        let depth = match depth {
+            Some(depth) => depth,
+            None => {
+                self.add_var_info(varName, location, val, is_const);
+                return;
+            }
+        };
 
-            if current.is_none() {
-                println!("Empty varlist");
+        if let Some(info) = self.scopes[depth].get_mut(&varName) {
+            let key = varKey::Variable(depth, varName.clone());
+            if self.locations.get(&info.location) == Some(&key) {
+                self.locations.remove(&info.location);
             }
+            self.locations.insert(location.clone(), key);
+
+            info.value = val;
+            info.location = location;
+            info.is_const = is_const;
+        }
+    }
 
-            loop {
-                if let Some(node) = current.as_mut() {
-                    if node.name == varName {
-                        node.value = val;
-                        node.location = location;
-                        node.is_const = is_const;
-                        break;
+    /*
+    ***********************************************************************
+      FUNCTION TO REMOVE THE ENTRY STORED AT A LOCATION FROM THE TABLE
+    ************************************************************************
+    */
+    fn remove_location(&mut self, location: &str) {
+        match self.locations.remove(location) {
+            Some(varKey::Constant(val)) => {
+                if let Some(pool) = self.constants.get_mut(&val) {
+                    pool.retain(|info| info.location != location);
+                    if pool.is_empty() {
+                        self.constants.remove(&val);
                     }
-                    current = &mut node.next;
-                } else {
-                    break;
                 }
             }
+            Some(varKey::Variable(depth, name)) => {
+                if let Some(scope) = self.scopes.get_mut(depth) {
+                    scope.remove(&name);
+                }
+            }
+            None => {}
         }
     }
 
     /*
     ***********************************************************************
-      FUNCTION TO FREE THE VARIABLE INFORMATION LIST
+      FUNCTION TO FREE THE VARIABLE INFORMATION TABLE
     ************************************************************************
     */
     fn free_list(&mut self) {
-        while !self.head.is_none() {
-            self.head.take().map(|node| {
-                self.head = node.next;
-            });
-        }
+        self.scopes = vec![HashMap::new()];
+        self.constants.clear();
+        self.locations.clear();
     }
 
     fn print_list(&self) {
-        let mut current = &self.head;
+        let mut entries: Vec<&varStoreInfo> = self.scopes.iter().flat_map(|scope| scope.values()).collect();
+        entries.extend(self.constants.values().flatten());
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.seq));
 
-        if current.is_none() {
+        if entries.is_empty() {
             println!("Empty varlist");
         }
 
-        loop {
-            if let Some(node) = current.as_ref() {
-                if !node.is_const {
-                    print!("\t {} : {} -> ", node.name, node.location);
-                } else {
-                    print!("\t {} : {} -> ", node.value, node.location);
-                }
-
-                current = &node.next;
+        for node in entries {
+            if !node.is_const {
+                print!("\t {} : {} -> ", node.name, node.location);
             } else {
-                break;
+                print!("\t {} : {} -> ", node.value.unwrap_or(0), node.location);
             }
         }
         println!();
@@ -406,14 +613,19 @@ impl varStList {
 **************************************************************************************
 */
 #[no_mangle]
-fn init_asm(fileptr: &mut File, funcName: String) {
+fn init_asm(fileptr: &mut dyn Write, funcName: String, glb: &globals) {
     fileptr
-        .write_all(format!("\n.globl {}", funcName).as_bytes())
+        .write_all(symbol_directives(glb, &funcName, "@function").as_bytes())
         .expect("Unable to write data");
     fileptr
         .write_all(format!("\n{}:", funcName).as_bytes())
         .expect("Unable to write data");
 
+    if glb.frame_base != "%rbp" {
+        // Frame pointer omitted, slots are addressed from %rsp
+        return;
+    }
+
     // Iinitialize the stack and base pointer
     fileptr
         .write_all("\npushq %rbp".as_bytes())
@@ -421,6 +633,16 @@ fn init_asm(fileptr: &mut File, funcName: String) {
     fileptr
         .write_all("\nmovq %rsp, %rbp".as_bytes())
         .expect("Unable to write data");
+
+    // The body has already been lowered so the frame size is exact. Keep
+    // %rsp 16 byte aligned for the calls made from the body.
+    let stack_size = (glb.frame_size + 15) / 16 * 16;
+    if !glb.uses_red_zone && stack_size > 0 {
+        debug!("[DEBUG] Allocating {} bytes on the stack for {}.", stack_size, funcName);
+        fileptr
+            .write_all(format!("\nsubq ${}, %rsp  # Allocate stack space", stack_size).as_bytes())
+            .expect("Failed to allocate stack space");
+    }
 }
 
 /*
@@ -429,15 +651,21 @@ fn init_asm(fileptr: &mut File, funcName: String) {
 ****************************************************************************
 */
 #[no_mangle]
-fn ret_asm(fileptr: &mut File, glb: &globals) {
-    if glb.last_used_offset + 8 < 0 {
+fn ret_asm(fileptr: &mut dyn Write, glb: &globals) {
+    restore_registers(fileptr, glb);
+
+    // The frame size isn't known yet when a return is lowered, so restore
+    // %rsp from the frame pointer instead of adding the size back
+    if !glb.uses_red_zone {
         fileptr
-            .write_all(format!("\naddq ${}, %rsp  # Deallocate stack space", -(glb.last_used_offset + 8)).as_bytes())
+            .write_all("\nmovq %rbp, %rsp  # Deallocate stack space".as_bytes())
             .expect("Failed to deallocate stack space");
     }
-    fileptr
-        .write_all("\npopq %rbp".as_bytes())
-        .expect("Unable to write data");
+    if glb.frame_base == "%rbp" {
+        fileptr
+            .write_all("\npopq %rbp".as_bytes())
+            .expect("Unable to write data");
+    }
     fileptr
         .write_all("\nretq\n".as_bytes())
         .expect("Unable to write data");
@@ -449,23 +677,17 @@ fn ret_asm(fileptr: &mut File, glb: &globals) {
 ****************************************************************************
 */
 #[no_mangle]
-fn long_to_char_offset(glb: &mut globals) {
-    if glb.last_used_offset == 0 {
-        glb.last_used_offset -= 8;
-        println!("[DEBUG] First constant stored, offset moved to: {}", glb.last_used_offset);
-    }
-
-    // glb.last_used_offset -= 8;
-    println!("\n[DEBUG] Offset is now(long_to_char): {}...", glb.last_used_offset);
+fn long_to_char_offset(glb: &mut globals, offset: i64) {
+    debug!("\n[DEBUG] Offset is now(long_to_char): {}...", offset);
 
-    glb.last_offset_used = format!("{}", glb.last_used_offset);
+    glb.last_offset_used = format!("{}", offset);
 
     // ensure no more than 100 characters are used
     if glb.last_offset_used.len() > 100 {
         glb.last_offset_used.truncate(100);
     }
 
-    glb.last_offset_used.push_str("(%rbp)");
+    glb.last_offset_used.push_str(&format!("({})", glb.frame_base));
 }
 
 /*
@@ -475,22 +697,26 @@ fn long_to_char_offset(glb: &mut globals) {
 */
 #[no_mangle]
 fn save_val_rax(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     name: String,
     glb: &mut globals,
     var_list: &mut varStList,
     reg_list: &mut regList,
 ) {
-    let temp_reg = reg_list.get_next_avail_reg(true);
+    // A variable whose address is taken can't be kept only in a register
+    let mut temp_reg = reg_list.get_next_avail_reg(true);
+    if glb.address_taken.contains(&name) {
+        temp_reg = "NoReg".to_string();
+    }
 
     if temp_reg == "NoReg" {
-        long_to_char_offset(glb);
+        new_stack_slot(glb);
 
         fileptr
             .write_all(format!("\n movq %rax, {}", glb.last_offset_used).as_bytes())
             .expect("Unable to write data");
 
-        var_list.update_var_info(name, glb.last_offset_used.clone(), INVAL, false);
+        var_list.update_var_info(name, glb.last_offset_used.clone(), None, false);
         reg_list.update_reg_info("%rax".to_string(), 1);
     } else {
         fileptr
@@ -498,7 +724,7 @@ fn save_val_rax(
             .expect("Unable to write data");
 
         reg_list.update_reg_info(temp_reg.clone(), 0);
-        var_list.update_var_info(name, temp_reg, INVAL, false);
+        var_list.update_var_info(name, temp_reg, None, false);
         reg_list.update_reg_info("%rax".to_string(), 1);
     }
 }
@@ -515,9 +741,10 @@ fn create_reg_list(reg_list: &mut regList) {
               TODO : YOUR CODE HERE
      ***************************************
     */
-    //general purpose registers
+    //general purpose registers, rbx is callee saved and only used when an
+    //asm statement names it
     reg_list.add_reg("%rax", 1);
-    reg_list.add_reg("%rbx", 1);
+    reg_list.add_reg("%rbx", 0);
     reg_list.add_reg("%rcx", 1);
     reg_list.add_reg("%rdx", 1);
 
@@ -530,8 +757,285 @@ fn create_reg_list(reg_list: &mut regList) {
     //other registers
     reg_list.add_reg("%r8", 1);
     reg_list.add_reg("%r9", 1);
+    reg_list.add_reg("%r10", 1);
+    reg_list.add_reg("%r11", 1);
+
+    //callee saved, like rbx
+    for register in CALLEE_SAVED_REGISTERS.iter().skip(1) {
+        reg_list.add_reg(register, 0);
+    }
+
+    //SSE registers pass double arguments and return values, xmm0 and xmm1
+    //are also the scratch registers of double arithmetic
+    for xmm in FLOAT_ARG_REGISTERS.iter() {
+        reg_list.add_reg(xmm, 0);
+    }
+
+}
+/*
+***********************************************************************
+  FUNCTION TO ASSIGN ARGUMENTS OF THE GIVEN TYPES TO REGISTERS, ONE PER
+  EIGHTBYTE. INTEGERS AND POINTERS TAKE THE NEXT OF THE SIX INTEGER
+  REGISTERS, DOUBLES THE NEXT OF XMM0-XMM7 AND A SMALL STRUCT ONE OF
+  EITHER FOR EACH OF ITS EIGHTBYTES. NONE FOR AN ARGUMENT PASSED ON THE
+  STACK, WHICH IS WHERE A STRUCT GOES WHOLE IF IT DOESN'T FIT.
+************************************************************************
+*/
+const ARG_REGISTERS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+const FLOAT_ARG_REGISTERS: [&str; 8] = ["%xmm0", "%xmm1", "%xmm2", "%xmm3", "%xmm4", "%xmm5", "%xmm6", "%xmm7"];
+
+fn assign_arg_registers(types: &[DataType]) -> Vec<Option<Vec<&'static str>>> {
+    let mut next_int = 0;
+    let mut next_float = 0;
+
+    types
+        .iter()
+        .map(|dataType| {
+            let classes = match arg_classes(dataType) {
+                Some(classes) => classes,
+                None => return None,
+            };
+            let ints = classes.iter().filter(|class| **class == argClass::INTEGER).count();
+            if next_int + ints > ARG_REGISTERS.len() || next_float + classes.len() - ints > FLOAT_ARG_REGISTERS.len() {
+                return None;
+            }
+
+            let registers = classes
+                .iter()
+                .map(|class| {
+                    if *class == argClass::SSE {
+                        next_float += 1;
+                        FLOAT_ARG_REGISTERS[next_float - 1]
+                    } else {
+                        next_int += 1;
+                        ARG_REGISTERS[next_int - 1]
+                    }
+                })
+                .collect();
+            Some(registers)
+        })
+        .collect()
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO CLASSIFY A VALUE THE SYSTEM V WAY. AN EIGHTBYTE HOLDING
+  ONLY DOUBLES IS SSE, ANY OTHER IS INTEGER. A STRUCT OVER 16 BYTES IS
+  MEMORY: IT IS PASSED ON THE STACK AND RETURNED THROUGH A HIDDEN
+  POINTER THE CALLER PASSES IN %RDI.
+************************************************************************
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum argClass {
+    INTEGER,
+    SSE,
+}
+
+// Classes of the eightbytes of a value, None for a struct in memory
+fn arg_classes(dataType: &DataType) -> Option<Vec<argClass>> {
+    if !dataType.is_struct() {
+        return Some(vec![if dataType.is_float() { argClass::SSE } else { argClass::INTEGER }]);
+    }
+    if dataType.size() > 16 {
+        return None;
+    }
+
+    let mut scalars: Vec<(i64, DataType)> = vec![];
+    scalar_fields(dataType, 0, &mut scalars);
+
+    // Fields are aligned, so none of them straddles two eightbytes
+    let eightbytes = (dataType.size() + 7) / 8;
+    let classes = (0..eightbytes)
+        .map(|eightbyte| {
+            let all_double = scalars
+                .iter()
+                .filter(|(offset, _)| offset / 8 == eightbyte)
+                .all(|(_, scalar)| scalar.is_float());
+            if all_double {
+                argClass::SSE
+            } else {
+                argClass::INTEGER
+            }
+        })
+        .collect();
+    Some(classes)
+}
+
+// Every scalar inside a struct or array with its offset
+fn scalar_fields(dataType: &DataType, offset: i64, scalars: &mut Vec<(i64, DataType)>) {
+    match dataType {
+        DataType::STRUCT(layout) => {
+            for field in layout.fields.borrow().iter() {
+                scalar_fields(&field.dataType, offset + field.offset, scalars);
+            }
+        }
+        DataType::ARRAY(element, count) => {
+            for index in 0..*count {
+                scalar_fields(element, offset + index * element.size(), scalars);
+            }
+        }
+        scalar => scalars.push((offset, scalar.clone())),
+    }
+}
+
+// A struct's integer eightbytes come back in %rax and %rdx, its double
+// ones in %xmm0 and %xmm1
+fn return_registers(classes: &[argClass]) -> Vec<&'static str> {
+    let mut ints = ["%rax", "%rdx"].iter();
+    let mut floats = ["%xmm0", "%xmm1"].iter();
+
+    classes
+        .iter()
+        .filter_map(|class| if *class == argClass::SSE { floats.next() } else { ints.next() })
+        .copied()
+        .collect()
+}
+
+fn returns_in_memory(dataType: &DataType) -> bool {
+    arg_classes(dataType).is_none()
+}
+
+// Parameter types of a function declared in the file, none if it isn't
+fn param_types(glb: &globals, name: &str) -> Vec<DataType> {
+    let mut types: Vec<DataType> = vec![];
+    if let Some(func) = glb.functions.iter().find(|func| func.name == name) {
+        let mut params = func.arguments.as_deref();
+        while let Some(param_list) = params {
+            if let Some(param) = param_list.node.as_ref() {
+                types.push(param.dataType.clone());
+            }
+            params = param_list.next.as_deref();
+        }
+    }
+    types
+}
+
+fn return_type(glb: &globals, name: &str) -> Option<DataType> {
+    glb.functions.iter().find(|func| func.name == name).map(|func| func.dataType.clone())
+}
+
+// A name that is no variable in scope but a function is the function's address
+fn is_function_name(glb: &globals, name: &str) -> bool {
+    glb.functions.iter().any(|func| func.name == name)
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR THE SYMBOLS OF THE FILE. A STATIC SYMBOL IS LOCAL TO THE
+  OBJECT, A HIDDEN ONE TO THE SHARED LIBRARY OR EXECUTABLE IT IS LINKED
+  INTO. NO OTHER MODULE CAN OVERRIDE EITHER, SO EVEN POSITION INDEPENDENT
+  CODE REFERS TO THEM DIRECTLY.
+************************************************************************
+*/
+fn declarations<'g>(glb: &'g globals, name: &'g str) -> impl Iterator<Item = &'g RNode> + 'g {
+    glb.functions
+        .iter()
+        .chain(glb.variables.iter())
+        .map(|declaration| -> &RNode { declaration })
+        .filter(move |declaration| declaration.name == name)
+}
+
+fn is_definition(declaration: &RNode) -> bool {
+    match declaration.type_ {
+        NodeType::FUNCTIONDECL => true,
+        NodeType::VARDECL => declaration.storage != StorageClass::EXTERN,
+        _ => false,
+    }
+}
+
+// An attribute on any declaration decides, a symbol defined here without
+// one gets the visibility of the options
+fn symbol_visibility(glb: &globals, name: &str) -> Visibility {
+    if let Some(declaration) = declarations(glb, name).find(|declaration| declaration.visibility != Visibility::V_NONE) {
+        return declaration.visibility;
+    }
+    if declarations(glb, name).any(is_definition) {
+        glb.opts.visibility
+    } else {
+        Visibility::DEFAULT
+    }
+}
+
+fn is_static_symbol(glb: &globals, name: &str) -> bool {
+    declarations(glb, name).any(|declaration| declaration.storage == StorageClass::STATIC)
+}
+
+fn is_local_symbol(glb: &globals, name: &str) -> bool {
+    is_static_symbol(glb, name) || symbol_visibility(glb, name) == Visibility::HIDDEN
+}
+
+// Binding, visibility and type of a symbol defined in the file
+fn symbol_directives(glb: &globals, name: &str, kind: &str) -> String {
+    let mut code = String::new();
+    if !is_static_symbol(glb, name) {
+        code.push_str(&format!("\n.globl {}", name));
+        if symbol_visibility(glb, name) == Visibility::HIDDEN {
+            code.push_str(&format!("\n.hidden {}", name));
+        }
+    }
+    code.push_str(&format!("\n.type {}, {}", name, kind));
+    code
+}
+
+// A hidden symbol of another file is referred to directly, the linker is
+// told so it finds the definition in the same library or executable
+fn emit_undefined_visibility(fileptr: &mut dyn Write, glb: &globals) {
+    for declaration in glb.functions.iter().chain(glb.variables.iter()) {
+        if declaration.visibility != Visibility::HIDDEN || declarations(glb, &declaration.name).any(is_definition) {
+            continue;
+        }
+        fileptr
+            .write_all(format!("\n.hidden {}", declaration.name).as_bytes())
+            .expect("Failed to write symbol visibility");
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO REFER TO A FUNCTION. A FUNCTION OF ANOTHER MODULE MAY BE
+  LOADED ANYWHERE, ITS ADDRESS IS READ FROM THE GOT AND CALLS GO THROUGH
+  THE PLT. IN A SHARED LIBRARY THAT HOLDS FOR EVERY FUNCTION THAT ISN'T
+  STATIC OR HIDDEN, ANOTHER MODULE MAY OVERRIDE IT.
+************************************************************************
+*/
+fn is_defined_function(glb: &globals, name: &str) -> bool {
+    glb.functions.iter().any(|func| func.name == name && func.type_ == NodeType::FUNCTIONDECL)
+}
+
+fn call_target(glb: &globals, name: &str) -> String {
+    if glb.opts.pic && !is_local_symbol(glb, name) {
+        format!("{}@PLT", name)
+    } else {
+        name.to_string()
+    }
+}
+
+// An executable is usually position independent too, so even without
+// -fPIC the address of a function of another module comes from the GOT
+fn function_address(glb: &globals, name: &str, register: &str) -> String {
+    let is_direct = is_local_symbol(glb, name) || (!glb.opts.pic && is_defined_function(glb, name));
+    if !is_direct {
+        format!("\nmovq {}@GOTPCREL(%rip), {}", name, register)
+    } else {
+        format!("\nleaq {}(%rip), {}", name, register)
+    }
+}
 
+// Parameter types of the callee and whether it is variadic. A call through a
+// function pointer takes them from the pointer's type.
+fn call_signature(glb: &globals, call: &RNode) -> (Vec<DataType>, bool) {
+    let callee = call.left.as_ref().map_or("", |left| left.name.as_str());
+    if call.is_indirect {
+        let pointer = call.left.as_ref().and_then(|left| left.valueType.as_ref());
+        return match pointer.and_then(|pointer| pointer.element()) {
+            Some(DataType::FUNCTION(_, params, is_variadic)) => (params.clone(), *is_variadic),
+            _ => panic!("Error: {} is not a function pointer!", callee),
+        };
+    }
+    let is_variadic = glb.functions.iter().any(|func| func.name == callee && func.is_variadic);
+    (param_types(glb, callee), is_variadic)
 }
+
 /*
 ***********************************************************************
   THIS FUNCTION IS MEANT TO PUT THE FUNCTION ARGUMENTS ON STACK
@@ -539,11 +1043,11 @@ fn create_reg_list(reg_list: &mut regList) {
 */
 #[no_mangle]
 fn push_arg_on_stack(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     arguments: &RList,
     glb: &mut globals,
     var_list: &mut varStList,
-    reg_list: &mut regList,
+    _reg_list: &mut regList,
 ) {
     /*
      ****************************************
@@ -566,7 +1070,7 @@ fn push_arg_on_stack(
         if let Some(node) = args.node.as_ref() {
             let argument_value = node.value;
             //used to store the reg names and stack offset
-            let mut location = String::new();
+            let location;
 
             if argument_index < 6{
                 location = argument_registers[argument_index].to_string();
@@ -575,18 +1079,15 @@ fn push_arg_on_stack(
                     .write_all(format!("\nmovq ${}, {}",argument_value, location).as_bytes())
                     .expect("Failed to write argument to register");
             } else{
-                //push on  stack
-                location = format!("-{}(%rbp)", glb.last_used_offset);
+                //store in a stack slot of the frame
+                location = new_stack_slot(glb);
 
                 fileptr
-                    .write_all(format!("\npushq ${}", argument_value).as_bytes())
-                    .expect("Failed to push argument to stack");
-                    glb.last_used_offset -= 8;
-                    println!("\n[DEBUG] Offset is now(push_arg_): {}...", glb.last_used_offset);
-
+                    .write_all(format!("\nmovq ${}, {}", argument_value, location).as_bytes())
+                    .expect("Failed to write argument to stack");
             }
             // Store argument location in variable list
-            var_list.add_var_info(format!("arg{}", argument_index + 1), location, argument_value, false);
+            var_list.add_var_info(format!("arg{}", argument_index + 1), location, Some(argument_value), false);
 
             argument_index += 1;
 
@@ -609,16 +1110,34 @@ fn push_arg_on_stack(
 */
 #[no_mangle]
 fn pop_arg_from_stack(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     arguments: &RList,
     glb: &mut globals,
     var_list: &mut varStList,
-    reg_list: &mut regList,
+    _reg_list: &mut regList,
 ) {
     let mut args = arguments;
-    let argument_registers = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+    // Every parameter's register, doubles and integers are counted separately
+    // The address for a struct returned in memory comes first, in %rdi
+    let mut param_types: Vec<DataType> = vec![];
+    if !glb.return_address.is_empty() {
+        param_types.push(DataType::POINTER(Box::new(DataType::LONG)));
+    }
+    let mut param = Some(arguments);
+    while let Some(param_list) = param {
+        if let Some(node) = param_list.node.as_ref() {
+            param_types.push(node.dataType.clone());
+        }
+        param = param_list.next.as_deref();
+    }
+    let mut param_registers = assign_arg_registers(&param_types);
+    if !glb.return_address.is_empty() {
+        param_registers.remove(0);
+    }
 
     let mut argument_index = 0;
+    let mut stack_index = 0;
     /*
      ****************************************
               TODO : YOUR CODE HERE
@@ -635,19 +1154,57 @@ fn pop_arg_from_stack(
         ************************************************************************
         */
         if let Some(node) = args.node.as_ref() {
-            let mut location = String::new();
+            let location;
 
-            if argument_index <argument_registers.len(){
-                location = argument_registers[argument_index].to_string();
+            if let (Some(registers), true) = (param_registers[argument_index].as_ref(), node.dataType.is_struct()) {
+                // A struct's eightbytes are put back together in the frame
+                location = new_stack_array(glb, &node.dataType);
+                for (eightbyte, register) in registers.iter().enumerate() {
+                    fileptr
+                        .write_all(format!("\nmovq {}, {}", register, displaced(&location, 8 * eightbyte as i64)).as_bytes())
+                        .expect("Failed to save struct argument register");
+                }
+            } else if let Some(registers) = param_registers[argument_index].as_ref() {
+                // Copy the register into the frame so calls and divisions made
+                // by the body can't clobber the parameter
+                let register = registers[0];
+                location = new_stack_slot(glb);
+                if needs_conversion(&DataType::LONG, &node.dataType) {
+                    // Only the parameter's own width is passed, the rest of the register is undefined
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax", register).as_bytes())
+                        .expect("Failed to load argument register");
+                    emit_convert(fileptr, &node.dataType);
+                    fileptr
+                        .write_all(format!("\nmovq %rax, {}", location).as_bytes())
+                        .expect("Failed to save argument register");
+                } else {
+                    fileptr
+                        .write_all(format!("\nmovq {}, {}", register, location).as_bytes())
+                        .expect("Failed to save argument register");
+                }
             }else{
-                if glb.last_used_offset < 0{
-                    glb.last_used_offset += 8;
-                    println!("\n[DEBUG] Offset is now(pop_arg): {}...", glb.last_used_offset);
-                    location = format!("{}(%rbp)", glb.last_used_offset);
+                // The caller pushed the remaining arguments above the return
+                // address, and above the saved %rbp when there is a frame pointer
+                let mut offset = 8 + 8 * stack_index;
+                if glb.frame_base == "%rbp" {
+                    offset += 8;
+                }
+                stack_index += frame_bytes(&node.dataType) / 8;
+                location = format!("{}({})", offset, glb.frame_base);
+                debug!("\n[DEBUG] Stack argument {} is at {}...", argument_index + 1, location);
+
+                if needs_conversion(&DataType::LONG, &node.dataType) {
+                    fileptr
+                        .write_all(load_from(&node.dataType, &location).as_bytes())
+                        .expect("Failed to load stack argument");
+                    fileptr
+                        .write_all(format!("\nmovq %rax, {}", location).as_bytes())
+                        .expect("Failed to save stack argument");
                 }
             }
 
-            var_list.add_var_info(node.name.clone(), location.clone(), INVAL, false);
+            var_list.add_typed_var_info(node.name.clone(), location.clone(), node.dataType.clone());
 
             // Debug output to see what’s happening
             // fileptr
@@ -673,138 +1230,185 @@ fn pop_arg_from_stack(
 */
 #[no_mangle]
 fn process_constant(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     op_node: &RNode,
     glb: &mut globals,
     var_list: &mut varStList,
 ) {
-    long_to_char_offset(glb);
-
-    let mut value = format!("{}", op_node.value);
-    if value.len() > 10 {
-        value.truncate(10);
-    }
-
-    let mut offset = format!("{}", glb.last_used_offset);
-    if offset.len() > 100 {
-        offset.truncate(100);
-    }
-    offset.push_str("(%rbp)");
+    // Constants only live until the operation using them is emitted
+    let offset = new_temp_slot(glb);
 
-    var_list.add_var_info("".to_string(), offset.clone(), op_node.value, true);
+    var_list.add_var_info("".to_string(), offset.clone(), Some(op_node.value), true);
 
     fileptr
-        .write_all(format!("\nmovq ${}, {}", value, offset).as_bytes())
+        .write_all(load_constant(op_node.value, &offset).as_bytes())
         .expect("Unable to write data");
 }
 
 /*
 ***********************************************************************
- THIS FUNCTION IS MEANT TO PROCESS EACH CODE STATEMENT AND GENERATE
- ASSEMBLY FOR IT.
- TIP: YOU CAN MODULARIZE BETTER AND ADD NEW SMALLER FUNCTIONS IF YOU
+  FUNCTION TO MOVE A CONSTANT INTO A REGISTER OR A SLOT. AN IMMEDIATE
+  OPERAND IS SIGN EXTENDED FROM 32 BITS, WIDER CONSTANTS NEED MOVABSQ,
+  WHICH CAN ONLY WRITE A REGISTER.
+************************************************************************
+*/
+fn load_constant(value: i64, destination: &str) -> String {
+    if value == value as i32 as i64 {
+        format!("\nmovq ${}, {}", value, destination)
+    } else if destination.starts_with('%') {
+        format!("\nmovabsq ${}, {}", value, destination)
+    } else {
+        format!("\nmovabsq ${}, %r11\nmovq %r11, {}", value, destination)
+    }
+}
+
+/*
+***********************************************************************
+ THIS FUNCTION IS MEANT TO PROCESS EACH CODE STATEMENT AND GENERATE
+ ASSEMBLY FOR IT.
+ TIP: YOU CAN MODULARIZE BETTER AND ADD NEW SMALLER FUNCTIONS IF YOU
  WANT THAT CAN BE CALLED FROM HERE.
 ************************************************************************
 */
 #[no_mangle]
 fn process_statements(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     statements: &RList,
     glb: &mut globals,
     var_list: &mut varStList,
     reg_list: &mut regList,
 ) {
+    // The stack space is not allocated here: slots are handed out while
+    // lowering and the prologue is written once the frame size is known.
     let mut stmt = statements;
-    let mut local_vars = vec![];
-    let mut last_temp_location: Option<String> = None; // Track last temp location
-
-    println!("\n[DEBUG] Counting unique variables for stack allocation...");
+    debug!("[DEBUG] Processing statements...");
 
-    // **Step 1: Count unique variables**
     loop {
         if let Some(node) = stmt.node.as_ref() {
-            if node.stmtCode == StmtType::ASSIGN {
-                println!("\n[DEBUG] Okay, assignment {}...", node.name);
-                let variable_name = node.name.clone();
-                if !local_vars.contains(&variable_name) {
-                    println!("[DEBUG] Found variable for stack: {}", variable_name);
-                    local_vars.push(variable_name.clone());
-                } else {
-                    println!("\n[DEBUG] Variable already allocated...");
+            match node.stmtCode {
+                StmtType::ASSIGN if node.left.is_some() => {
+                    debug!("[DEBUG] Processing ASSIGN to an array element...");
+                    store_indirect(fileptr, node, glb, var_list, reg_list);
                 }
-            }
-        }
 
-        if let Some(next) = stmt.next.as_ref() {
-            stmt = next;
-        } else {
-            break;
-        }
-    }
+                StmtType::ASSIGN => {
+                    debug!("[DEBUG] Processing ASSIGN statement...");
 
-    // **Step 2: Allocate Stack Space for Local Variables**
-    let stack_size = local_vars.len() as i64 * 8; // Each variable takes 8 bytes
-    if stack_size > 0 {
-        println!("[DEBUG] Allocating {} bytes on the stack for local variables.", stack_size);
-        fileptr
-            .write_all(format!("\nsubq ${}, %rsp  # Allocate stack space", stack_size).as_bytes())
-            .expect("Failed to allocate stack space");
-    }
+                    if let Some(right) = node.right.as_ref() {
+                        debug!("[DEBUG] Processing right-hand side of ASSIGN...");
+                        load_into_rax(fileptr, right, glb, var_list, reg_list);
 
-    stmt = statements;
-    println!("[DEBUG] Processing statements...");
+                        // ✅ **The value takes the type of the variable**
+                        let dataType = var_list.lookup_var_type(&node.name).unwrap_or(DataType::LONG);
+                        let value_type = expression_type(right);
+                        emit_cast(fileptr, glb, &value_type, &dataType);
+                    }
 
-    loop {
-        if let Some(node) = stmt.node.as_ref() {
-            match node.stmtCode {
-                StmtType::ASSIGN => {
-                    println!("[DEBUG] Processing ASSIGN statement...");
+                    let variable_name = node.name.clone(); // The assigned variable
+                    debug!("[DEBUG] Storing variable '{}' into memory.", variable_name);
+
+                    // ✅ **Reassignments reuse the slot the variable already has**
+                    let mut stack_location = var_list.lookup_var_info(variable_name.clone(), None);
+                    if stack_location.is_empty() {
+                        stack_location = new_stack_slot(glb);
+                        debug!("[DEBUG] Allocated stack space for '{}': {}", variable_name, stack_location);
+
+                        // ✅ **Update variable storage**
+                        var_list.add_var_info(variable_name.clone(), stack_location.clone(), None, false);
+                    }
+
+                    // ✅ **Store result in memory, a struct is copied whole**
+                    let dataType = var_list.lookup_var_type(&variable_name).unwrap_or(DataType::LONG);
+                    let is_extern = is_extern_variable(glb, &variable_name, &stack_location);
+                    let stack_location = resolve_global(fileptr, stack_location);
+                    store_variable(fileptr, &stack_location, &dataType, is_extern);
+                }
 
+                StmtType::DECLARE if node.dataType.is_aggregate() => {
+                    // A struct's initial value is computed before it is in scope
                     if let Some(right) = node.right.as_ref() {
-                        println!("[DEBUG] Processing right-hand side of ASSIGN...");
-                        process_expression(fileptr, right, glb, var_list, reg_list);
+                        load_into_rax(fileptr, right, glb, var_list, reg_list);
                     }
 
-                    let variable_name = node.name.clone(); // The assigned variable
-                    println!("[DEBUG] Storing variable '{}' into memory.", variable_name);
+                    let location = new_stack_array(glb, &node.dataType);
+                    debug!("[DEBUG] Declared '{}' of {} bytes at {} in scope {}", node.name, node.dataType.size(), location, var_list.depth());
+                    var_list.add_typed_var_info(node.name.clone(), location.clone(), node.dataType.clone());
 
-                    if glb.last_used_offset == 0{
-                        glb.last_used_offset -= 8;
+                    if node.right.is_some() {
+                        store_variable(fileptr, &location, &node.dataType, false);
+                    }
+                }
+
+                StmtType::DECLARE => {
+                    debug!("[DEBUG] Processing DECLARE statement...");
+
+                    // The value is computed first, so `long x = x + 1;` reads the outer x
+                    if let Some(right) = node.right.as_ref() {
+                        load_into_rax(fileptr, right, glb, var_list, reg_list);
+                        let value_type = expression_type(right);
+                        emit_cast(fileptr, glb, &value_type, &node.dataType);
                     }
-                   // ✅ **Always allocate new stack space for the variable**
-                    let stack_location = format!("{}(%rbp)", glb.last_used_offset);
-                    println!("[DEBUG] Allocated stack space for '{}': {}", variable_name, stack_location);
-                    glb.last_used_offset -= 8;
-                    println!("[DEBUG]offset is now: {}", glb.last_used_offset);
 
-                    // ✅ **Update variable storage**
-                    var_list.add_var_info(variable_name.clone(), stack_location.clone(), INVAL, false);
+                    let stack_location = new_stack_slot(glb);
+                    debug!("[DEBUG] Declared '{}' at {} in scope {}", node.name, stack_location, var_list.depth());
+                    var_list.add_typed_var_info(node.name.clone(), stack_location.clone(), node.dataType.clone());
 
-                    // ✅ **Store result in memory**
                     fileptr
                         .write_all(format!("\nmovq %rax, {}", stack_location).as_bytes())
                         .expect("Failed to store variable in memory");
                 }
 
+                StmtType::BLOCK => {
+                    debug!("[DEBUG] Entering nested scope...");
+
+                    if let Some(body) = node.statements.as_ref() {
+                        var_list.push_scope();
+                        process_statements(fileptr, body, glb, var_list, reg_list);
+                        release_scope_slots(glb, var_list);
+                    }
+                }
+
                 StmtType::RETURN => {
-                    println!("[DEBUG] Processing RETURN statement...");
+                    debug!("[DEBUG] Processing RETURN statement...");
+
+                    let mut tail_called = false;
 
                     if let Some(val_left) = node.left.as_ref() {
-                        println!("[DEBUG] Processing return value {}...", val_left.name);
+                        debug!("[DEBUG] Processing return value {}...", val_left.name);
                 
-                        if val_left.exprCode == ExprType::CONSTANT {
+                        if is_tail_call(val_left, glb) {
+                            debug!("[DEBUG] Returned call is in tail position, lowering to a jump...");
+                            emit_tail_call(fileptr, val_left, glb, var_list, reg_list);
+                            tail_called = true;
+                        } else if val_left.exprCode == ExprType::CONSTANT {
                             // ✅ Directly load constant into %rax
                             fileptr
-                                .write_all(format!("\nmovq ${}, %rax", val_left.value).as_bytes())
+                                .write_all(load_constant(val_left.value, "%rax").as_bytes())
                                 .expect("Failed to write return constant");
                         } else {
                             // Process expressions or variables normally
                             process_expression(fileptr, val_left, glb, var_list, reg_list);
                         }
+
+                        // ✅ The value takes the return type, a double is returned in %xmm0
+                        let own_type = return_type(glb, &glb.func_name).unwrap_or(DataType::LONG);
+                        if !tail_called && own_type.is_struct() {
+                            emit_struct_return(fileptr, glb, &own_type);
+                        } else if !tail_called {
+                            let value_type = expression_type(val_left);
+                            emit_cast(fileptr, glb, &value_type, &own_type);
+                            if own_type.is_float() {
+                                fileptr
+                                    .write_all("\nmovq %rax, %xmm0".as_bytes())
+                                    .expect("Failed to write floating point result");
+                            }
+                        }
                     }
                 
-                    println!("[DEBUG] Return statement processed. Generating return sequence...");
-                    ret_asm(fileptr,glb);
+                    if !tail_called {
+                        debug!("[DEBUG] Return statement processed. Generating return sequence...");
+                        ret_asm(fileptr,glb);
+                    }
 
                     // // Store return value in %rax
                     // fileptr
@@ -815,10 +1419,18 @@ fn process_statements(
                     // ret_asm(fileptr, glb);
                 }
 
+                StmtType::ASM => {
+                    debug!("[DEBUG] Processing ASM statement...");
+                    process_asm(fileptr, node, glb, var_list, reg_list);
+                }
+
                 StmtType::S_NONE => {
-                    println!("[DEBUG] Encountered an empty statement (S_NONE). Skipping.");
+                    debug!("[DEBUG] Encountered an empty statement (S_NONE). Skipping.");
                 }
             }
+
+            // Nothing computed by a statement outlives it
+            release_temp_slots(glb, 0, var_list);
         } else {
             break;
         }
@@ -830,14 +1442,14 @@ fn process_statements(
         }
     }
 
-    println!("[DEBUG] Finished processing statements.");
+    debug!("[DEBUG] Finished processing statements.");
 }
 
 
 
 #[no_mangle]
 fn process_expression(
-    fileptr: &mut File,
+    fileptr: &mut dyn Write,
     expression_node: &RNode,
     glb: &mut globals,
     var_list: &mut varStList,
@@ -845,137 +1457,323 @@ fn process_expression(
 ) {
     match expression_node.exprCode {
         ExprType::VARIABLE => {
-            println!("\n[DEBUG] Processing expr_VARIABLE: {}", expression_node.name);
+            debug!("\n[DEBUG] Processing expr_VARIABLE: {}", expression_node.name);
             
-            let variable_location = var_list.lookup_var_info(expression_node.name.clone(), INVAL);
+            let variable_location = var_list.lookup_var_info(expression_node.name.clone(), None);
+            if variable_location.is_empty() && is_function_name(glb, &expression_node.name) {
+                fileptr
+                    .write_all(function_address(glb, &expression_node.name, "%rax").as_bytes())
+                    .expect("Failed to load function address");
+                return;
+            }
             if variable_location.is_empty() {
                 panic!("Error: Variable {} not found!", expression_node.name);
             }
-
+            let variable_location = resolve_global(fileptr, variable_location);
+
+            // An array used as a value is the address of its first element, a
+            // struct its own address, and a narrow variable in memory may have
+            // been stored through a pointer
+            let dataType = var_list.lookup_var_type(&expression_node.name).unwrap_or(DataType::LONG);
+            let code = if dataType.is_aggregate() {
+                format!("\nleaq {}, %rax", variable_location)
+            } else if variable_location.contains('(') {
+                load_from(&dataType, &variable_location)
+            } else {
+                format!("\nmovq {}, %rax", variable_location)
+            };
             fileptr
-                .write_all(format!("\nmovq {}, %rax", variable_location).as_bytes())
+                .write_all(code.as_bytes())
                 .expect("Failed to load variable");
         }
 
         ExprType::CONSTANT => {
-            println!("\n[DEBUG] Processing expr_CONSTANT: {}", expression_node.value);
-            process_constant(fileptr, expression_node, glb, var_list);
+            debug!("\n[DEBUG] Processing expr_CONSTANT: {}", expression_node.value);
+            fileptr
+                .write_all(load_constant(expression_node.value, "%rax").as_bytes())
+                .expect("Failed to load constant");
+        }
+
+        ExprType::FCONSTANT => {
+            debug!("\n[DEBUG] Processing expr_FCONSTANT: {:?}", expression_node.fvalue);
+            fileptr
+                .write_all(format!("\nmovq {}, %rax", glb.literals.double_operand(expression_node.fvalue)).as_bytes())
+                .expect("Failed to load floating point constant");
+        }
+
+        ExprType::STRING => {
+            debug!("\n[DEBUG] Processing expr_STRING: {:?}", expression_node.name);
+            fileptr
+                .write_all(format!("\nleaq {}(%rip), %rax", glb.literals.string_label(&expression_node.name)).as_bytes())
+                .expect("Failed to load string address");
         }
 
         ExprType::OPERATION => {
-            println!("\n[DEBUG] Processing OPERATION: {:?}", expression_node.opCode);
+            debug!("\n[DEBUG] Processing OPERATION: {:?}", expression_node.opCode);
 
+            // Operands that don't live in a variable are computed into temporaries,
+            // which are released again once the operation has been emitted
+            let temp_mark = glb.temp_slots.len();
             let mut left_location = String::new();
             let mut right_location = String::new();
-
-            if let Some(left_expr) = expression_node.left.as_ref() {
-                left_location = var_list.lookup_var_info(left_expr.name.clone(), INVAL);
-                if left_location.is_empty() {
-                    process_expression(fileptr, left_expr, glb, var_list, reg_list);
-                    left_location = glb.last_offset_used.clone();
+            let result_type = expression_type(expression_node);
+
+            // The callee, the indexed array, the operand of & and the struct a
+            // field is selected from are not values
+            let left_is_value = !matches!(
+                expression_node.opCode,
+                OpType::FUNCTIONCALL | OpType::INDEX | OpType::ADDRESS | OpType::FIELD
+            );
+
+            if expression_node.opCode != OpType::FUNCTIONCALL {
+                if let Some(left_expr) = expression_node.left.as_ref().filter(|_| left_is_value) {
+                    left_location = process_operand(fileptr, left_expr, glb, var_list, reg_list);
                 }
-            }
 
-            if let Some(right_expr) = expression_node.right.as_ref() {
-                right_location = var_list.lookup_var_info(right_expr.name.clone(), INVAL);
-                if right_location.is_empty() {
-                    process_expression(fileptr, right_expr, glb, var_list, reg_list);
-                    right_location = glb.last_offset_used.clone();
+                if let Some(right_expr) = expression_node.right.as_ref() {
+                    right_location = process_operand(fileptr, right_expr, glb, var_list, reg_list);
                 }
             }
             match expression_node.opCode {
                 // ✅ Handle Function Calls
                 OpType::FUNCTIONCALL => {  
+                    let mut callee_name = String::new();
                     if let Some(left) = expression_node.left.as_ref() {
-                        println!("\n[DEBUG] Processing function call: {}", left.name);
+                        debug!("\n[DEBUG] Processing function call: {}", left.name);
+                        callee_name = left.name.clone();
+                    }
+
+                    // Small leaf callees are expanded in place instead of called
+                    if let Some(callee) = find_inline_candidate(glb, &callee_name, expression_node) {
+                        inline_call(fileptr, callee, expression_node, glb, var_list, reg_list);
+                        emit_convert(fileptr, &result_type);
+                        return;
+                    }
+
+                    // ✅ A function pointer is copied aside first, the variable holding
+                    // it may live in a register the arguments are loaded into
+                    let mut callee_location = String::new();
+                    if expression_node.is_indirect {
+                        if let Some(left) = expression_node.left.as_ref() {
+                            load_into_rax(fileptr, left, glb, var_list, reg_list);
+                        }
+                        callee_location = new_temp_slot(glb);
+                        fileptr
+                            .write_all(format!("\nmovq %rax, {}", callee_location).as_bytes())
+                            .expect("Failed to save function pointer");
                     }
-                    
-                    
-                    let mut arg_count = 0;
+
+                    // Evaluate every argument before loading the argument registers,
+                    // a later argument may itself contain a call that clobbers them.
+                    // An argument is passed as the parameter's type, past the
+                    // parameters of a variadic callee as its own type.
+                    let (param_types, is_variadic) = call_signature(glb, expression_node);
+                    let mut arg_locations: Vec<String> = vec![];
+                    let mut arg_types: Vec<DataType> = vec![];
                     let mut args = expression_node.arguments.as_ref();  
-                    let arg_registers = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
             
+                    // ✅ A struct returned in memory is written where the hidden first argument points
+                    let mut result_space = String::new();
+                    if result_type.is_struct() {
+                        result_space = new_stack_array(glb, &result_type);
+                    }
+                    if returns_in_memory(&result_type) {
+                        let location = new_temp_slot(glb);
+                        fileptr
+                            .write_all(format!("\nleaq {}, %rax\nmovq %rax, {}", result_space, location).as_bytes())
+                            .expect("Failed to pass the address of the result");
+                        arg_locations.push(location);
+                        arg_types.push(DataType::POINTER(Box::new(result_type.clone())));
+                    }
+                    let first_arg = arg_locations.len();
+
                     while let Some(arg_list) = args {  
                         if let Some(arg) = arg_list.node.as_ref() {  
-                            let mut arg_location = var_list.lookup_var_info(arg.name.clone(), INVAL);
-                            if arg_location.is_empty() {
-                                process_expression(fileptr, arg, glb, var_list, reg_list);
-                                arg_location = glb.last_offset_used.clone();
-                            }
+                            let location = process_operand(fileptr, arg, glb, var_list, reg_list);
+                            let arg_type = expression_type(arg);
+                            let passed_type = param_types.get(arg_locations.len() - first_arg).cloned().unwrap_or(arg_type.clone());
+                            arg_locations.push(converted_operand(fileptr, glb, &location, &arg_type, &passed_type));
+                            arg_types.push(passed_type);
+                        }
             
-                            if arg_count < 6 {
-                                if arg_location != arg_registers[arg_count]{
-                                    fileptr
-                                    .write_all(format!("\nmovq {}, {}", arg_location, arg_registers[arg_count]).as_bytes())
-                                    .expect("Failed to pass function argument in register");
+                        args = arg_list.next.as_ref();
+                    }
+                    let arg_registers = assign_arg_registers(&arg_types);
+
+                    // Arguments without a register are pushed right to left, padded so
+                    // that %rsp is still 16 byte aligned at the call. A struct's
+                    // location holds its address, its eightbytes are pushed last first.
+                    let stack_args: Vec<(&String, &DataType)> = arg_locations
+                        .iter()
+                        .zip(arg_types.iter())
+                        .zip(arg_registers.iter())
+                        .filter(|(_, registers)| registers.is_none())
+                        .map(|(arg, _)| arg)
+                        .collect();
+                    let mut stack_cleanup = 0;
+                    if !stack_args.is_empty() {
+                        stack_cleanup = stack_args.iter().map(|(_, dataType)| frame_bytes(dataType) as usize).sum();
+                        if stack_cleanup % 16 != 0 {
+                            fileptr
+                                .write_all("\nsubq $8, %rsp  # Align stack for call".as_bytes())
+                                .expect("Failed to align stack for function call");
+                            stack_cleanup += 8;
+                        }
+                        for (arg_location, dataType) in stack_args.iter().rev() {
+                            let code = if dataType.is_struct() {
+                                let mut code = format!("\nmovq {}, %r11", arg_location);
+                                for eightbyte in (0..frame_bytes(dataType) / 8).rev() {
+                                    code.push_str(&format!("\npushq {}(%r11)", 8 * eightbyte));
                                 }
-                                
+                                code
                             } else {
-                                fileptr
-                                    .write_all(format!("\npushq {}", arg_location).as_bytes())
-                                    .expect("Failed to push function argument to stack");
-                            }
-            
-                            arg_count += 1;
+                                format!("\npushq {}", arg_location)
+                            };
+                            fileptr
+                                .write_all(code.as_bytes())
+                                .expect("Failed to push function argument to stack");
                         }
-            
-                        args = arg_list.next.as_ref();
                     }
-                    if let Some(left) = expression_node.left.as_ref() {
+
+                    for ((arg_location, dataType), registers) in arg_locations.iter().zip(arg_types.iter()).zip(arg_registers.iter()) {
+                        let code = match registers {
+                            Some(registers) if !dataType.is_struct() => format!("\nmovq {}, {}", arg_location, registers[0]),
+                            Some(registers) => {
+                                let mut code = format!("\nmovq {}, %r11", arg_location);
+                                for (eightbyte, register) in registers.iter().enumerate() {
+                                    code.push_str(&format!("\nmovq {}(%r11), {}", 8 * eightbyte, register));
+                                }
+                                code
+                            }
+                            None => continue,
+                        };
                         fileptr
-                            .write_all(format!("\ncall {}", left.name).as_bytes())
-                            .expect("Failed to generate function call");
-                
-                        if arg_count > 6 {
-                            let stack_cleanup = (arg_count - 6) * 8;
+                            .write_all(code.as_bytes())
+                            .expect("Failed to pass function argument in register");
+                    }
+
+                    let float_count = arg_registers.iter().flatten().flatten().filter(|register| register.starts_with("%xmm")).count();
+                    emit_vector_count(fileptr, is_variadic, float_count);
+                    let code = if expression_node.is_indirect {
+                        format!("\nmovq {}, %r11\ncall *%r11", callee_location)
+                    } else {
+                        format!("\ncall {}", call_target(glb, &callee_name))
+                    };
+                    fileptr
+                        .write_all(code.as_bytes())
+                        .expect("Failed to generate function call");
+
+                    if stack_cleanup > 0 {
+                        fileptr
+                            .write_all(format!("\naddq ${}, %rsp  # Restore stack", stack_cleanup).as_bytes())
+                            .expect("Failed to restore stack after function call");
+                    }
+
+                    // A double comes back in %xmm0, a small struct in the registers
+                    // of its eightbytes and is stored so its address can be the value
+                    if let Some(classes) = arg_classes(&result_type).filter(|_| result_type.is_struct()) {
+                        for (eightbyte, register) in return_registers(&classes).iter().enumerate() {
                             fileptr
-                                .write_all(format!("\naddq ${}, %rsp  # Restore stack", stack_cleanup).as_bytes())
-                                .expect("Failed to restore stack after function call");
+                                .write_all(format!("\nmovq {}, {}", register, displaced(&result_space, 8 * eightbyte as i64)).as_bytes())
+                                .expect("Failed to store returned struct");
                         }
+                        fileptr
+                            .write_all(format!("\nleaq {}, %rax", result_space).as_bytes())
+                            .expect("Failed to take returned struct address");
+                    } else if result_type.is_float() {
+                        fileptr
+                            .write_all("\nmovq %xmm0, %rax".as_bytes())
+                            .expect("Failed to read floating point result");
                     }
-            
-                    long_to_char_offset(glb);
-                    // fileptr
-                    //     .write_all(format!("\nmovq %rax, {}", glb.last_offset_used).as_bytes())
-                    //     .expect("Failed to store function return value");
-            
-                    // var_list.update_var_info(expression_node.name.clone(), glb.last_offset_used.clone(), INVAL, false);
+                }
+
+                // ✅ Double arithmetic, both operands are converted and computed in SSE registers
+                OpType::MULTIPLY | OpType::DIVIDE | OpType::ADD | OpType::SUBTRACT if result_type.is_float() => {
+                    let [left_type, right_type] = operand_types(expression_node);
+                    let left_location = converted_operand(fileptr, glb, &left_location, &left_type, &result_type);
+                    let right_location = converted_operand(fileptr, glb, &right_location, &right_type, &result_type);
+
+                    let operation_instr = match expression_node.opCode {
+                        OpType::ADD => "addsd",
+                        OpType::SUBTRACT => "subsd",
+                        OpType::MULTIPLY => "mulsd",
+                        _ => "divsd",
+                    };
+                    fileptr
+                        .write_all(
+                            format!(
+                                "\nmovq {}, %xmm0\nmovq {}, %xmm1\n{} %xmm1, %xmm0\nmovq %xmm0, %rax",
+                                left_location, right_location, operation_instr
+                            )
+                            .as_bytes(),
+                        )
+                        .expect("Failed to generate floating point operation");
+                }
+
+                // ✅ Bitwise operators only take integers
+                OpType::MOD | OpType::BOR | OpType::BAND | OpType::BXOR | OpType::BSHL | OpType::BSHR | OpType::BNOT
+                    if operand_types(expression_node).iter().any(|dataType| dataType.is_float()) =>
+                {
+                    panic!("Error: Operator {:?} needs integer operands!", expression_node.opCode);
+                }
+
+                // ✅ Pointer arithmetic, the integer side counts elements
+                OpType::ADD | OpType::SUBTRACT if operand_types(expression_node).iter().any(|dataType| dataType.is_pointer()) => {
+                    let [left_type, right_type] = operand_types(expression_node);
+                    emit_pointer_arithmetic(
+                        fileptr,
+                        expression_node.opCode,
+                        (&left_location, &left_type),
+                        (&right_location, &right_type),
+                    );
                 }
 
                 // ✅ Handle Arithmetic Operations (Multiplication, Division, Addition, Subtraction)
-                OpType::MULTIPLY | OpType::DIVIDE | OpType::ADD | OpType::SUBTRACT => {  
-                    let mut left_location = String::new();
-                    let mut right_location = String::new();
-            
-                    if let Some(left_expr) = expression_node.left.as_ref() {
-                        left_location = var_list.lookup_var_info(left_expr.name.clone(), INVAL);
-                        if left_location.is_empty() {
-                            process_expression(fileptr, left_expr, glb, var_list, reg_list);
-                            left_location = glb.last_offset_used.clone();
-                        }
-                    }
-            
-                    if let Some(right_expr) = expression_node.right.as_ref() {
-                        right_location = var_list.lookup_var_info(right_expr.name.clone(), INVAL);
-                        if right_location.is_empty() {
-                            process_expression(fileptr, right_expr, glb, var_list, reg_list);
-                            right_location = glb.last_offset_used.clone();
-                        }
-                    }
-            
-                    if expression_node.opCode == OpType::DIVIDE {
+                OpType::MULTIPLY | OpType::DIVIDE | OpType::MOD | OpType::ADD | OpType::SUBTRACT => {  
+                    if expression_node.opCode == OpType::DIVIDE || expression_node.opCode == OpType::MOD {
+                        // ✅ Both operands are converted first, -1 is a large unsigned int
+                        let [left_type, right_type] = operand_types(expression_node);
+                        let left_location = converted_operand(fileptr, glb, &left_location, &left_type, &result_type);
+                        let right_location = converted_operand(fileptr, glb, &right_location, &right_type, &result_type);
+
                         // ✅ Corrected `idivq` handling to use `%rax` as the dividend and a single operand
                         fileptr
                             .write_all(format!("\nmovq {}, %rax", left_location).as_bytes())  // ✅ Load numerator into %rax
                             .expect("Failed to load numerator");
 
-                        fileptr
-                            .write_all("\ncqto".as_bytes())  // ✅ Sign-extend RAX into RDX:RAX
-                            .expect("Failed to sign-extend for division");
+                        if glb.opts.checked_arith {
+                            emit_division_check(fileptr, glb, expression_node, &right_location, &result_type);
+                        }
 
-                        fileptr
-                            .write_all(format!("\nidivq {}", right_location).as_bytes())  // ✅ Correct: idivq only takes one operand
-                            .expect("Failed to generate div operation");
+                        if result_type.is_unsigned() {
+                            fileptr
+                                .write_all("\nxorl %edx, %edx".as_bytes())  // ✅ Zero-extend RAX into RDX:RAX
+                                .expect("Failed to zero-extend for division");
+
+                            fileptr
+                                .write_all(format!("\ndivq {}", right_location).as_bytes())
+                                .expect("Failed to generate div operation");
+                        } else {
+                            fileptr
+                                .write_all("\ncqto".as_bytes())  // ✅ Sign-extend RAX into RDX:RAX
+                                .expect("Failed to sign-extend for division");
+
+                            fileptr
+                                .write_all(format!("\nidivq {}", right_location).as_bytes())  // ✅ Correct: idivq only takes one operand
+                                .expect("Failed to generate div operation");
+                        }
+
+                        // ✅ The remainder is left in %rdx
+                        if expression_node.opCode == OpType::MOD {
+                            fileptr
+                                .write_all("\nmovq %rdx, %rax".as_bytes())
+                                .expect("Failed to read remainder");
+                        }
+
+                        // A long quotient can't overflow once INT64_MIN / -1 is ruled out
+                        if glb.opts.checked_arith && result_type != DataType::LONG {
+                            emit_overflow_check(fileptr, glb, expression_node, &result_type);
+                        }
                     } else {
                         // ✅ Handle all other arithmetic operations
                         let operation_instr = match expression_node.opCode {
@@ -992,6 +1790,10 @@ fn process_expression(
                         fileptr
                             .write_all(format!("\n{} {}, %rax", operation_instr, right_location).as_bytes())
                             .expect("Failed to generate arithmetic operation");
+
+                        if glb.opts.checked_arith {
+                            emit_overflow_check(fileptr, glb, expression_node, &result_type);
+                        }
                     }
                 }
                 OpType::BOR => {
@@ -1021,6 +1823,9 @@ fn process_expression(
 
                 // ✅ **Bit Shifting**
                 OpType::BSHL => {
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax", left_location).as_bytes())
+                        .expect("Failed to load left operand");
                     fileptr
                         .write_all(format!("\nmovq {}, %rcx", right_location).as_bytes())
                         .expect("Failed to load shift amount");
@@ -1029,110 +1834,2421 @@ fn process_expression(
                         .expect("Failed to generate Left Shift");
                 }
                 OpType::BSHR => {
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax", left_location).as_bytes())
+                        .expect("Failed to load left operand");
                     fileptr
                         .write_all(format!("\nmovq {}, %rcx", right_location).as_bytes())
                         .expect("Failed to load shift amount");
+                    // ✅ Unsigned values shift in zeros, signed ones copies of the sign bit
+                    let instr = if result_type.is_unsigned() { "shrq" } else { "sarq" };
                     fileptr
-                        .write_all("\nsarq %cl, %rax".as_bytes())
+                        .write_all(format!("\n{} %cl, %rax", instr).as_bytes())
                         .expect("Failed to generate Right Shift");
                 }
 
+                // ✅ **Array element read**
+                OpType::INDEX => {
+                    let array = match expression_node.left.as_ref() {
+                        Some(array) => array,
+                        None => panic!("Error: index without an array!"),
+                    };
+
+                    let operand = element_operand(fileptr, glb, var_list, reg_list, array, &right_location);
+                    let element_type = expression_type(expression_node);
+                    let code = if element_type.is_aggregate() {
+                        format!("\nleaq {}, %rax", operand)
+                    } else {
+                        load_from(&element_type, &operand)
+                    };
+                    fileptr
+                        .write_all(code.as_bytes())
+                        .expect("Failed to load array element");
+                }
+
+                // ✅ **Field of a struct, read at its offset**
+                OpType::FIELD => {
+                    // An array field is used as a pointer to its first element
+                    let field_type = operand_types(expression_node)[0]
+                        .field(&expression_node.name)
+                        .map_or(DataType::LONG, |field| field.dataType);
+                    let operand = field_operand(fileptr, expression_node, glb, var_list, reg_list);
+                    let code = if field_type.is_aggregate() {
+                        format!("\nleaq {}, %rax", operand)
+                    } else {
+                        load_from(&result_type, &operand)
+                    };
+                    fileptr
+                        .write_all(code.as_bytes())
+                        .expect("Failed to load struct field");
+                }
+
+                // ✅ **Address of a variable or an element**
+                OpType::ADDRESS => {
+                    if let Some(operand) = expression_node.left.as_ref() {
+                        emit_address(fileptr, operand, glb, var_list, reg_list);
+                    }
+                }
+
+                // ✅ **Load through a pointer**
+                OpType::DEREF => {
+                    // A struct pointed to is used through its address, which is the pointer
+                    let target_type = expression_type(expression_node);
+                    let code = if target_type.is_aggregate() {
+                        format!("\nmovq {}, %rax", left_location)
+                    } else {
+                        format!("\nmovq {}, %r11{}", left_location, load_from(&target_type, "(%r11)"))
+                    };
+                    fileptr
+                        .write_all(code.as_bytes())
+                        .expect("Failed to load through pointer");
+                }
+
+                // ✅ **Negating a double flips its sign bit**
+                OpType::NEGATE if result_type.is_float() => {
+                    let operand_location = if left_location.is_empty() { &right_location } else { &left_location };
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax\nbtcq $63, %rax", operand_location).as_bytes())
+                        .expect("Failed to generate floating point negation");
+                }
+
                 // ✅ **Unary Negation**
                 OpType::NEGATE => {
+                    // The single operand may hang off either side
+                    let operand_location = if left_location.is_empty() { &right_location } else { &left_location };
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax", operand_location).as_bytes())
+                        .expect("Failed to load operand");
                     fileptr
                         .write_all("\nnegq %rax".as_bytes())
                         .expect("Failed to generate NEG");
+
+                    if glb.opts.checked_arith {
+                        emit_overflow_check(fileptr, glb, expression_node, &result_type);
+                    }
+                }
+
+                // ✅ **Bitwise NOT**
+                OpType::BNOT => {
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax\nnotq %rax", left_location).as_bytes())
+                        .expect("Failed to generate NOT");
+                }
+
+                // ✅ **Logical NOT, 1 for zero and 0 for anything else**
+                OpType::LNOT => {
+                    // Shifting out the sign bit makes -0.0 zero too, a NaN stays non-zero
+                    let [operand_type, _] = operand_types(expression_node);
+                    let test = if operand_type.is_float() { "shlq $1, %rax" } else { "testq %rax, %rax" };
+                    fileptr
+                        .write_all(format!("\nmovq {}, %rax\n{}\nsete %al\nmovzbl %al, %eax", left_location, test).as_bytes())
+                        .expect("Failed to generate logical NOT");
                 }
                 
             
                 _ => {  
-                    println!("[WARNING] Unhandled operation type: {:?}", expression_node.opCode);
+                    eprintln!("[WARNING] Unhandled operation type: {:?}", expression_node.opCode);
                 }
             }
+
+            // Results narrower than a long wrap at their width, loads already
+            // extend the value they read
+            if !matches!(expression_node.opCode, OpType::INDEX | OpType::ADDRESS | OpType::DEREF | OpType::FIELD) {
+                emit_convert(fileptr, &result_type);
+            }
+
+            release_temp_slots(glb, temp_mark, var_list);
         }
 
         _ => {
-            println!("[WARNING] Unsupported expression type: {:?}", expression_node.exprCode);
+            eprintln!("[WARNING] Unsupported expression type: {:?}", expression_node.exprCode);
         }
     }
 }
 
 
-        
 /*
- ***********************************************************************
-  THIS FUNCTION IS MEANT TO DO CODEGEN FOR ALL THE FUNCTIONS IN THE FILE
- ************************************************************************
+***********************************************************************
+  FUNCTION TO LOWER THE PARAMETERS AND BODY OF A FUNCTION. THE CODE IS
+  RETURNED RATHER THAN WRITTEN SO THE PROLOGUE CAN BE SIZED FROM IT.
+************************************************************************
 */
-#[no_mangle]
-pub fn Codegen(mut worklist: &RList) {
-    /*
-     ****************************************
-              TODO : YOUR CODE HERE
-     ****************************************
-    */
+fn process_function(node: &RNode, glb: &mut globals, reg_list: &mut regList) -> Vec<u8> {
+    let mut body: Vec<u8> = vec![];
 
-    //creates output assembly file
-    let mut fileptr = File::create("assembly.s").expect("Unable to create assembly file");
+    // Initialize variable storage list, globals are in the outermost scope
+    let mut var_list = varStList::new();
+    declare_global_vars(glb, &mut var_list);
 
-    // // Initialize register list
-    let mut reg_list = regList::new();
-    // create_reg_list(&mut reg_list);
+    glb.address_taken.clear();
+    if let Some(statements) = node.statements.as_ref() {
+        collect_address_taken(statements, &mut glb.address_taken);
+    }
 
-    // // Print register list to check if it's working
-    // println!("Register List After Initialization:");
-    // reg_list.print_list();
-    
-    // Loop through each function in the program
-    loop {
-        if let Some(node) = worklist.node.as_ref() {
-            if node.type_ == NodeType::FUNCTIONDECL {
-                // Get function name
-                let func_name = node.name.clone();
-                println!("Generating assembly for function: {}", func_name);
+    // The prologue saves the callee saved registers an asm changes
+    let mut saved: Vec<String> = vec![];
+    if let Some(statements) = node.statements.as_ref() {
+        collect_asm_saved_registers(statements, &mut saved);
+    }
+    for register in saved {
+        let slot = new_stack_slot(glb);
+        glb.saved_registers.push((register, slot));
+    }
 
-                // Initialize function prologue
-                init_asm(&mut fileptr, func_name.clone());
+    // Self tail calls jump here, reusing the frame set up by the prologue
+    body.write_all(format!("\n.L{}_body:", glb.func_name).as_bytes())
+        .expect("Unable to write data");
 
-                // Initialize global variables
-                let mut glb = globals::new();
+    // The address a large struct is returned to comes in before the parameters
+    if returns_in_memory(&node.dataType) {
+        glb.return_address = new_stack_slot(glb);
+        body.write_all(format!("\nmovq %rdi, {}", glb.return_address).as_bytes())
+            .expect("Failed to save the address of the result");
+    }
+
+    // Process function parameters (if any)
+    if let Some(arguments) = node.arguments.as_ref() {
+        pop_arg_from_stack(&mut body, arguments, glb, &mut var_list, reg_list);
+    }
 
-                // Initialize variable storage list
-                let mut var_list = varStList::new();
+    // Process function body statements
+    if let Some(statements) = node.statements.as_ref() {
+        process_statements(&mut body, statements, glb, &mut var_list, reg_list);
+    }
 
-                // Process function parameters (if any)
-                if let Some(arguments) = node.arguments.as_ref() {
-                    pop_arg_from_stack(&mut fileptr, arguments, &mut glb, &mut var_list, &mut reg_list);
-                }
+    // Every function ends with a return, so nothing falls into the trap code
+    emit_trap_stub(&mut body, glb);
 
-                // Process function body statements
-                if let Some(statements) = node.statements.as_ref() {
-                    process_statements(&mut fileptr, statements, &mut glb, &mut var_list, &mut reg_list);
-                }
+    body
+}
 
-                // Add function epilogue (return)
-                // ret_asm(&mut fileptr);
-            }
-        } else {
-            break;
+/*
+*************************************************************************************
+ THE PASS MANAGER RUNS NAMED PASSES OVER ONE FUNCTION AT A TIME. THE FUNCTION'S
+ CODE IS KEPT IN A BUFFER SO LATER PASSES CAN REWRITE WHAT EARLIER ONES EMITTED.
+*************************************************************************************
+*/
+struct funcUnit<'a> {
+    node: &'a RNode,
+    glb: globals<'a>,
+    reg_list: regList,
+    asm: Vec<u8>,
+    // Copy of the code as lowered, kept when the options ask for an IR dump
+    ir: Vec<u8>,
+}
+
+struct codegenPass {
+    name: &'static str,
+    run: fn(&mut funcUnit),
+}
+
+const CODEGEN_PASSES: [codegenPass; 4] = [
+    codegenPass { name: "leaf-frame", run: leaf_frame_pass },
+    codegenPass { name: "lower", run: lower_pass },
+    codegenPass { name: "peephole", run: peephole_pass },
+    codegenPass { name: "prologue", run: prologue_pass },
+];
+
+/*
+***********************************************************************
+  FUNCTION TO RUN THE PASSES LISTED IN THE OPTIONS OVER A FUNCTION
+************************************************************************
+*/
+fn run_passes(unit: &mut funcUnit, opts: &CodegenOptions) {
+    for pass_name in opts.passes.iter() {
+        let pass = match CODEGEN_PASSES.iter().find(|pass| pass.name == pass_name.as_str()) {
+            Some(pass) => pass,
+            None => panic!("Error: Unknown codegen pass {}!", pass_name),
+        };
+
+        debug!("[DEBUG] Running pass {} on {}", pass.name, unit.glb.func_name);
+        let start = Instant::now();
+        (pass.run)(unit);
+        let elapsed = start.elapsed();
+
+        if opts.time_passes {
+            eprintln!("[TIME] {:<12} {:<20} {:>10.3} ms", pass.name, unit.glb.func_name, elapsed.as_secs_f64() * 1000.0);
+        }
+        if opts.print_after.iter().any(|name| name == pass.name) {
+            eprintln!("# *** {} after {} ***", unit.glb.func_name, pass.name);
+            eprintln!("{}", String::from_utf8_lossy(&unit.asm));
         }
+    }
+}
 
-        // Move to the next function in the list
-        if let Some(next) = worklist.next.as_ref() {
-            worklist = next;
-        } else {
-            break;
+/*
+***********************************************************************
+  LEAF-FRAME: LEAF FUNCTIONS DON'T NEED TO MOVE %RSP IF THEIR LOCALS FIT
+  IN THE RED ZONE, AND MAY DROP THE FRAME POINTER
+************************************************************************
+*/
+fn leaf_frame_pass(unit: &mut funcUnit) {
+    let is_leaf = match unit.node.statements.as_ref() {
+        Some(statements) => !function_makes_call(statements, &unit.glb),
+        None => true,
+    };
+
+    if is_leaf {
+        debug!("[DEBUG] {} is a leaf function, trying the red zone", unit.glb.func_name);
+        unit.glb.uses_red_zone = true;
+        if unit.glb.opts.omit_frame_pointer {
+            unit.glb.frame_base = "%rsp".to_string();
         }
     }
+}
+
+/*
+***********************************************************************
+  LOWER: GENERATE THE CODE OF THE PARAMETERS AND BODY
+************************************************************************
+*/
+fn lower_pass(unit: &mut funcUnit) {
+    unit.asm = process_function(unit.node, &mut unit.glb, &mut unit.reg_list);
+
+    if unit.glb.uses_red_zone && unit.glb.frame_size > RED_ZONE_SIZE {
+        debug!("[DEBUG] {} needs {} bytes of locals, too many for the red zone", unit.glb.func_name, unit.glb.frame_size);
+        let func_name = unit.glb.func_name.clone();
+        unit.glb = globals::new(unit.glb.opts, unit.glb.functions, unit.glb.variables, unit.glb.literals, unit.glb.asm_count);
+        unit.glb.func_name = func_name;
+        unit.asm = process_function(unit.node, &mut unit.glb, &mut unit.reg_list);
+    }
 
-    println!("Assembly code successfully written to 'assembly.s'");
+    if unit.glb.opts.ir_path.is_some() {
+        unit.ir = format!("\n{}:", unit.glb.func_name).into_bytes();
+        unit.ir.extend_from_slice(&unit.asm);
+        unit.ir.push(b'\n');
+    }
 }
 
 /*
-**********************************************************************************************************************************
- YOU CAN MAKE ADD AUXILLIARY FUNCTIONS BELOW THIS LINE. DO NOT FORGET TO DECLARE THEM IN THE HEADER
-**********************************************************************************************************************************
+***********************************************************************
+  PEEPHOLE: DROP MOVES THAT DON'T CHANGE ANYTHING, SUCH AS RELOADING A
+  VALUE THAT WAS JUST STORED. THE INSTRUCTIONS OF AN ASM STATEMENT ARE
+  KEPT AS THEY ARE WRITTEN AND NOTHING IS PAIRED ACROSS THEM.
+************************************************************************
 */
+fn split_movq(line: &str) -> Option<(&str, &str)> {
+    let instr = line.split('#').next()?.trim();
+    let operands = instr.strip_prefix("movq ")?;
+    let (src, dst) = operands.split_once(", ")?;
+
+    Some((src.trim(), dst.trim()))
+}
+
+fn peephole_pass(unit: &mut funcUnit) {
+    let code = String::from_utf8_lossy(&unit.asm).to_string();
+    let mut kept: Vec<&str> = vec![];
+    let mut in_asm = false;
+
+    for line in code.split('\n') {
+        if line == "# asm" || line == "# end of asm" {
+            in_asm = line == "# asm";
+        } else if let Some((src, dst)) = split_movq(line).filter(|_| !in_asm) {
+            if src == dst {
+                continue;
+            }
+            if let Some((prev_src, prev_dst)) = kept.last().and_then(|prev| split_movq(prev)) {
+                if prev_src == dst && prev_dst == src {
+                    continue;
+                }
+            }
+        }
+        kept.push(line);
+    }
+
+    unit.asm = kept.join("\n").into_bytes();
+}
+
+/*
+***********************************************************************
+  PROLOGUE: PUT THE SYMBOL AND FRAME SETUP IN FRONT OF THE BODY, NOW THAT
+  THE FRAME SIZE IS KNOWN
+************************************************************************
+*/
+fn prologue_pass(unit: &mut funcUnit) {
+    let mut code: Vec<u8> = vec![];
+
+    init_asm(&mut code, unit.glb.func_name.clone(), &unit.glb);
+    save_registers(&mut code, &unit.glb);
+    code.extend_from_slice(&unit.asm);
+    code.extend_from_slice(format!("\n.size {}, .-{}", unit.glb.func_name, unit.glb.func_name).as_bytes());
+    unit.asm = code;
+}
+
+        
+/*
+ ***********************************************************************
+  THIS FUNCTION IS MEANT TO DO CODEGEN FOR ALL THE FUNCTIONS IN THE FILE
+ ************************************************************************
+*/
+#[no_mangle]
+pub fn Codegen(worklist: &RList) {
+    // Codegen needs the type of every expression, the type checker records
+    // them in a copy of the program
+    let mut checked = worklist.clone();
+    if let Some(diagnostic) = crate::typecheck::check_types(&mut checked).first() {
+        panic!("Error: {}", diagnostic);
+    }
+    if let Err(message) = CodegenWithOptions(&checked, &CodegenOptions::default()) {
+        panic!("Error: {}", message);
+    }
+}
+
+/*
+ ***********************************************************************
+  SAME AS CODEGEN BUT WITH EXPLICIT OPTIONS (EG: THE OPTIMIZATION LEVEL).
+  THE PROGRAM MUST HAVE BEEN THROUGH THE TYPE CHECKER. FAILS WHEN THE
+  OUTPUT FILES CAN'T BE CREATED.
+ ************************************************************************
+*/
+#[no_mangle]
+pub fn CodegenWithOptions(mut worklist: &RList, opts: &CodegenOptions) -> Result<(), String> {
+    /*
+     ****************************************
+              TODO : YOUR CODE HERE
+     ****************************************
+    */
+
+    VERBOSE.store(opts.verbose, Ordering::Relaxed);
+
+    // Collect every function declaration so call sites can look up their
+    // callee, every global variable so functions can use them and every
+    // literal so they can be placed in .rodata
+    let mut functions: Vec<&RNode> = vec![];
+    let mut variables: Vec<&RNode> = vec![];
+    let mut literals = literalPool::default();
+    let mut decl = worklist;
+    loop {
+        if let Some(node) = decl.node.as_ref() {
+            match node.type_ {
+                NodeType::FUNCTIONDECL => {
+                    functions.push(node);
+                    literals.collect_statements(node.statements.as_deref());
+                    if opts.checked_arith {
+                        literals.add_string(&node.name);
+                    }
+                }
+                NodeType::EXTERNDECL => functions.push(node),
+                NodeType::VARDECL => {
+                    variables.push(node);
+                    if let Some(right) = node.right.as_ref() {
+                        literals.collect_expression(right);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(next) = decl.next.as_ref() {
+            decl = next;
+        } else {
+            break;
+        }
+    }
+
+    if opts.checked_arith {
+        for reason in TRAP_REASONS.iter() {
+            literals.add_string(reason);
+        }
+    }
+
+    //creates output assembly file
+    let mut fileptr = match File::create(&opts.output_path) {
+        Ok(file) => file,
+        Err(err) => return Err(format!("unable to create {}: {}", opts.output_path, err)),
+    };
+    let mut ir: Vec<u8> = vec![];
+
+    fileptr.write_all(".text".as_bytes()).expect("Unable to write data");
+    let asm_count = Cell::new(0);
+
+    // Loop through each function in the program
+    loop {
+        if let Some(node) = worklist.node.as_ref() {
+            if node.type_ == NodeType::FUNCTIONDECL {
+                // Get function name
+                let func_name = node.name.clone();
+                debug!("Generating assembly for function: {}", func_name);
+
+                // Initialize global variables
+                let mut unit = funcUnit {
+                    node,
+                    glb: globals::new(opts, &functions, &variables, &literals, &asm_count),
+                    reg_list: regList::new(),
+                    asm: vec![],
+                    ir: vec![],
+                };
+                unit.glb.func_name = func_name.clone();
+                create_reg_list(&mut unit.reg_list);
+
+                run_passes(&mut unit, opts);
+                fileptr.write_all(&unit.asm).expect("Unable to write data");
+                ir.extend_from_slice(&unit.ir);
+
+                // Add function epilogue (return)
+                // ret_asm(&mut fileptr);
+            }
+        } else {
+            break;
+        }
+
+        // Move to the next function in the list
+        if let Some(next) = worklist.next.as_ref() {
+            worklist = next;
+        } else {
+            break;
+        }
+    }
+
+    let glb = globals::new(opts, &functions, &variables, &literals, &asm_count);
+    emit_global_vars(&mut fileptr, &glb);
+    emit_undefined_visibility(&mut fileptr, &glb);
+    emit_literals(&mut fileptr, &literals);
+
+    // The stack of a program or library linked with the output needn't be executable
+    fileptr
+        .write_all("\n\n.section .note.GNU-stack,\"\",@progbits".as_bytes())
+        .expect("Unable to write data");
+
+    // The assembler wants the last line terminated
+    fileptr.write_all("\n".as_bytes()).expect("Unable to write data");
+
+    if let Some(ir_path) = opts.ir_path.as_ref() {
+        let mut irptr = match File::create(ir_path) {
+            Ok(file) => file,
+            Err(err) => return Err(format!("unable to create {}: {}", ir_path, err)),
+        };
+        irptr.write_all(&ir).expect("Unable to write data");
+    }
+
+    debug!("Assembly code successfully written to '{}'", opts.output_path);
+    Ok(())
+}
+
+/*
+**********************************************************************************************************************************
+ YOU CAN MAKE ADD AUXILLIARY FUNCTIONS BELOW THIS LINE. DO NOT FORGET TO DECLARE THEM IN THE HEADER
+**********************************************************************************************************************************
+*/
+
+/*
+***********************************************************************
+  FUNCTION TO EVALUATE AN EXPRESSION AND LEAVE ITS VALUE IN RAX
+************************************************************************
+*/
+fn load_into_rax(
+    fileptr: &mut dyn Write,
+    expr: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    if expr.exprCode == ExprType::CONSTANT {
+        fileptr
+            .write_all(load_constant(expr.value, "%rax").as_bytes())
+            .expect("Failed to load constant");
+    } else {
+        process_expression(fileptr, expr, glb, var_list, reg_list);
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO HAND OUT 8 BYTE STACK SLOTS IN THE CURRENT FRAME. SLOTS
+  RELEASED BY DEAD TEMPORARIES ARE REUSED BEFORE THE FRAME IS GROWN.
+************************************************************************
+*/
+fn frame_slot(glb: &globals, offset: i64) -> String {
+    format!("{}({})", offset, glb.frame_base)
+}
+
+fn alloc_stack_slot(glb: &mut globals) -> i64 {
+    if let Some(offset) = glb.free_slots.pop() {
+        return offset;
+    }
+
+    glb.frame_size += 8;
+    -glb.frame_size
+}
+
+fn new_stack_slot(glb: &mut globals) -> String {
+    let offset = alloc_stack_slot(glb);
+    long_to_char_offset(glb, offset);
+    debug!("[DEBUG] Reserved stack slot {}, frame size is now: {}", glb.last_offset_used, glb.frame_size);
+
+    glb.last_offset_used.clone()
+}
+
+// Arrays and structs always get fresh contiguous space at the bottom of the frame
+fn new_stack_array(glb: &mut globals, dataType: &DataType) -> String {
+    glb.frame_size += frame_bytes(dataType);
+    let offset = -glb.frame_size;
+    long_to_char_offset(glb, offset);
+    debug!("[DEBUG] Reserved {} bytes for an array at {}, frame size is now: {}", frame_bytes(dataType), glb.last_offset_used, glb.frame_size);
+
+    glb.last_offset_used.clone()
+}
+
+// Space a variable takes in the frame or in .bss. Scalars always fill a
+// whole slot, arrays and structs are padded so the slots after them stay
+// aligned. Nothing needs more than 8 byte alignment.
+fn frame_bytes(dataType: &DataType) -> i64 {
+    if dataType.is_aggregate() {
+        (dataType.size() + 7) / 8 * 8
+    } else {
+        8
+    }
+}
+
+fn new_temp_slot(glb: &mut globals) -> String {
+    let offset = alloc_stack_slot(glb);
+    glb.temp_slots.push(offset);
+    long_to_char_offset(glb, offset);
+
+    glb.last_offset_used.clone()
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR GLOBAL VARIABLES. EVERY FUNCTION SEES THEM IN A SCOPE
+  AROUND ITS OWN, ADDRESSED RELATIVE TO RIP. INITIALIZED ONES GO IN
+  .DATA, THE OTHERS IN .BSS WHICH THE LOADER ZERO FILLS.
+************************************************************************
+*/
+fn declare_global_vars(glb: &globals, var_list: &mut varStList) {
+    for variable in glb.variables.iter() {
+        let location = global_location(glb, &variable.name);
+        var_list.add_typed_var_info(variable.name.clone(), location, variable.dataType.clone());
+    }
+    var_list.push_scope();
+}
+
+fn global_location(glb: &globals, name: &str) -> String {
+    if glb.opts.pic && !is_local_symbol(glb, name) {
+        format!("{}@GOTPCREL(%rip)", name)
+    } else {
+        format!("{}(%rip)", name)
+    }
+}
+
+// A global reached through its GOT entry has its address loaded into %r11
+// first, any other location is used as it is
+fn resolve_global(fileptr: &mut dyn Write, location: String) -> String {
+    if !location.ends_with("@GOTPCREL(%rip)") {
+        return location;
+    }
+    fileptr
+        .write_all(format!("\nmovq {}, %r11", location).as_bytes())
+        .expect("Failed to load global address");
+    "(%r11)".to_string()
+}
+
+// A global that is only declared extern here is read and written with the
+// width of its type, the defining file may not have a whole slot for it
+fn is_extern_variable(glb: &globals, name: &str, location: &str) -> bool {
+    let mut declarations = glb.variables.iter().filter(|variable| variable.name == name).peekable();
+    location == global_location(glb, name)
+        && declarations.peek().is_some()
+        && declarations.all(|variable| variable.storage == StorageClass::EXTERN)
+}
+
+fn emit_global_vars(fileptr: &mut dyn Write, glb: &globals) {
+    let literals = glb.literals;
+    for variable in glb.variables.iter().filter(|variable| variable.storage != StorageClass::EXTERN) {
+        // A global initialized with a string literal points at its copy in .rodata
+        let initial = match variable.right.as_ref() {
+            Some(right) if right.exprCode == ExprType::STRING => Some(literals.string_label(&right.name)),
+            // The address of a function, filled in by the linker
+            Some(right) if address_constant(right).is_some() => address_constant(right).map(str::to_string),
+            Some(right) if variable.dataType.is_float() => fold_double(right)
+                .filter(|value| value.to_bits() != 0)
+                .map(|value| format!("{}  # {:?}", value.to_bits(), value)),
+            Some(right) if has_double_constant(right) => fold_double(right)
+                .map(|value| variable.dataType.wrap(value as i64))
+                .filter(|value| *value != 0)
+                .map(|value| value.to_string()),
+            Some(right) => fold_constant(right)
+                .map(|value| variable.dataType.wrap(value))
+                .filter(|value| *value != 0)
+                .map(|value| value.to_string()),
+            None => None,
+        };
+        debug!("[DEBUG] Emitting global {} = {}", variable.name, initial.as_deref().unwrap_or("0"));
+
+        let section = if initial.is_some() { ".data" } else { ".bss" };
+        let contents = match initial {
+            Some(initial) => format!(".quad {}", initial),
+            None => format!(".zero {}", frame_bytes(&variable.dataType)),
+        };
+        fileptr
+            .write_all(
+                format!(
+                    "\n\n{}{}\n.align 8\n{}:\n{}\n.size {}, {}",
+                    section,
+                    symbol_directives(glb, &variable.name, "@object"),
+                    variable.name,
+                    contents,
+                    variable.name,
+                    frame_bytes(&variable.dataType)
+                )
+                .as_bytes(),
+            )
+            .expect("Failed to write global variable");
+    }
+}
+
+/*
+*************************************************************************************
+ LITERALS OF THE FILE THAT ARE PLACED IN .RODATA, STRINGS AS .LC<N> AND DOUBLES,
+ BY THEIR BIT PATTERN, AS .LD<N>. EACH DISTINCT LITERAL IS KEPT ONCE.
+*************************************************************************************
+*/
+#[derive(Default)]
+struct literalPool {
+    strings: Vec<String>,
+    doubles: Vec<u64>,
+}
+
+impl literalPool {
+    fn collect_statements(&mut self, statements: Option<&RList>) {
+        let mut current = statements;
+        while let Some(item) = current {
+            if let Some(node) = item.node.as_ref() {
+                if node.stmtCode == StmtType::BLOCK {
+                    self.collect_statements(node.statements.as_deref());
+                }
+                for operand in [node.left.as_ref(), node.right.as_ref()].into_iter().flatten() {
+                    self.collect_expression(operand);
+                }
+            }
+            current = item.next.as_deref();
+        }
+    }
+
+    fn add_string(&mut self, contents: &str) {
+        if !self.strings.iter().any(|string| string == contents) {
+            self.strings.push(contents.to_string());
+        }
+    }
+
+    fn collect_expression(&mut self, expr: &RNode) {
+        if expr.exprCode == ExprType::STRING {
+            self.add_string(&expr.name);
+            return;
+        }
+        if expr.exprCode == ExprType::FCONSTANT {
+            if !self.doubles.contains(&expr.fvalue.to_bits()) {
+                self.doubles.push(expr.fvalue.to_bits());
+            }
+            return;
+        }
+        for operand in [expr.left.as_ref(), expr.right.as_ref()].into_iter().flatten() {
+            self.collect_expression(operand);
+        }
+
+        let mut args = expr.arguments.as_deref();
+        while let Some(arg_list) = args {
+            if let Some(arg) = arg_list.node.as_ref() {
+                self.collect_expression(arg);
+            }
+            args = arg_list.next.as_deref();
+        }
+    }
+
+    fn string_label(&self, contents: &str) -> String {
+        match self.strings.iter().position(|string| string == contents) {
+            Some(index) => format!(".LC{}", index),
+            None => panic!("Error: String literal \"{}\" was not collected", contents),
+        }
+    }
+
+    // Operand reading the constant from .rodata
+    fn double_operand(&self, value: f64) -> String {
+        match self.doubles.iter().position(|bits| *bits == value.to_bits()) {
+            Some(index) => format!(".LD{}(%rip)", index),
+            None => panic!("Error: Floating point constant {:?} was not collected", value),
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO EMIT THE STRING AND DOUBLE LITERALS INTO .RODATA
+************************************************************************
+*/
+fn emit_literals(fileptr: &mut dyn Write, literals: &literalPool) {
+    if literals.strings.is_empty() && literals.doubles.is_empty() {
+        return;
+    }
+
+    fileptr.write_all("\n\n.section .rodata".as_bytes()).expect("Failed to write literals");
+    for (index, contents) in literals.strings.iter().enumerate() {
+        fileptr
+            .write_all(format!("\n.LC{}:\n.string \"{}\"", index, escape_string(contents)).as_bytes())
+            .expect("Failed to write string literal");
+    }
+    if !literals.doubles.is_empty() {
+        fileptr.write_all("\n.align 8".as_bytes()).expect("Failed to write literals");
+    }
+    for (index, bits) in literals.doubles.iter().enumerate() {
+        fileptr
+            .write_all(format!("\n.LD{}:\n.quad {}  # {:?}", index, bits, f64::from_bits(*bits)).as_bytes())
+            .expect("Failed to write floating point literal");
+    }
+}
+
+// Quote a string the way the assembler's .string directive reads it back
+fn escape_string(contents: &str) -> String {
+    let mut escaped = String::new();
+    for byte in contents.bytes() {
+        match byte {
+            b'\\' => escaped.push_str("\\\\"),
+            b'"' => escaped.push_str("\\\""),
+            b'\n' => escaped.push_str("\\n"),
+            b'\t' => escaped.push_str("\\t"),
+            b'\r' => escaped.push_str("\\r"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+/*
+***********************************************************************
+  FUNCTION TO LEAVE THE INNERMOST SCOPE, GIVING THE SLOTS OF ITS
+  VARIABLES BACK TO THE FRAME
+************************************************************************
+*/
+fn release_scope_slots(glb: &mut globals, var_list: &mut varStList) {
+    for (location, dataType) in var_list.pop_scope() {
+        let offset = location.split('(').next().and_then(|offset| offset.parse::<i64>().ok());
+        if let Some(offset) = offset {
+            debug!("[DEBUG] Scope ended, releasing slot {}", location);
+
+            // An array's space is handed out again as separate slots
+            let mut slot = offset + frame_bytes(&dataType) - 8;
+            while slot >= offset {
+                glb.free_slots.push(slot);
+                slot -= 8;
+            }
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO RELEASE THE TEMPORARIES CREATED SINCE THE GIVEN MARK
+************************************************************************
+*/
+fn release_temp_slots(glb: &mut globals, mark: usize, var_list: &mut varStList) {
+    while glb.temp_slots.len() > mark {
+        if let Some(offset) = glb.temp_slots.pop() {
+            // A constant kept in this slot must not be found there anymore
+            var_list.remove_location(&frame_slot(glb, offset));
+            glb.free_slots.push(offset);
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR ARRAY ELEMENTS AND STORES THROUGH POINTERS. THE INDEX
+  GOES IN RCX AND THE ELEMENT IS ADDRESSED WITH A SCALED INDEX FROM THE
+  START OF THE ARRAY, THROUGH R11 FOR POINTERS, FOR GLOBALS SINCE RIP
+  RELATIVE ADDRESSES CAN'T BE INDEXED AND FOR ARRAYS THAT AREN'T A
+  VARIABLE (AN ARRAY FIELD).
+************************************************************************
+*/
+fn element_operand(
+    fileptr: &mut dyn Write,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+    array: &RNode,
+    index_location: &str,
+) -> String {
+    if array.exprCode != ExprType::VARIABLE {
+        return computed_element_operand(fileptr, glb, var_list, reg_list, array, index_location);
+    }
+
+    let array_name = array.name.as_str();
+    let location = var_list.lookup_var_info(array_name.to_string(), None);
+    let dataType = var_list.lookup_var_type(array_name);
+
+    fileptr
+        .write_all(format!("\nmovq {}, %rcx", index_location).as_bytes())
+        .expect("Failed to load array index");
+
+    // Indexing a pointer addresses from the pointer's value, unchecked
+    if let Some(DataType::POINTER(element)) = dataType {
+        let scale = index_scale(fileptr, &element);
+        let location = resolve_global(fileptr, location);
+        fileptr
+            .write_all(format!("\nmovq {}, %r11", location).as_bytes())
+            .expect("Failed to load pointer");
+        return format!("(%r11,%rcx,{})", scale);
+    }
+
+    let (element, count) = match dataType {
+        Some(DataType::ARRAY(element, count)) => (element, count),
+        _ => panic!("Error: {} is not an array!", array_name),
+    };
+
+    if glb.opts.bounds_check {
+        emit_bounds_check(fileptr, glb, count);
+    }
+    let scale = index_scale(fileptr, &element);
+
+    if location.ends_with("(%rip)") {
+        let instr = if location.ends_with("@GOTPCREL(%rip)") { "movq" } else { "leaq" };
+        fileptr
+            .write_all(format!("\n{} {}, %r11", instr, location).as_bytes())
+            .expect("Failed to load array address");
+        return format!("(%r11,%rcx,{})", scale);
+    }
+
+    match location.split_once('(') {
+        Some((offset, base)) => format!("{}({},%rcx,{})", offset, base.trim_end_matches(')'), scale),
+        None => panic!("Error: array {} is not in memory!", array_name),
+    }
+}
+
+// The value of an array expression is the address of its first element, of
+// a pointer the address it holds. Only an array field knows its length for
+// the bounds check.
+fn computed_element_operand(
+    fileptr: &mut dyn Write,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+    array: &RNode,
+    index_location: &str,
+) -> String {
+    let mut array_type = expression_type(array);
+    if array.exprCode == ExprType::OPERATION && array.opCode == OpType::FIELD {
+        if let Some(base) = array.left.as_ref() {
+            if let Some(field) = expression_type(base).field(&array.name) {
+                array_type = field.dataType;
+            }
+        }
+    }
+    let element = match array_type.element() {
+        Some(element) => element.clone(),
+        None => panic!("Error: {} can't be indexed!", array_type.type_name()),
+    };
+
+    load_into_rax(fileptr, array, glb, var_list, reg_list);
+    fileptr
+        .write_all(format!("
+movq %rax, %r11
+movq {}, %rcx", index_location).as_bytes())
+        .expect("Failed to load array address");
+
+    if let DataType::ARRAY(_, count) = array_type {
+        if glb.opts.bounds_check {
+            emit_bounds_check(fileptr, glb, count);
+        }
+    }
+    let scale = index_scale(fileptr, &element);
+    format!("(%r11,%rcx,{})", scale)
+}
+
+// An index can only be scaled by 1, 2, 4 or 8, for other element sizes
+// (structs) the index in rcx is turned into a byte offset
+fn index_scale(fileptr: &mut dyn Write, element: &DataType) -> i64 {
+    if matches!(element.size(), 1 | 2 | 4 | 8) {
+        return element.size();
+    }
+
+    fileptr
+        .write_all(format!("\nimulq ${}, %rcx", element.size()).as_bytes())
+        .expect("Failed to scale array index");
+    1
+}
+
+// An unsigned compare also catches negative indices
+fn emit_bounds_check(fileptr: &mut dyn Write, glb: &mut globals, count: i64) {
+    let label = format!(".L{}_in_bounds{}", glb.func_name, glb.label_counter);
+    glb.label_counter += 1;
+
+    // The trap doesn't return, so realigning the stack for it is harmless
+    fileptr
+        .write_all(
+            format!(
+                "\ncmpq ${}, %rcx\njb {}\nmovq %rcx, %rdi\nmovq ${}, %rsi\nandq $-16, %rsp\ncall {}\n{}:",
+                count,
+                label,
+                count,
+                call_target(glb, &glb.opts.bounds_trap),
+                label
+            )
+            .as_bytes(),
+        )
+        .expect("Failed to write bounds check");
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR CHECKED ARITHMETIC. A FAILED CHECK JUMPS TO A LANDING
+  PAD AFTER THE FUNCTION, WHICH LOADS THE ARGUMENTS OF THE TRAP ROUTINE,
+  trap(function, line, col, reason), AND JUMPS TO THE FUNCTION'S TRAP
+  STUB THAT CALLS IT.
+************************************************************************
+*/
+const TRAP_REASONS: [&str; 2] = ["integer overflow", "division by zero"];
+
+// Label of a new landing pad for the operator of expr
+fn trap_site(glb: &mut globals, expr: &RNode, reason: &str) -> String {
+    let label = format!(".L{}_trap{}", glb.func_name, glb.label_counter);
+    glb.label_counter += 1;
+
+    let function = glb.inlined.last().unwrap_or(&glb.func_name);
+    let pad = format!(
+        "\n{}:\nleaq {}(%rip), %rdi\nmovq ${}, %rsi\nmovq ${}, %rdx\nleaq {}(%rip), %rcx\njmp .L{}_trap",
+        label,
+        glb.literals.string_label(function),
+        expr.line,
+        expr.col,
+        glb.literals.string_label(reason),
+        glb.func_name
+    );
+    glb.trap_sites.push(pad);
+    label
+}
+
+// Signed results only, unsigned arithmetic wraps. A long overflows when the
+// instruction sets OF, a narrower result when it changes on sign extension.
+fn emit_overflow_check(fileptr: &mut dyn Write, glb: &mut globals, expr: &RNode, dataType: &DataType) {
+    let extend = match dataType {
+        DataType::LONG => None,
+        DataType::INT => Some("movslq %eax, %r11"),
+        DataType::SHORT => Some("movswq %ax, %r11"),
+        DataType::CHAR => Some("movsbq %al, %r11"),
+        _ => return,
+    };
+
+    let label = trap_site(glb, expr, TRAP_REASONS[0]);
+    let code = match extend {
+        None => format!("\njo {}", label),
+        Some(extend) => format!("\n{}\ncmpq %r11, %rax\njne {}", extend, label),
+    };
+    fileptr
+        .write_all(code.as_bytes())
+        .expect("Failed to write overflow check");
+}
+
+// Before a division with the dividend in %rax: the divisor must not be zero
+// and a signed INT64_MIN must not be divided by -1, which negq detects
+fn emit_division_check(fileptr: &mut dyn Write, glb: &mut globals, expr: &RNode, divisor: &str, dataType: &DataType) {
+    let label = trap_site(glb, expr, TRAP_REASONS[1]);
+    let mut code = format!("\ncmpq $0, {}\nje {}", divisor, label);
+
+    if !dataType.is_unsigned() {
+        let label = trap_site(glb, expr, TRAP_REASONS[0]);
+        let divisor_ok = format!(".L{}_divisor_ok{}", glb.func_name, glb.label_counter);
+        glb.label_counter += 1;
+        code.push_str(&format!(
+            "\ncmpq $-1, {}\njne {}\nmovq %rax, %r11\nnegq %r11\njo {}\n{}:",
+            divisor, divisor_ok, label, divisor_ok
+        ));
+    }
+    fileptr
+        .write_all(code.as_bytes())
+        .expect("Failed to write division check");
+}
+
+// The landing pads of the function followed by its trap stub
+fn emit_trap_stub(fileptr: &mut dyn Write, glb: &mut globals) {
+    if glb.trap_sites.is_empty() {
+        return;
+    }
+
+    // The trap doesn't return, so realigning the stack for it is harmless
+    let mut code = glb.trap_sites.concat();
+    code.push_str(&format!("\n.L{}_trap:\nandq $-16, %rsp\ncall {}", glb.func_name, call_target(glb, &glb.opts.arith_trap)));
+    glb.trap_sites.clear();
+    fileptr
+        .write_all(code.as_bytes())
+        .expect("Failed to write trap stub");
+}
+
+fn store_indirect(
+    fileptr: &mut dyn Write,
+    node: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    let target = match node.left.as_ref() {
+        Some(target) => target,
+        None => return,
+    };
+    let temp_mark = glb.temp_slots.len();
+
+    // The address parts are computed before the value, which ends up in rax
+    let mut index_location = String::new();
+    if let Some(index) = target.right.as_ref() {
+        index_location = process_operand(fileptr, index, glb, var_list, reg_list);
+    }
+    let mut pointer_location = String::new();
+    if target.opCode == OpType::DEREF {
+        if let Some(pointer) = target.left.as_ref() {
+            pointer_location = process_operand(fileptr, pointer, glb, var_list, reg_list);
+        }
+    }
+    // An element of an array that isn't a variable has its address kept
+    // like a pointer, computing it needs rax
+    if target.opCode == OpType::INDEX {
+        if let Some(array) = target.left.as_ref().filter(|array| array.exprCode != ExprType::VARIABLE) {
+            let element = computed_element_operand(fileptr, glb, var_list, reg_list, array, &index_location);
+            let temp = new_temp_slot(glb);
+            fileptr
+                .write_all(format!("
+leaq {}, %rax
+movq %rax, {}", element, temp).as_bytes())
+                .expect("Failed to save element address");
+            pointer_location = temp;
+        }
+    }
+    // A field computed from a pointer has its address kept like a pointer
+    let mut field_location = String::new();
+    if target.opCode == OpType::FIELD {
+        field_location = field_operand(fileptr, target, glb, var_list, reg_list);
+        if field_location.contains("%r11") {
+            let temp = new_temp_slot(glb);
+            fileptr
+                .write_all(format!("\nleaq {}, %rax\nmovq %rax, {}", field_location, temp).as_bytes())
+                .expect("Failed to save field address");
+            pointer_location = temp;
+        }
+    }
+
+    let target_type = expression_type(target);
+    if let Some(right) = node.right.as_ref() {
+        load_into_rax(fileptr, right, glb, var_list, reg_list);
+
+        // A narrower integer is truncated by the store itself
+        let value_type = expression_type(right);
+        if value_type.is_float() != target_type.is_float() && !target_type.is_struct() {
+            emit_cast(fileptr, glb, &value_type, &target_type);
+        }
+    }
+
+    let operand = if !pointer_location.is_empty() {
+        fileptr
+            .write_all(format!("\nmovq {}, %r11", pointer_location).as_bytes())
+            .expect("Failed to load pointer");
+        "(%r11)".to_string()
+    } else if target.opCode == OpType::FIELD {
+        field_location
+    } else {
+        let array = match target.left.as_ref() {
+            Some(array) => array,
+            None => panic!("Error: index without an array!"),
+        };
+        element_operand(fileptr, glb, var_list, reg_list, array, &index_location)
+    };
+    if target_type.is_struct() {
+        fileptr
+            .write_all(format!("\nleaq {}, %r11", operand).as_bytes())
+            .expect("Failed to take store address");
+        emit_struct_copy(fileptr, target_type.size());
+    } else {
+        fileptr
+            .write_all(format!("\n{}, {}", store_instr(&target_type), operand).as_bytes())
+            .expect("Failed to store through address");
+    }
+
+    release_temp_slots(glb, temp_mark, var_list);
+}
+
+/*
+***********************************************************************
+  FUNCTION TO GET THE MEMORY OPERAND OF A STRUCT FIELD. A FIELD OF A
+  STRUCT VARIABLE IS ADDRESSED AT ITS OFFSET FROM THE VARIABLE, ANY
+  OTHER STRUCT IS COMPUTED TO ITS ADDRESS, WHICH IS LEFT IN R11.
+************************************************************************
+*/
+fn field_operand(
+    fileptr: &mut dyn Write,
+    expr: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) -> String {
+    let base = match expr.left.as_ref() {
+        Some(base) => base,
+        None => panic!("Error: field {} has no struct!", expr.name),
+    };
+    let base_type = expression_type(base);
+    let offset = match base_type.field(&expr.name) {
+        Some(field) => field.offset,
+        None => panic!("Error: {} has no field {}!", base_type.type_name(), expr.name),
+    };
+
+    if base.exprCode == ExprType::VARIABLE {
+        let location = resolve_global(fileptr, var_list.lookup_var_info(base.name.clone(), None));
+        if location.contains('(') {
+            return displaced(&location, offset);
+        }
+    }
+    if base.exprCode == ExprType::OPERATION && base.opCode == OpType::FIELD {
+        let operand = field_operand(fileptr, base, glb, var_list, reg_list);
+        return displaced(&operand, offset);
+    }
+
+    // The value of a struct expression is its address
+    process_expression(fileptr, base, glb, var_list, reg_list);
+    fileptr
+        .write_all("\nmovq %rax, %r11".as_bytes())
+        .expect("Failed to load struct address");
+    displaced("(%r11)", offset)
+}
+
+// The memory operand the given number of bytes past another one
+fn displaced(location: &str, offset: i64) -> String {
+    if offset == 0 {
+        return location.to_string();
+    }
+
+    match location.split_once('(') {
+        Some(("", base)) => format!("{}({}", offset, base),
+        Some((displacement, base)) => format!("{}+{}({}", displacement, offset, base),
+        None => panic!("Error: {} is not in memory!", location),
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO COPY STRUCTS. A STRUCT VALUE IN RAX IS ITS ADDRESS, THE
+  BYTES ARE COPIED FROM THERE TO THE ADDRESS IN R11 THROUGH RCX.
+************************************************************************
+*/
+fn emit_struct_copy(fileptr: &mut dyn Write, size: i64) {
+    let mut code = String::new();
+    let mut offset = 0;
+
+    for (width, register, suffix) in [(8, "%rcx", "q"), (4, "%ecx", "l"), (2, "%cx", "w"), (1, "%cl", "b")] {
+        while size - offset >= width {
+            code.push_str(&format!(
+                "\nmov{} {}(%rax), {}\nmov{} {}, {}(%r11)",
+                suffix, offset, register, suffix, register, offset
+            ));
+            offset += width;
+        }
+    }
+    fileptr.write_all(code.as_bytes()).expect("Failed to copy struct");
+}
+
+// Stores the value in rax into a variable, only the width of its type if
+// the memory has no more
+fn store_variable(fileptr: &mut dyn Write, location: &str, dataType: &DataType, exact_width: bool) {
+    let code = if dataType.is_struct() {
+        format!("\nleaq {}, %r11", location)
+    } else if exact_width {
+        format!("\n{}, {}", store_instr(dataType), location)
+    } else {
+        format!("\nmovq %rax, {}", location)
+    };
+    fileptr.write_all(code.as_bytes()).expect("Failed to store variable in memory");
+
+    if dataType.is_struct() {
+        emit_struct_copy(fileptr, dataType.size());
+    }
+}
+
+// Returns the struct whose address is in rax: a large one is copied to the
+// caller's space and that address returned, a small one is loaded into the
+// return registers of its eightbytes
+fn emit_struct_return(fileptr: &mut dyn Write, glb: &globals, dataType: &DataType) {
+    match arg_classes(dataType) {
+        None => {
+            fileptr
+                .write_all(format!("\nmovq {}, %r11", glb.return_address).as_bytes())
+                .expect("Failed to load the address of the result");
+            emit_struct_copy(fileptr, dataType.size());
+            fileptr
+                .write_all("\nmovq %r11, %rax".as_bytes())
+                .expect("Failed to return the address of the result");
+        }
+        Some(classes) => {
+            let mut code = "\nmovq %rax, %r11".to_string();
+            for (eightbyte, register) in return_registers(&classes).iter().enumerate() {
+                code.push_str(&format!("\nmovq {}(%r11), {}", 8 * eightbyte, register));
+            }
+            fileptr.write_all(code.as_bytes()).expect("Failed to return struct in registers");
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO LEAVE THE ADDRESS OF A VARIABLE, AN ELEMENT, A FIELD OR A
+  DEREFERENCED POINTER IN RAX
+************************************************************************
+*/
+fn emit_address(
+    fileptr: &mut dyn Write,
+    operand: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    if operand.exprCode == ExprType::VARIABLE {
+        let location = var_list.lookup_var_info(operand.name.clone(), None);
+        // &f is the same as f, the GOT entry of a global holds its address
+        if location.is_empty() && is_function_name(glb, &operand.name) {
+            fileptr
+                .write_all(function_address(glb, &operand.name, "%rax").as_bytes())
+                .expect("Failed to take function address");
+            return;
+        }
+        if location.ends_with("@GOTPCREL(%rip)") {
+            fileptr
+                .write_all(format!("\nmovq {}, %rax", location).as_bytes())
+                .expect("Failed to take global address");
+            return;
+        }
+        if location.is_empty() || location.starts_with('%') {
+            panic!("Error: Variable {} is not in memory, its address can't be taken!", operand.name);
+        }
+        fileptr
+            .write_all(format!("\nleaq {}, %rax", location).as_bytes())
+            .expect("Failed to take address");
+        return;
+    }
+
+    match operand.opCode {
+        // &*p is p
+        OpType::DEREF => {
+            if let Some(pointer) = operand.left.as_ref() {
+                load_into_rax(fileptr, pointer, glb, var_list, reg_list);
+            }
+        }
+
+        OpType::INDEX => {
+            let temp_mark = glb.temp_slots.len();
+            let mut index_location = String::new();
+            if let Some(index) = operand.right.as_ref() {
+                index_location = process_operand(fileptr, index, glb, var_list, reg_list);
+            }
+            let array = match operand.left.as_ref() {
+                Some(array) => array,
+                None => panic!("Error: index without an array!"),
+            };
+
+            let element = element_operand(fileptr, glb, var_list, reg_list, array, &index_location);
+            fileptr
+                .write_all(format!("\nleaq {}, %rax", element).as_bytes())
+                .expect("Failed to take element address");
+            release_temp_slots(glb, temp_mark, var_list);
+        }
+
+        OpType::FIELD => {
+            let field = field_operand(fileptr, operand, glb, var_list, reg_list);
+            fileptr
+                .write_all(format!("\nleaq {}, %rax", field).as_bytes())
+                .expect("Failed to take field address");
+        }
+
+        _ => panic!("Error: can't take the address of a {:?}!", operand.opCode),
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR POINTER ARITHMETIC. A POINTER PLUS OR MINUS AN INTEGER
+  MOVES BY WHOLE ELEMENTS, THE DIFFERENCE OF TWO POINTERS COUNTS THE
+  ELEMENTS BETWEEN THEM.
+************************************************************************
+*/
+fn expression_type(expr: &RNode) -> DataType {
+    // The type checker has annotated every expression
+    match expr.valueType.as_ref() {
+        Some(dataType) => dataType.clone(),
+        None => panic!("Error: the expression at {}:{} has no type, run the type checker first!", expr.line, expr.col),
+    }
+}
+
+fn operand_types(expr: &RNode) -> [DataType; 2] {
+    [
+        expr.left.as_ref().map_or(DataType::LONG, |left| expression_type(left)),
+        expr.right.as_ref().map_or(DataType::LONG, |right| expression_type(right)),
+    ]
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR INTEGERS NARROWER THAN A LONG. IN MEMORY THEY HAVE THEIR
+  OWN WIDTH, IN %RAX THEY ARE SIGN OR ZERO EXTENDED TO 64 BITS. WRITING
+  A 32 BIT REGISTER CLEARS THE UPPER HALF, WHICH ZERO EXTENDS.
+************************************************************************
+*/
+fn load_from(dataType: &DataType, operand: &str) -> String {
+    match dataType {
+        DataType::CHAR => format!("\nmovsbq {}, %rax", operand),
+        DataType::SHORT => format!("\nmovswq {}, %rax", operand),
+        DataType::INT => format!("\nmovslq {}, %rax", operand),
+        DataType::UCHAR => format!("\nmovzbl {}, %eax", operand),
+        DataType::USHORT => format!("\nmovzwl {}, %eax", operand),
+        DataType::UINT => format!("\nmovl {}, %eax", operand),
+        _ => format!("\nmovq {}, %rax", operand),
+    }
+}
+
+fn store_instr(dataType: &DataType) -> &'static str {
+    match dataType {
+        DataType::CHAR | DataType::UCHAR => "movb %al",
+        DataType::SHORT | DataType::USHORT => "movw %ax",
+        DataType::INT | DataType::UINT => "movl %eax",
+        _ => "movq %rax",
+    }
+}
+
+// Re-extends the value in %rax from the width of the type, wrapping it
+// into the type's range
+fn emit_convert(fileptr: &mut dyn Write, dataType: &DataType) {
+    let instr = match dataType {
+        DataType::CHAR => "movsbq %al, %rax",
+        DataType::SHORT => "movswq %ax, %rax",
+        DataType::INT => "movslq %eax, %rax",
+        DataType::UCHAR => "movzbl %al, %eax",
+        DataType::USHORT => "movzwl %ax, %eax",
+        DataType::UINT => "movl %eax, %eax",
+        _ => return,
+    };
+    fileptr
+        .write_all(format!("\n{}", instr).as_bytes())
+        .expect("Failed to convert value");
+}
+
+// A value converts for free if every value of its type is held the same way
+// in the other type, which is always the case for a 64 bit target
+fn needs_conversion(from: &DataType, to: &DataType) -> bool {
+    if to.size() == 8 || from == to {
+        return false;
+    }
+    !(from.size() < to.size() && (from.is_unsigned() || !to.is_unsigned()))
+}
+
+/*
+***********************************************************************
+  FUNCTION TO CONVERT THE VALUE IN %RAX TO ANOTHER TYPE. CONVERSIONS
+  BETWEEN INTEGERS AND DOUBLES GO THROUGH %XMM0, A DOUBLE IS TRUNCATED
+  TOWARD ZERO AND MUST FIT IN A LONG.
+************************************************************************
+*/
+fn emit_cast(fileptr: &mut dyn Write, glb: &mut globals, from: &DataType, to: &DataType) {
+    if from.is_float() == to.is_float() {
+        emit_convert(fileptr, to);
+        return;
+    }
+
+    let code = if from.is_float() {
+        "\nmovq %rax, %xmm0\ncvttsd2siq %xmm0, %rax".to_string()
+    } else if *from == DataType::ULONG {
+        // cvtsi2sdq reads a signed value: one with the top bit set is halved,
+        // keeping the low bit so it rounds the same, and doubled again
+        let label = format!(".L{}_to_double{}", glb.func_name, glb.label_counter);
+        glb.label_counter += 1;
+        format!(
+            "\ntestq %rax, %rax\njs {}_big\ncvtsi2sdq %rax, %xmm0\njmp {}\n{}_big:\nmovq %rax, %rcx\nshrq %rcx\nandl $1, %eax\norq %rax, %rcx\ncvtsi2sdq %rcx, %xmm0\naddsd %xmm0, %xmm0\n{}:\nmovq %xmm0, %rax",
+            label, label, label, label
+        )
+    } else {
+        "\ncvtsi2sdq %rax, %xmm0\nmovq %xmm0, %rax".to_string()
+    };
+    fileptr.write_all(code.as_bytes()).expect("Failed to convert value");
+
+    if from.is_float() {
+        emit_convert(fileptr, to);
+    }
+}
+
+fn needs_cast(from: &DataType, to: &DataType) -> bool {
+    from.is_float() != to.is_float() || needs_conversion(from, to)
+}
+
+// Location of an operand converted to the type an operation is done in
+fn converted_operand(
+    fileptr: &mut dyn Write,
+    glb: &mut globals,
+    location: &str,
+    from: &DataType,
+    to: &DataType,
+) -> String {
+    if !needs_cast(from, to) {
+        return location.to_string();
+    }
+
+    let temp = new_temp_slot(glb);
+    fileptr
+        .write_all(format!("\nmovq {}, %rax", location).as_bytes())
+        .expect("Failed to load operand");
+    emit_cast(fileptr, glb, from, to);
+    fileptr
+        .write_all(format!("\nmovq %rax, {}", temp).as_bytes())
+        .expect("Failed to save converted operand");
+    temp
+}
+
+fn element_size(dataType: &DataType) -> i64 {
+    dataType.element().map_or(1, |element| element.size())
+}
+
+fn emit_pointer_arithmetic(
+    fileptr: &mut dyn Write,
+    op: OpType,
+    left: (&String, &DataType),
+    right: (&String, &DataType),
+) {
+    let (left_location, left_type) = left;
+    let (right_location, right_type) = right;
+
+    let code = if left_type.is_pointer() && right_type.is_pointer() {
+        // Only a difference gets here, the byte distance is divided exactly
+        let size = element_size(left_type);
+        format!(
+            "\nmovq {}, %rax\nsubq {}, %rax\ncqto\nmovq ${}, %rcx\nidivq %rcx",
+            left_location, right_location, size
+        )
+    } else if left_type.is_pointer() {
+        let instr = if op == OpType::ADD { "addq" } else { "subq" };
+        format!(
+            "\nmovq {}, %rcx\nimulq ${}, %rcx\nmovq {}, %rax\n{} %rcx, %rax",
+            right_location,
+            element_size(left_type),
+            left_location,
+            instr
+        )
+    } else {
+        format!(
+            "\nmovq {}, %rax\nimulq ${}, %rax\naddq {}, %rax",
+            left_location,
+            element_size(right_type),
+            right_location
+        )
+    };
+
+    fileptr.write_all(code.as_bytes()).expect("Failed to generate pointer arithmetic");
+}
+
+/*
+***********************************************************************
+  FUNCTION TO GET THE LOCATION OF AN OPERAND. VARIABLES ARE USED WHERE
+  THEY LIVE, ANYTHING ELSE IS COMPUTED INTO A TEMPORARY.
+************************************************************************
+*/
+fn process_operand(
+    fileptr: &mut dyn Write,
+    expr: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) -> String {
+    match expr.exprCode {
+        ExprType::VARIABLE => {
+            let location = var_list.lookup_var_info(expr.name.clone(), None);
+            if location.is_empty() && is_function_name(glb, &expr.name) {
+                let temp = new_temp_slot(glb);
+                fileptr
+                    .write_all(format!("{}\nmovq %rax, {}", function_address(glb, &expr.name, "%rax"), temp).as_bytes())
+                    .expect("Failed to load function address");
+                return temp;
+            }
+            if location.is_empty() {
+                panic!("Error: Variable {} not found!", expr.name);
+            }
+
+            // The operand is read later, %r11 may be reused by then
+            let is_extern = is_extern_variable(glb, &expr.name, &location);
+            let through_got = location.ends_with("@GOTPCREL(%rip)");
+            let location = resolve_global(fileptr, location);
+
+            // An array's or a struct's value is its address, which has to be computed
+            let dataType = var_list.lookup_var_type(&expr.name).unwrap_or(DataType::LONG);
+            if dataType.is_aggregate() {
+                let temp = new_temp_slot(glb);
+                fileptr
+                    .write_all(format!("\nleaq {}, %rax\nmovq %rax, {}", location, temp).as_bytes())
+                    .expect("Failed to take array address");
+                return temp;
+            }
+
+            // A store through a pointer only writes the width of the type
+            let narrow = dataType.size() < 8 && (glb.address_taken.contains(&expr.name) || is_extern);
+            if narrow || through_got {
+                let temp = new_temp_slot(glb);
+                fileptr
+                    .write_all(format!("{}\nmovq %rax, {}", load_from(&dataType, &location), temp).as_bytes())
+                    .expect("Failed to load variable");
+                return temp;
+            }
+            location
+        }
+
+        ExprType::CONSTANT => {
+            process_constant(fileptr, expr, glb, var_list);
+            glb.last_offset_used.clone()
+        }
+
+        // Read straight from .rodata
+        ExprType::FCONSTANT => glb.literals.double_operand(expr.fvalue),
+
+        _ => {
+            process_expression(fileptr, expr, glb, var_list, reg_list);
+            let location = new_temp_slot(glb);
+            fileptr
+                .write_all(format!("\nmovq %rax, {}", location).as_bytes())
+                .expect("Failed to spill intermediate result");
+            location
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO MEASURE THE SIZE OF A FUNCTION BODY AND TO CHECK IF IT
+  CALLS ANY OTHER FUNCTION
+************************************************************************
+*/
+fn expression_size(expr: &RNode) -> usize {
+    let mut size = 1;
+
+    if let Some(left) = expr.left.as_ref() {
+        size += expression_size(left);
+    }
+    if let Some(right) = expr.right.as_ref() {
+        size += expression_size(right);
+    }
+
+    let mut args = expr.arguments.as_ref();
+    while let Some(arg_list) = args {
+        if let Some(arg) = arg_list.node.as_ref() {
+            size += expression_size(arg);
+        }
+        args = arg_list.next.as_ref();
+    }
+
+    size
+}
+
+fn expression_has_call(expr: &RNode) -> bool {
+    if expr.exprCode == ExprType::OPERATION && expr.opCode == OpType::FUNCTIONCALL {
+        return true;
+    }
+
+    if let Some(left) = expr.left.as_ref() {
+        if expression_has_call(left) {
+            return true;
+        }
+    }
+    if let Some(right) = expr.right.as_ref() {
+        if expression_has_call(right) {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn statements_size(statements: &RList) -> usize {
+    let mut size = 0;
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            size += 1;
+            if let Some(right) = node.right.as_ref() {
+                size += expression_size(right);
+            }
+            if let Some(left) = node.left.as_ref() {
+                size += expression_size(left);
+            }
+            if let Some(body) = node.statements.as_ref() {
+                size += statements_size(body);
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+
+    size
+}
+
+fn statements_have_call(statements: &RList) -> bool {
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            if let Some(right) = node.right.as_ref() {
+                if expression_has_call(right) {
+                    return true;
+                }
+            }
+            if let Some(left) = node.left.as_ref() {
+                if expression_has_call(left) {
+                    return true;
+                }
+            }
+            if let Some(body) = node.statements.as_ref() {
+                if statements_have_call(body) {
+                    return true;
+                }
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+
+    false
+}
+
+fn list_length(list: Option<&RList>) -> usize {
+    let mut count = 0;
+    let mut current = list;
+
+    while let Some(item) = current {
+        if item.node.is_some() {
+            count += 1;
+        }
+        current = item.next.as_deref();
+    }
+
+    count
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO FIND THE VARIABLES WHOSE ADDRESS IS TAKEN, THEY ESCAPE
+  AND HAVE TO STAY IN MEMORY FOR AS LONG AS THEY ARE IN SCOPE
+************************************************************************
+*/
+fn expression_address_taken(expr: &RNode, names: &mut Vec<String>) {
+    if expr.exprCode == ExprType::OPERATION && expr.opCode == OpType::ADDRESS {
+        if let Some(operand) = expr.left.as_ref() {
+            let mut base = operand;
+            // &a[i] takes the address of a, &s.x the address of s
+            while base.opCode == OpType::INDEX || base.opCode == OpType::FIELD {
+                match base.left.as_ref() {
+                    Some(inner) => base = inner,
+                    None => break,
+                }
+            }
+            if base.exprCode == ExprType::VARIABLE && !names.contains(&base.name) {
+                names.push(base.name.clone());
+            }
+        }
+    }
+
+    if let Some(left) = expr.left.as_ref() {
+        expression_address_taken(left, names);
+    }
+    if let Some(right) = expr.right.as_ref() {
+        expression_address_taken(right, names);
+    }
+
+    let mut args = expr.arguments.as_deref();
+    while let Some(arg_list) = args {
+        if let Some(arg) = arg_list.node.as_ref() {
+            expression_address_taken(arg, names);
+        }
+        args = arg_list.next.as_deref();
+    }
+}
+
+fn collect_address_taken(statements: &RList, names: &mut Vec<String>) {
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            // A local array's address escapes whenever it is used as a value
+            if node.stmtCode == StmtType::DECLARE && node.dataType.is_array() && !names.contains(&node.name) {
+                names.push(node.name.clone());
+            }
+            if let Some(left) = node.left.as_ref() {
+                expression_address_taken(left, names);
+            }
+            if let Some(right) = node.right.as_ref() {
+                expression_address_taken(right, names);
+            }
+            if let Some(body) = node.statements.as_ref() {
+                collect_address_taken(body, names);
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO FIND THE DECLARATION OF A CALLEE THAT CAN BE INLINED.
+  ONLY LEAF FUNCTIONS (NO CALLS IN THEIR BODY, SO NEVER RECURSIVE) THAT
+  ARE WITHIN THE SIZE THRESHOLD AND MATCH THE CALL'S ARITY QUALIFY.
+************************************************************************
+*/
+fn find_inline_candidate<'a>(glb: &globals<'a>, name: &str, call: &RNode) -> Option<&'a RNode> {
+    // Which function a pointer holds is only known at run time
+    if glb.opts.inline_threshold == 0 || call.is_indirect {
+        return None;
+    }
+
+    // A prototype may come before the definition
+    for func in glb.functions.iter() {
+        if func.name != name || func.type_ != NodeType::FUNCTIONDECL {
+            continue;
+        }
+
+        let statements = func.statements.as_ref()?;
+        if statements_have_call(statements) {
+            return None;
+        }
+        // The caller's prologue doesn't save what the callee's asm changes
+        let mut saved: Vec<String> = vec![];
+        collect_asm_saved_registers(statements, &mut saved);
+        if !saved.is_empty() {
+            return None;
+        }
+        if statements_size(statements) > glb.opts.inline_threshold {
+            return None;
+        }
+        if list_length(func.arguments.as_deref()) != list_length(call.arguments.as_deref()) {
+            return None;
+        }
+        // Structs are passed and returned the way the ABI says, only by a real call
+        if func.dataType.is_struct() || param_types(glb, name).iter().any(|dataType| dataType.is_struct()) {
+            return None;
+        }
+
+        return Some(*func);
+    }
+
+    None
+}
+
+/*
+***********************************************************************
+  FUNCTION TO EXPAND A CALL IN PLACE. THE CALLEE'S PARAMETERS AND LOCALS
+  ARE KEPT IN THEIR OWN VARIABLE LIST SO THEIR NAMES ARE RENAMED AWAY
+  FROM THE CALLER'S. THE RETURNED VALUE IS LEFT IN RAX LIKE A REAL CALL.
+************************************************************************
+*/
+fn inline_call(
+    fileptr: &mut dyn Write,
+    callee: &RNode,
+    call: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    debug!("[DEBUG] Inlining call to {}", callee.name);
+    fileptr
+        .write_all(format!("\n# inlined call to {}", callee.name).as_bytes())
+        .expect("Unable to write data");
+
+    // The callee's parameters and locals are dead once its value is in rax
+    let temp_mark = glb.temp_slots.len();
+    let mut inline_vars = varStList::new();
+    declare_global_vars(glb, &mut inline_vars);
+
+    // Bind every parameter to a fresh slot holding the argument value
+    let mut params = callee.arguments.as_deref();
+    let mut args = call.arguments.as_deref();
+    while let (Some(param_list), Some(arg_list)) = (params, args) {
+        if let (Some(param), Some(arg)) = (param_list.node.as_ref(), arg_list.node.as_ref()) {
+            load_into_rax(fileptr, arg, glb, var_list, reg_list);
+            let arg_type = expression_type(arg);
+            emit_cast(fileptr, glb, &arg_type, &param.dataType);
+
+            let location = new_temp_slot(glb);
+            fileptr
+                .write_all(format!("\nmovq %rax, {}", location).as_bytes())
+                .expect("Failed to bind inlined parameter");
+            inline_vars.add_typed_var_info(param.name.clone(), location, param.dataType.clone());
+        }
+        params = param_list.next.as_deref();
+        args = arg_list.next.as_deref();
+    }
+
+    // Lower the body; the first return ends the inlined code
+    if let Some(body) = callee.statements.as_deref() {
+        glb.inlined.push(callee.name.clone());
+        inline_statements(fileptr, body, glb, &mut inline_vars, reg_list, &callee.dataType);
+        glb.inlined.pop();
+    }
+
+    release_temp_slots(glb, temp_mark, &mut inline_vars);
+
+    fileptr
+        .write_all(format!("\n# end of inlined call to {}", callee.name).as_bytes())
+        .expect("Unable to write data");
+}
+
+/*
+***********************************************************************
+  FUNCTION TO LOWER THE STATEMENTS OF AN INLINED BODY. RETURNS TRUE ONCE
+  A RETURN HAS LEFT THE VALUE IN RAX, WHICH ENDS THE INLINED CODE.
+************************************************************************
+*/
+fn inline_statements(
+    fileptr: &mut dyn Write,
+    statements: &RList,
+    glb: &mut globals,
+    inline_vars: &mut varStList,
+    reg_list: &mut regList,
+    callee_type: &DataType,
+) -> bool {
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            match node.stmtCode {
+                StmtType::ASSIGN if node.left.is_some() => {
+                    store_indirect(fileptr, node, glb, inline_vars, reg_list);
+                }
+
+                StmtType::DECLARE if node.dataType.is_aggregate() => {
+                    if let Some(right) = node.right.as_ref() {
+                        load_into_rax(fileptr, right, glb, inline_vars, reg_list);
+                    }
+
+                    // Every slot is a temporary of the inlined call
+                    let location = new_stack_array(glb, &node.dataType);
+                    let mut slot = -glb.frame_size;
+                    while slot < -glb.frame_size + frame_bytes(&node.dataType) {
+                        glb.temp_slots.push(slot);
+                        slot += 8;
+                    }
+                    inline_vars.add_typed_var_info(node.name.clone(), location.clone(), node.dataType.clone());
+
+                    if node.right.is_some() {
+                        store_variable(fileptr, &location, &node.dataType, false);
+                    }
+                }
+
+                StmtType::ASSIGN | StmtType::DECLARE => {
+                    let mut dataType = node.dataType.clone();
+                    if node.stmtCode == StmtType::ASSIGN {
+                        dataType = inline_vars.lookup_var_type(&node.name).unwrap_or(dataType);
+                    }
+                    if let Some(right) = node.right.as_ref() {
+                        load_into_rax(fileptr, right, glb, inline_vars, reg_list);
+                        let value_type = expression_type(right);
+                        emit_cast(fileptr, glb, &value_type, &dataType);
+                    }
+
+                    let mut location = "".to_string();
+                    if node.stmtCode == StmtType::ASSIGN {
+                        location = inline_vars.lookup_var_info(node.name.clone(), None);
+                    }
+                    if location.is_empty() {
+                        location = new_temp_slot(glb);
+                        inline_vars.add_typed_var_info(node.name.clone(), location.clone(), node.dataType.clone());
+                    }
+                    let is_extern = is_extern_variable(glb, &node.name, &location);
+                    let location = resolve_global(fileptr, location);
+                    store_variable(fileptr, &location, &dataType, is_extern);
+                }
+
+                StmtType::RETURN => {
+                    if let Some(val_left) = node.left.as_ref() {
+                        load_into_rax(fileptr, val_left, glb, inline_vars, reg_list);
+                        let value_type = expression_type(val_left);
+                        emit_cast(fileptr, glb, &value_type, callee_type);
+                    }
+                    return true;
+                }
+
+                StmtType::BLOCK => {
+                    // The slots are temporaries released with the whole inlined call
+                    if let Some(body) = node.statements.as_ref() {
+                        inline_vars.push_scope();
+                        let returned = inline_statements(fileptr, body, glb, inline_vars, reg_list, callee_type);
+                        inline_vars.pop_scope();
+                        if returned {
+                            return true;
+                        }
+                    }
+                }
+
+                StmtType::ASM => {
+                    process_asm(fileptr, node, glb, inline_vars, reg_list);
+                }
+
+                StmtType::S_NONE => {}
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+
+    false
+}
+
+/*
+***********************************************************************
+  FUNCTION TO DETERMINE IF A RETURNED EXPRESSION IS A CALL THAT CAN BE
+  LOWERED TO A JUMP. CALLS PASSING ARGUMENTS ON THE STACK ARE EXCLUDED
+  BECAUSE THE CALLER'S FRAME IS TORN DOWN BEFORE THE JUMP.
+************************************************************************
+*/
+fn is_tail_call(expr: &RNode, glb: &globals) -> bool {
+    if !glb.opts.tail_calls {
+        return false;
+    }
+    if expr.exprCode != ExprType::OPERATION || expr.opCode != OpType::FUNCTIONCALL || expr.is_indirect {
+        return false;
+    }
+    if list_length(expr.arguments.as_deref()) > 6 {
+        return false;
+    }
+    // The callee may be handed the address of something in our frame
+    if !glb.address_taken.is_empty() {
+        return false;
+    }
+    // A narrower result has to be extended before we return it, and a
+    // double comes back in another register than an integer
+    let callee_type = expr.left.as_ref().and_then(|left| return_type(glb, &left.name));
+    // Structs are passed in memory or spread over several registers
+    let callee_params = expr.left.as_ref().map_or(vec![], |left| param_types(glb, &left.name));
+    let own_type = return_type(glb, &glb.func_name);
+    if callee_params.iter().chain(callee_type.iter()).chain(own_type.iter()).any(|dataType| dataType.is_struct()) {
+        return false;
+    }
+    if let (Some(callee_type), Some(own_type)) = (callee_type, own_type) {
+        if callee_type != own_type && (needs_conversion(&DataType::LONG, &callee_type) || needs_cast(&callee_type, &own_type)) {
+            return false;
+        }
+    }
+
+    match expr.left.as_ref() {
+        // An inlined call is cheaper than a jump
+        Some(left) => find_inline_candidate(glb, &left.name, expr).is_none(),
+        None => false,
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO LOWER A CALL IN TAIL POSITION. A CALL TO THE FUNCTION
+  ITSELF JUMPS BACK TO THE START OF THE BODY, ANY OTHER CALL TEARS DOWN
+  THE FRAME AND JUMPS TO THE CALLEE, WHICH RETURNS TO OUR CALLER.
+************************************************************************
+*/
+fn emit_tail_call(
+    fileptr: &mut dyn Write,
+    call: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    let mut callee_name = String::new();
+    if let Some(left) = call.left.as_ref() {
+        callee_name = left.name.clone();
+    }
+
+    // Evaluate every argument before writing the argument registers, since
+    // they still hold the parameters the arguments may refer to.
+    let (param_types, is_variadic) = call_signature(glb, call);
+    let mut arg_types: Vec<DataType> = vec![];
+    let mut args = call.arguments.as_deref();
+    while let Some(arg_list) = args {
+        if let Some(arg) = arg_list.node.as_ref() {
+            load_into_rax(fileptr, arg, glb, var_list, reg_list);
+            let arg_type = expression_type(arg);
+            let passed_type = param_types.get(arg_types.len()).cloned().unwrap_or(arg_type.clone());
+            emit_cast(fileptr, glb, &arg_type, &passed_type);
+            fileptr
+                .write_all("\npushq %rax".as_bytes())
+                .expect("Failed to save tail call argument");
+            arg_types.push(passed_type);
+        }
+        args = arg_list.next.as_deref();
+    }
+
+    // Only calls whose arguments all fit in registers are tail calls
+    let arg_registers = assign_arg_registers(&arg_types);
+    for register in arg_registers.iter().rev().flatten().flat_map(|registers| registers.iter().rev()) {
+        let code = if register.starts_with("%xmm") {
+            format!("\npopq %rax\nmovq %rax, {}", register)
+        } else {
+            format!("\npopq {}", register)
+        };
+        fileptr
+            .write_all(code.as_bytes())
+            .expect("Failed to pass tail call argument in register");
+    }
+
+    if callee_name == glb.func_name {
+        fileptr
+            .write_all(format!("\njmp .L{}_body  # Self tail call", callee_name).as_bytes())
+            .expect("Failed to generate self tail call");
+    } else {
+        restore_registers(fileptr, glb);
+        fileptr
+            .write_all("\nmovq %rbp, %rsp".as_bytes())
+            .expect("Unable to write data");
+        fileptr
+            .write_all("\npopq %rbp".as_bytes())
+            .expect("Unable to write data");
+        let float_count = arg_types.iter().filter(|dataType| dataType.is_float()).count();
+        emit_vector_count(fileptr, is_variadic, float_count);
+        fileptr
+            .write_all(format!("\njmp {}  # Tail call", call_target(glb, &callee_name)).as_bytes())
+            .expect("Failed to generate tail call");
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO TELL A VARIADIC CALLEE HOW MANY VECTOR REGISTERS HOLD
+  ARGUMENTS, THE ABI PASSES THE COUNT IN %AL
+************************************************************************
+*/
+fn emit_vector_count(fileptr: &mut dyn Write, is_variadic: bool, count: usize) {
+    if is_variadic {
+        fileptr
+            .write_all(format!("\nmovl ${}, %eax  # Vector registers used by a variadic call", count).as_bytes())
+            .expect("Failed to set the vector register count");
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO DETERMINE IF A FUNCTION BODY STILL MAKES A REAL CALL ONCE
+  INLINING IS DONE. FUNCTIONS THAT DON'T ARE LEAF FUNCTIONS.
+************************************************************************
+*/
+fn expression_makes_call(expr: &RNode, glb: &globals) -> bool {
+    if expr.exprCode == ExprType::OPERATION && expr.opCode == OpType::FUNCTIONCALL {
+        let inlined = match expr.left.as_ref() {
+            Some(left) => find_inline_candidate(glb, &left.name, expr).is_some(),
+            None => false,
+        };
+        if !inlined {
+            return true;
+        }
+
+        let mut args = expr.arguments.as_deref();
+        while let Some(arg_list) = args {
+            if let Some(arg) = arg_list.node.as_ref() {
+                if expression_makes_call(arg, glb) {
+                    return true;
+                }
+            }
+            args = arg_list.next.as_deref();
+        }
+        return false;
+    }
+
+    if let Some(left) = expr.left.as_ref() {
+        if expression_makes_call(left, glb) {
+            return true;
+        }
+    }
+    if let Some(right) = expr.right.as_ref() {
+        if expression_makes_call(right, glb) {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn function_makes_call(statements: &RList, glb: &globals) -> bool {
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            if let Some(right) = node.right.as_ref() {
+                if expression_makes_call(right, glb) {
+                    return true;
+                }
+            }
+            if let Some(left) = node.left.as_ref() {
+                if expression_makes_call(left, glb) {
+                    return true;
+                }
+            }
+            if let Some(body) = node.statements.as_ref() {
+                if function_makes_call(body, glb) {
+                    return true;
+                }
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+
+    false
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR ASM STATEMENTS. EVERY INPUT IS COMPUTED INTO A TEMPORARY
+  FIRST AND THEN LOADED INTO ITS REGISTER, SINCE COMPUTING ONE MAY USE
+  THE REGISTER OF ANOTHER. THE OUTPUTS ARE SAVED THE SAME WAY BEFORE
+  THEY ARE STORED THROUGH RAX. THE CLOBBERED REGISTERS AND THOSE OF THE
+  OPERANDS ARE TAKEN OUT OF THE REGISTER LIST WHILE THE ASM IS LOWERED.
+************************************************************************
+*/
+const CALLEE_SAVED_REGISTERS: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+// The 64, 32, 16 and 8 bit names of every general purpose register
+const SIZED_REGISTERS: [[&str; 4]; 16] = [
+    ["%rax", "%eax", "%ax", "%al"],
+    ["%rbx", "%ebx", "%bx", "%bl"],
+    ["%rcx", "%ecx", "%cx", "%cl"],
+    ["%rdx", "%edx", "%dx", "%dl"],
+    ["%rsi", "%esi", "%si", "%sil"],
+    ["%rdi", "%edi", "%di", "%dil"],
+    ["%rbp", "%ebp", "%bp", "%bpl"],
+    ["%rsp", "%esp", "%sp", "%spl"],
+    ["%r8", "%r8d", "%r8w", "%r8b"],
+    ["%r9", "%r9d", "%r9w", "%r9b"],
+    ["%r10", "%r10d", "%r10w", "%r10b"],
+    ["%r11", "%r11d", "%r11w", "%r11b"],
+    ["%r12", "%r12d", "%r12w", "%r12b"],
+    ["%r13", "%r13d", "%r13w", "%r13b"],
+    ["%r14", "%r14d", "%r14w", "%r14b"],
+    ["%r15", "%r15d", "%r15w", "%r15b"],
+];
+
+fn sized_register(register: &str, size: i64) -> String {
+    let column = match size {
+        1 => 3,
+        2 => 2,
+        4 => 1,
+        _ => 0,
+    };
+    match SIZED_REGISTERS.iter().find(|names| names[0] == register) {
+        Some(names) => names[column].to_string(),
+        None => register.to_string(),
+    }
+}
+
+fn asm_operands(node: &RNode) -> Vec<&RNode> {
+    let mut operands: Vec<&RNode> = vec![];
+    let mut current = node.arguments.as_deref();
+    while let Some(item) = current {
+        if let Some(operand) = item.node.as_ref() {
+            operands.push(operand);
+        }
+        current = item.next.as_deref();
+    }
+    operands
+}
+
+// Callee saved registers an asm of the statements names or may hand to
+// an operand once the caller saved ones run out, the function has to
+// give them back to its caller unchanged
+fn collect_asm_saved_registers(statements: &RList, registers: &mut Vec<String>) {
+    let mut stmt = Some(statements);
+
+    while let Some(stmt_list) = stmt {
+        if let Some(node) = stmt_list.node.as_ref() {
+            if node.stmtCode == StmtType::ASM {
+                let operands = asm_operands(node);
+                let fixed = operands.iter().filter_map(|operand| asm_fixed_register(&operand.constraint));
+                let unbound = asm_unbound_registers(&node.clobbers, &operands);
+                let given = unbound.into_iter().take(asm_register_demand(&operands));
+                for register in node.clobbers.iter().map(|clobber| clobber.as_str()).chain(fixed).chain(given) {
+                    if CALLEE_SAVED_REGISTERS.contains(&register) && !registers.iter().any(|saved| saved == register) {
+                        registers.push(register.to_string());
+                    }
+                }
+            }
+            if let Some(body) = node.statements.as_ref() {
+                collect_asm_saved_registers(body, registers);
+            }
+        }
+        stmt = stmt_list.next.as_deref();
+    }
+}
+
+fn save_registers(fileptr: &mut dyn Write, glb: &globals) {
+    for (register, slot) in glb.saved_registers.iter() {
+        fileptr
+            .write_all(format!("\nmovq {}, {}  # Saved for the caller", register, slot).as_bytes())
+            .expect("Failed to save callee saved register");
+    }
+}
+
+fn restore_registers(fileptr: &mut dyn Write, glb: &globals) {
+    for (register, slot) in glb.saved_registers.iter() {
+        fileptr
+            .write_all(format!("\nmovq {}, {}", slot, register).as_bytes())
+            .expect("Failed to restore callee saved register");
+    }
+}
+
+// Takes a register out of the list for the rest of the asm, remembering
+// it so it can be handed back
+fn reserve_register(reg_list: &mut regList, register: &str, reserved: &mut Vec<String>) {
+    if reg_list.is_avail_reg(register.to_string()) {
+        reg_list.update_reg_info(register.to_string(), 0);
+        reserved.push(register.to_string());
+    }
+}
+
+// A caller saved register if one is left, otherwise the next of the
+// callee saved ones the prologue saved for this asm
+fn free_asm_register(node: &RNode, reg_list: &mut regList, reserved: &mut Vec<String>, fallback: &mut Vec<&str>) -> String {
+    let register = reg_list.get_next_avail_reg(false);
+    if register != "NoReg" {
+        reserve_register(reg_list, &register, reserved);
+        return register;
+    }
+    if fallback.is_empty() {
+        panic!("Error: not enough free registers for the operands of the asm at {}:{}!", node.line, node.col);
+    }
+    fallback.remove(0).to_string()
+}
+
+/*
+***********************************************************************
+  FUNCTION TO LOWER AN ASM STATEMENT
+************************************************************************
+*/
+fn process_asm(
+    fileptr: &mut dyn Write,
+    node: &RNode,
+    glb: &mut globals,
+    var_list: &mut varStList,
+    reg_list: &mut regList,
+) {
+    let operands = asm_operands(node);
+
+    let mut reserved: Vec<String> = vec![];
+    for clobber in node.clobbers.iter() {
+        reserve_register(reg_list, clobber, &mut reserved);
+    }
+    for operand in operands.iter() {
+        if let Some(register) = asm_fixed_register(&operand.constraint) {
+            reserve_register(reg_list, register, &mut reserved);
+        }
+    }
+
+    let mut fallback: Vec<&str> = asm_unbound_registers(&node.clobbers, &operands)
+        .into_iter()
+        .filter(|register| glb.saved_registers.iter().any(|(saved, _)| saved == register))
+        .collect();
+
+    // ✅ **Where each operand is while the template runs, a register or memory**
+    let mut places: Vec<String> = vec![];
+    let mut addresses: Vec<(String, String)> = vec![];
+    for operand in operands.iter() {
+        let location = var_list.lookup_var_info(operand.name.clone(), None);
+        if asm_is_memory(&operand.constraint) {
+            // A global reached through the GOT is addressed from a register
+            // holding the address the GOT gives
+            if location.ends_with("@GOTPCREL(%rip)") {
+                let register = free_asm_register(node, reg_list, &mut reserved, &mut fallback);
+                places.push(format!("({})", register));
+                addresses.push((location, register));
+            } else {
+                places.push(location);
+            }
+        } else if let Some(register) = asm_fixed_register(&operand.constraint) {
+            places.push(register.to_string());
+        } else {
+            places.push(free_asm_register(node, reg_list, &mut reserved, &mut fallback));
+        }
+    }
+
+    // ✅ **Compute the inputs, then load their registers**
+    let mut loads: Vec<(String, String)> = vec![];
+    for (operand, place) in operands.iter().zip(places.iter()) {
+        if asm_is_memory(&operand.constraint) || !asm_is_read(&operand.constraint) {
+            continue;
+        }
+        process_expression(fileptr, operand, glb, var_list, reg_list);
+        let slot = new_temp_slot(glb);
+        fileptr
+            .write_all(format!("\nmovq %rax, {}", slot).as_bytes())
+            .expect("Failed to save asm input");
+        loads.push((slot, place.clone()));
+    }
+    for (slot, register) in loads.iter() {
+        fileptr
+            .write_all(format!("\nmovq {}, {}", slot, register).as_bytes())
+            .expect("Failed to load asm input");
+    }
+    for (location, register) in addresses.iter() {
+        fileptr
+            .write_all(format!("\nmovq {}, {}", location, register).as_bytes())
+            .expect("Failed to load global address");
+    }
+
+    // ✅ **The template with the operands filled in, a register is named by its width**
+    let pieces = match asm_template(&node.name) {
+        Ok(pieces) => pieces,
+        Err(message) => panic!("Error: {}!", message),
+    };
+    let mut code = String::new();
+    for piece in pieces {
+        match piece {
+            asmPiece::Text(text) => code.push_str(&text),
+            asmPiece::Operand(index, modifier) => {
+                let size = match modifier {
+                    Some('b') => 1,
+                    Some('w') => 2,
+                    Some('k') => 4,
+                    Some('q') => 8,
+                    _ => operands[index].valueType.as_ref().map_or(8, |dataType| dataType.size()),
+                };
+                code.push_str(&sized_register(&places[index], size));
+            }
+            asmPiece::Unique => code.push_str(&glb.asm_count.get().to_string()),
+        }
+    }
+    glb.asm_count.set(glb.asm_count.get() + 1);
+    fileptr
+        .write_all(format!("\n# asm\n{}\n# end of asm", code).as_bytes())
+        .expect("Failed to write asm template");
+
+    // ✅ **Save the register outputs, then store each with its variable's width**
+    let mut stores: Vec<(String, &RNode)> = vec![];
+    for (operand, place) in operands.iter().zip(places.iter()) {
+        if !asm_is_output(&operand.constraint) || asm_is_memory(&operand.constraint) {
+            continue;
+        }
+        let slot = new_temp_slot(glb);
+        fileptr
+            .write_all(format!("\nmovq {}, {}", place, slot).as_bytes())
+            .expect("Failed to save asm output");
+        stores.push((slot, operand));
+    }
+    for (slot, operand) in stores.iter() {
+        fileptr
+            .write_all(format!("\nmovq {}, %rax", slot).as_bytes())
+            .expect("Failed to load asm output");
+        let dataType = var_list.lookup_var_type(&operand.name).unwrap_or(DataType::LONG);
+        emit_convert(fileptr, &dataType);
+
+        let location = var_list.lookup_var_info(operand.name.clone(), None);
+        let is_extern = is_extern_variable(glb, &operand.name, &location);
+        let location = resolve_global(fileptr, location);
+        store_variable(fileptr, &location, &dataType, is_extern);
+    }
+
+    // A narrow variable written in memory is extended over its whole slot again
+    for operand in operands.iter() {
+        if !asm_is_output(&operand.constraint) || !asm_is_memory(&operand.constraint) {
+            continue;
+        }
+        let dataType = var_list.lookup_var_type(&operand.name).unwrap_or(DataType::LONG);
+        let location = var_list.lookup_var_info(operand.name.clone(), None);
+        if dataType.is_aggregate() || !needs_conversion(&DataType::LONG, &dataType) || is_extern_variable(glb, &operand.name, &location) {
+            continue;
+        }
+        let location = resolve_global(fileptr, location);
+        fileptr
+            .write_all(format!("{}\nmovq %rax, {}", load_from(&dataType, &location), location).as_bytes())
+            .expect("Failed to extend asm output");
+    }
+
+    for register in reserved {
+        reg_list.update_reg_info(register, 1);
+    }
+}
 
 /*
 **********************************************************************************************************************************
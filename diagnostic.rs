@@ -0,0 +1,29 @@
+/*
+***********************************************************************
+  DIAGNOSTIC.RS : PROBLEMS FOUND IN THE SOURCE, WITH THEIR POSITION
+************************************************************************
+*/
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(line: usize, col: usize, message: String) -> Self {
+        Diagnostic {
+            line,
+            col,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: error: {}", self.line, self.col, self.message)
+    }
+}
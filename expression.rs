@@ -0,0 +1,937 @@
+/*
+***********************************************************************
+  EXPRESSION.RS : THE AST BUILT BY THE PARSER AND WALKED BY CODEGEN
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(non_snake_case)]
+#![allow(dead_code)]
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeType {
+    FUNCTIONDECL,
+    VARDECL,
+    STRUCTDECL,
+    EXTERNDECL,
+    IMPORTDECL,
+    STATEMENT,
+    EXPRESSION,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StmtType {
+    ASSIGN,
+    DECLARE,
+    RETURN,
+    BLOCK,
+    ASM,
+    S_NONE,
+}
+
+// Where a global lives, EXTERN for one declared 'extern' and defined in
+// another file, STATIC for one only this file sees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    EXTERN,
+    STATIC,
+    SC_NONE,
+}
+
+// Visibility of a global symbol outside the shared library or executable it
+// is linked into, V_NONE when the declaration doesn't say
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Visibility {
+    DEFAULT,
+    HIDDEN,
+    V_NONE,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExprType {
+    VARIABLE,
+    CONSTANT,
+    FCONSTANT,
+    STRING,
+    OPERATION,
+    E_NONE,
+}
+
+/*
+*************************************************************************************
+ TYPE OF A DECLARED VARIABLE. ARRAYS ARE ONLY INDEXED OR USED AS A POINTER TO
+ THEIR FIRST ELEMENT, THEY ARE NEVER COPIED AS A WHOLE. INTEGERS NARROWER THAN
+ A LONG HAVE THEIR OWN WIDTH IN AN ARRAY OR BEHIND A POINTER, AS A VARIABLE
+ THEY STILL TAKE A WHOLE SLOT, SIGN OR ZERO EXTENDED FROM THEIR WIDTH, EXCEPT
+ IN A VARIABLE DEFINED IN ANOTHER FILE WHICH ONLY HAS ITS WIDTH. A DOUBLE
+ IS HELD AS ITS 64 BIT PATTERN LIKE ANY OTHER VALUE AND ONLY MOVED TO AN SSE
+ REGISTER TO BE COMPUTED WITH. A STRUCT USED AS A VALUE STANDS FOR ITS ADDRESS,
+ IT IS COPIED AS A WHOLE WHEN IT IS ASSIGNED, PASSED OR RETURNED. A FUNCTION
+ TYPE ONLY APPEARS BEHIND A POINTER, A FUNCTION NAME USED AS A VALUE IS ITS
+ ADDRESS.
+*************************************************************************************
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataType {
+    CHAR,
+    SHORT,
+    INT,
+    LONG,
+    UCHAR,
+    USHORT,
+    UINT,
+    ULONG,
+    DOUBLE,
+    // type pointed to
+    POINTER(Box<DataType>),
+    // element type and number of elements
+    ARRAY(Box<DataType>, i64),
+    // layout shared by every use of the struct
+    STRUCT(Rc<structLayout>),
+    // return type, parameter types and whether more arguments can follow
+    FUNCTION(Box<DataType>, Vec<DataType>, bool),
+}
+
+/*
+*************************************************************************************
+ LAYOUT OF A STRUCT. A STRUCT CAN HOLD A POINTER TO ITSELF, SO THE LAYOUT IS
+ CREATED WHEN ITS NAME IS FIRST SEEN AND FILLED IN ONCE ITS BODY IS PARSED.
+ TWO STRUCT TYPES ARE THE SAME IF THEY HAVE THE SAME NAME.
+*************************************************************************************
+*/
+pub struct structLayout {
+    pub name: String,
+    pub fields: RefCell<Vec<structField>>,
+    pub size: Cell<i64>,
+    pub align: Cell<i64>,
+    pub complete: Cell<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct structField {
+    pub name: String,
+    pub dataType: DataType,
+    // bytes from the start of the struct
+    pub offset: i64,
+}
+
+impl structLayout {
+    pub fn new(name: &str) -> Self {
+        structLayout {
+            name: name.to_string(),
+            fields: RefCell::new(vec![]),
+            size: Cell::new(0),
+            align: Cell::new(1),
+            complete: Cell::new(false),
+        }
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO PLACE THE FIELDS IN ORDER, EACH ONE AT THE NEXT OFFSET
+      ALIGNED FOR ITS TYPE. THE SIZE IS ROUNDED UP TO THE ALIGNMENT OF THE
+      STRICTEST FIELD SO EVERY ELEMENT OF AN ARRAY IS ALIGNED TOO.
+    ************************************************************************
+    */
+    pub fn complete(&self, fields: Vec<(String, DataType)>) {
+        let mut placed: Vec<structField> = vec![];
+        let mut offset = 0;
+        let mut align = 1;
+
+        for (name, dataType) in fields {
+            let field_align = dataType.align();
+            offset = (offset + field_align - 1) / field_align * field_align;
+            placed.push(structField { name, dataType: dataType.clone(), offset });
+            offset += dataType.size();
+            align = align.max(field_align);
+        }
+
+        *self.fields.borrow_mut() = placed;
+        self.size.set((offset + align - 1) / align * align);
+        self.align.set(align);
+        self.complete.set(true);
+    }
+}
+
+impl PartialEq for structLayout {
+    fn eq(&self, other: &structLayout) -> bool {
+        self.name == other.name
+    }
+}
+
+// Only the name, a struct holding a pointer to itself would never end
+impl fmt::Debug for structLayout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "struct {}", self.name)
+    }
+}
+
+impl DataType {
+    // Size in bytes
+    pub fn size(&self) -> i64 {
+        match self {
+            DataType::CHAR | DataType::UCHAR => 1,
+            DataType::SHORT | DataType::USHORT => 2,
+            DataType::INT | DataType::UINT => 4,
+            DataType::LONG | DataType::ULONG => 8,
+            DataType::DOUBLE => 8,
+            DataType::POINTER(_) => 8,
+            DataType::ARRAY(element, count) => element.size() * count,
+            DataType::STRUCT(layout) => layout.size.get(),
+            // Not an object, there is no arithmetic on function pointers
+            DataType::FUNCTION(_, _, _) => 1,
+        }
+    }
+
+    // Alignment in bytes, scalars are aligned to their size
+    pub fn align(&self) -> i64 {
+        match self {
+            DataType::ARRAY(element, _) => element.align(),
+            DataType::STRUCT(layout) => layout.align.get(),
+            other => other.size(),
+        }
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, DataType::ARRAY(_, _))
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, DataType::POINTER(_))
+    }
+
+    pub fn is_struct(&self) -> bool {
+        matches!(self, DataType::STRUCT(_))
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self, DataType::FUNCTION(_, _, _))
+    }
+
+    pub fn is_function_pointer(&self) -> bool {
+        matches!(self, DataType::POINTER(target) if target.is_function())
+    }
+
+    // Arrays and structs live in memory and are used through their address
+    pub fn is_aggregate(&self) -> bool {
+        self.is_array() || self.is_struct()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        !self.is_aggregate() && !self.is_pointer() && !self.is_float() && !self.is_function()
+    }
+
+    pub fn is_float(&self) -> bool {
+        *self == DataType::DOUBLE
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(self, DataType::UCHAR | DataType::USHORT | DataType::UINT | DataType::ULONG)
+    }
+
+    // The type reached through a pointer or by indexing, None for scalars
+    pub fn element(&self) -> Option<&DataType> {
+        match self {
+            DataType::POINTER(element) | DataType::ARRAY(element, _) => Some(element),
+            _ => None,
+        }
+    }
+
+    // The named field of a struct, None for other types or an unknown name
+    pub fn field(&self, name: &str) -> Option<structField> {
+        match self {
+            DataType::STRUCT(layout) => layout.fields.borrow().iter().find(|field| field.name == name).cloned(),
+            _ => None,
+        }
+    }
+
+    // A struct whose body hasn't been seen, or an array of them, has no size yet
+    pub fn is_complete(&self) -> bool {
+        match self {
+            DataType::ARRAY(element, _) => element.is_complete(),
+            DataType::STRUCT(layout) => layout.complete.get(),
+            _ => true,
+        }
+    }
+
+    // An array used as a value is a pointer to its first element
+    pub fn decay(&self) -> DataType {
+        match self {
+            DataType::ARRAY(element, _) => DataType::POINTER(element.clone()),
+            other => other.clone(),
+        }
+    }
+
+    // Integers narrower than an int are computed with as ints
+    pub fn promote(&self) -> DataType {
+        match self {
+            DataType::CHAR | DataType::SHORT | DataType::UCHAR | DataType::USHORT => DataType::INT,
+            other => other.clone(),
+        }
+    }
+
+    // Type both operands of a binary operation are converted to: a double if
+    // either is one, else the wider integer or the unsigned one of the same
+    // width. A signed type wins only if it is wider, since it then holds
+    // every value of the other.
+    pub fn common(&self, other: &DataType) -> DataType {
+        let (left, right) = (self.promote(), other.promote());
+        if left.is_float() || right.is_float() {
+            return DataType::DOUBLE;
+        }
+        if left == right {
+            return left;
+        }
+        if left.is_unsigned() == right.is_unsigned() {
+            return if left.size() >= right.size() { left } else { right };
+        }
+
+        let (unsigned, signed) = if left.is_unsigned() { (left, right) } else { (right, left) };
+        if unsigned.size() >= signed.size() {
+            unsigned
+        } else {
+            signed
+        }
+    }
+
+    // The value a constant takes once it is stored in this type, as held in a
+    // 64 bit register
+    pub fn wrap(&self, value: i64) -> i64 {
+        match self {
+            DataType::CHAR => value as i8 as i64,
+            DataType::SHORT => value as i16 as i64,
+            DataType::INT => value as i32 as i64,
+            DataType::UCHAR => value as u8 as i64,
+            DataType::USHORT => value as u16 as i64,
+            DataType::UINT => value as u32 as i64,
+            _ => value,
+        }
+    }
+
+    pub fn type_name(&self) -> String {
+        match self {
+            DataType::CHAR => "char".to_string(),
+            DataType::SHORT => "short".to_string(),
+            DataType::INT => "int".to_string(),
+            DataType::LONG => "long".to_string(),
+            DataType::UCHAR => "unsigned char".to_string(),
+            DataType::USHORT => "unsigned short".to_string(),
+            DataType::UINT => "unsigned int".to_string(),
+            DataType::ULONG => "unsigned long".to_string(),
+            DataType::DOUBLE => "double".to_string(),
+            DataType::POINTER(target) if target.is_function() => target.function_name("(*)"),
+            DataType::POINTER(element) => format!("{}*", element.type_name()),
+            DataType::ARRAY(element, count) => format!("{}[{}]", element.type_name(), count),
+            DataType::STRUCT(layout) => format!("struct {}", layout.name),
+            DataType::FUNCTION(_, _, _) => self.function_name(""),
+        }
+    }
+
+    // 'long (*)(long, char*)' with "(*)" as the declarator
+    fn function_name(&self, declarator: &str) -> String {
+        match self {
+            DataType::FUNCTION(returnType, params, is_variadic) => {
+                let mut names: Vec<String> = params.iter().map(|param| param.type_name()).collect();
+                if *is_variadic {
+                    names.push("...".to_string());
+                }
+                format!("{} {}({})", returnType.type_name(), declarator, names.join(", "))
+            }
+            other => other.type_name(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpType {
+    MULTIPLY,
+    DIVIDE,
+    MOD,
+    ADD,
+    SUBTRACT,
+    BOR,
+    BAND,
+    BXOR,
+    BSHR,
+    BSHL,
+    NEGATE,
+    BNOT,
+    LNOT,
+    INDEX,
+    ADDRESS,
+    DEREF,
+    FIELD,
+    FUNCTIONCALL,
+    O_NONE,
+}
+
+/*
+*************************************************************************************
+ A NODE IS A FUNCTION DECLARATION, A STATEMENT OR AN EXPRESSION:
+   FUNCTIONDECL : name, dataType (the return type), arguments (the parameters),
+                  statements (the body), storage (STATIC for a function other
+                  files can't call) and visibility
+   VARDECL      : a global variable, name, dataType, right (the initializer,
+                  if any), storage (EXTERN for a variable defined in another
+                  file, never with an initializer, STATIC for one other files
+                  can't see) and visibility
+   STRUCTDECL   : a struct definition, name and dataType (the struct with its
+                  fields)
+   EXTERNDECL   : a function prototype, name, dataType, arguments (the
+                  parameters, their names may be empty), is_variadic and
+                  visibility. The function is defined in another file or later
+                  in this one
+   IMPORTDECL   : name (the path of the imported file, relative to the
+                  importing one). The driver replaces it with the extern
+                  declarations of what that file defines
+   ASSIGN       : name (the assigned variable) and right (the value). A store
+                  into an array element, through a pointer or into a field has
+                  the INDEX, DEREF or FIELD expression in left instead of a name
+   DECLARE      : like ASSIGN but always creates the variable in the current
+                  scope, shadowing any outer variable with the same name. Arrays
+                  are declared without a value, structs may be
+   RETURN       : left (the returned value)
+   BLOCK        : statements (the body of a nested scope)
+   ASM          : name (the template), arguments (the operands, VARIABLE nodes
+                  with their constraint, the outputs first) and clobbers (the
+                  registers the template changes, "memory" and "cc")
+   VARIABLE     : name, a function name is the function's address
+   CONSTANT     : value, dataType is ULONG for a constant too large for a
+                  long (value holds its bits)
+   FCONSTANT    : fvalue
+   STRING       : name (the contents, escapes already decoded)
+   OPERATION    : opCode with left and right, NEGATE, BNOT, LNOT, ADDRESS and
+                  DEREF only use left, FUNCTIONCALL has the callee (a function
+                  or a variable holding a function pointer) in left and the
+                  arguments, INDEX has the array or pointer variable in left
+                  and the index in right, FIELD has the struct in left and the
+                  field name in name ('p->x' is parsed as a FIELD of DEREF p)
+*************************************************************************************
+*/
+#[derive(Debug, Clone)]
+pub struct RNode {
+    pub type_: NodeType,
+    pub stmtCode: StmtType,
+    pub exprCode: ExprType,
+    pub opCode: OpType,
+    pub name: String,
+    pub value: i64,
+    // value of an FCONSTANT
+    pub fvalue: f64,
+    // type of the variable made by a DECLARE or VARDECL, of a parameter or
+    // returned by a function
+    pub dataType: DataType,
+    // type of the value of an expression, worked out by the type checker,
+    // codegen relies on it
+    pub valueType: Option<DataType>,
+    // an EXTERNDECL taking more arguments after its parameters ('...')
+    pub is_variadic: bool,
+    // a FUNCTIONCALL through a function pointer variable, set by the type checker
+    pub is_indirect: bool,
+    // storage class and visibility attribute of a FUNCTIONDECL, VARDECL or
+    // EXTERNDECL
+    pub storage: StorageClass,
+    pub visibility: Visibility,
+    // constraint of an asm operand, like "=r" or "m"
+    pub constraint: String,
+    // registers an ASM changes besides its outputs, as "%rax"
+    pub clobbers: Vec<String>,
+    pub left: Option<Box<RNode>>,
+    pub right: Option<Box<RNode>>,
+    pub arguments: Option<Box<RList>>,
+    pub statements: Option<Box<RList>>,
+    // Position of the node in the source file (1 based), 0 for nodes built by hand
+    pub line: usize,
+    pub col: usize,
+}
+
+impl RNode {
+    pub fn new(type_: NodeType, line: usize, col: usize) -> Self {
+        RNode {
+            type_,
+            stmtCode: StmtType::S_NONE,
+            exprCode: ExprType::E_NONE,
+            opCode: OpType::O_NONE,
+            name: "".to_string(),
+            value: 0,
+            fvalue: 0.0,
+            dataType: DataType::LONG,
+            valueType: None,
+            is_variadic: false,
+            is_indirect: false,
+            storage: StorageClass::SC_NONE,
+            visibility: Visibility::V_NONE,
+            constraint: "".to_string(),
+            clobbers: vec![],
+            left: None,
+            right: None,
+            arguments: None,
+            statements: None,
+            line,
+            col,
+        }
+    }
+}
+
+impl Default for RNode {
+    fn default() -> RNode {
+        RNode::new(NodeType::EXPRESSION, 0, 0)
+    }
+}
+
+/*
+*************************************************************************************
+ LINKED LIST OF NODES, USED FOR THE WORKLIST OF FUNCTIONS, PARAMETERS, ARGUMENTS
+ AND STATEMENTS. AN EMPTY LIST IS A SINGLE ELEMENT WITHOUT A NODE.
+*************************************************************************************
+*/
+#[derive(Debug, Clone)]
+pub struct RList {
+    pub node: Option<Box<RNode>>,
+    pub next: Option<Box<RList>>,
+}
+
+impl RList {
+    pub fn new() -> Self {
+        RList {
+            node: None,
+            next: None,
+        }
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO BUILD A LIST HOLDING THE GIVEN NODES IN ORDER
+    ************************************************************************
+    */
+    pub fn from_nodes(nodes: Vec<RNode>) -> Self {
+        let mut list = RList::new();
+
+        for node in nodes.into_iter().rev() {
+            if list.node.is_none() {
+                list.node = Some(Box::new(node));
+            } else {
+                list = RList {
+                    node: Some(Box::new(node)),
+                    next: Some(Box::new(list)),
+                };
+            }
+        }
+
+        list
+    }
+
+    // The nodes of the list in order, the opposite of from_nodes
+    pub fn into_nodes(self) -> Vec<RNode> {
+        let mut nodes: Vec<RNode> = vec![];
+        let mut current = Some(self);
+
+        while let Some(item) = current {
+            if let Some(node) = item.node {
+                nodes.push(*node);
+            }
+            current = item.next.map(|next| *next);
+        }
+
+        nodes
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO COMPUTE THE VALUE OF AN EXPRESSION MADE ONLY OF CONSTANTS,
+  NONE IF IT READS A VARIABLE, CALLS A FUNCTION OR CAN'T BE COMPUTED
+************************************************************************
+*/
+pub fn fold_constant(expr: &RNode) -> Option<i64> {
+    fold_integer(expr).ok().map(|(value, _)| value)
+}
+
+// Why an integer expression has no value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum foldError {
+    // It reads a variable, calls a function or uses an operator that isn't folded
+    NotConstant,
+    DivisionByZero,
+    // The result doesn't fit a long, or a shift is by 64 bits or more
+    Overflow,
+}
+
+// The value and whether it is an unsigned long, which is divided, taken
+// the remainder of and shifted right as unsigned and wraps around. An
+// operation is unsigned when one of its operands is, a shift when its
+// left operand is.
+pub fn fold_integer(expr: &RNode) -> Result<(i64, bool), foldError> {
+    match expr.exprCode {
+        ExprType::CONSTANT => Ok((expr.value, expr.dataType == DataType::ULONG)),
+        ExprType::OPERATION => {
+            let (left, left_unsigned) = fold_integer(expr.left.as_ref().ok_or(foldError::NotConstant)?)?;
+            match expr.opCode {
+                OpType::NEGATE if left_unsigned => return Ok((left.wrapping_neg(), true)),
+                OpType::NEGATE => return left.checked_neg().map(|value| (value, false)).ok_or(foldError::Overflow),
+                OpType::BNOT => return Ok((!left, left_unsigned)),
+                OpType::LNOT => return Ok(((left == 0) as i64, false)),
+                _ => {}
+            }
+            let (right, right_unsigned) = fold_integer(expr.right.as_ref().ok_or(foldError::NotConstant)?)?;
+            if matches!(expr.opCode, OpType::DIVIDE | OpType::MOD) && right == 0 {
+                return Err(foldError::DivisionByZero);
+            }
+
+            if matches!(expr.opCode, OpType::BSHR | OpType::BSHL) {
+                let value = match expr.opCode {
+                    OpType::BSHR if left_unsigned => (left as u64).checked_shr(right as u32).map(|value| value as i64),
+                    OpType::BSHR => left.checked_shr(right as u32),
+                    _ => left.checked_shl(right as u32),
+                };
+                return value.map(|value| (value, left_unsigned)).ok_or(foldError::Overflow);
+            }
+
+            if left_unsigned || right_unsigned {
+                let (left, right) = (left as u64, right as u64);
+                let value = match expr.opCode {
+                    OpType::MULTIPLY => left.wrapping_mul(right),
+                    OpType::DIVIDE => left / right,
+                    OpType::MOD => left % right,
+                    OpType::ADD => left.wrapping_add(right),
+                    OpType::SUBTRACT => left.wrapping_sub(right),
+                    OpType::BOR => left | right,
+                    OpType::BAND => left & right,
+                    OpType::BXOR => left ^ right,
+                    _ => return Err(foldError::NotConstant),
+                };
+                return Ok((value as i64, true));
+            }
+
+            let value = match expr.opCode {
+                OpType::MULTIPLY => left.checked_mul(right),
+                OpType::DIVIDE => left.checked_div(right),
+                OpType::MOD => left.checked_rem(right),
+                OpType::ADD => left.checked_add(right),
+                OpType::SUBTRACT => left.checked_sub(right),
+                OpType::BOR => Some(left | right),
+                OpType::BAND => Some(left & right),
+                OpType::BXOR => Some(left ^ right),
+                _ => return Err(foldError::NotConstant),
+            };
+            value.map(|value| (value, false)).ok_or(foldError::Overflow)
+        }
+        _ => Err(foldError::NotConstant),
+    }
+}
+
+// Whether an initializer has a double constant, without one it is folded
+// as an integer
+pub fn has_double_constant(expr: &RNode) -> bool {
+    expr.exprCode == ExprType::FCONSTANT
+        || expr.left.as_deref().is_some_and(has_double_constant)
+        || expr.right.as_deref().is_some_and(has_double_constant)
+}
+
+// Same for an initializer of a double, integer parts are folded as
+// integers and converted
+pub fn fold_double(expr: &RNode) -> Option<f64> {
+    if !has_double_constant(expr) {
+        return match fold_integer(expr) {
+            Ok((value, true)) => Some(value as u64 as f64),
+            Ok((value, false)) => Some(value as f64),
+            Err(_) => None,
+        };
+    }
+    match expr.exprCode {
+        ExprType::FCONSTANT => Some(expr.fvalue),
+        ExprType::OPERATION => {
+            let left = fold_double(expr.left.as_ref()?)?;
+            if expr.opCode == OpType::NEGATE {
+                return Some(-left);
+            }
+            let right = fold_double(expr.right.as_ref()?)?;
+
+            match expr.opCode {
+                OpType::MULTIPLY => Some(left * right),
+                OpType::DIVIDE => Some(left / right),
+                OpType::ADD => Some(left + right),
+                OpType::SUBTRACT => Some(left - right),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// The type of a FUNCTIONDECL or EXTERNDECL, to compare declarations of the same function
+pub fn function_type(function: &RNode) -> DataType {
+    let mut params: Vec<DataType> = vec![];
+    let mut current = function.arguments.as_deref();
+    while let Some(item) = current {
+        if let Some(param) = item.node.as_ref() {
+            params.push(param.dataType.clone());
+        }
+        current = item.next.as_deref();
+    }
+    DataType::FUNCTION(Box::new(function.dataType.clone()), params, function.is_variadic)
+}
+
+// Variables visible at a point of a function and their types, one list per
+// open scope, the innermost last
+pub type scopeList = Vec<Vec<(String, DataType)>>;
+
+// The type of the innermost variable of that name
+pub fn lookup_visible<'s>(scopes: &'s scopeList, name: &str) -> Option<&'s DataType> {
+    for scope in scopes.iter().rev() {
+        if let Some((_, dataType)) = scope.iter().find(|(declared, _)| declared == name) {
+            return Some(dataType);
+        }
+    }
+    None
+}
+
+// The name an initializer takes the address of, f or &f, which the linker
+// fills in. Only the address of a function is a constant.
+pub fn address_constant(expr: &RNode) -> Option<&str> {
+    match expr.exprCode {
+        ExprType::VARIABLE => Some(&expr.name),
+        ExprType::OPERATION if expr.opCode == OpType::ADDRESS => {
+            expr.left.as_ref().filter(|left| left.exprCode == ExprType::VARIABLE).map(|left| left.name.as_str())
+        }
+        _ => None,
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTIONS FOR ASM STATEMENTS. AN OPERAND'S CONSTRAINT IS '=' FOR AN
+  OUTPUT, '+' FOR ONE THAT IS ALSO READ, OR NOTHING FOR AN INPUT, THEN
+  'r' FOR ANY FREE REGISTER, 'm' FOR THE VARIABLE'S MEMORY OR ONE OF
+  a, b, c, d, S AND D FOR RAX, RBX, RCX, RDX, RSI AND RDI. AN OUTPUT MAY
+  BE MARKED '&' (EARLY CLOBBER), NO TWO OPERANDS SHARE A FREE REGISTER
+  ANYWAY.
+************************************************************************
+*/
+pub const ASM_REGISTERS: [&str; 16] = [
+    "%rax", "%rbx", "%rcx", "%rdx", "%rsi", "%rdi", "%rbp", "%rsp", "%r8", "%r9", "%r10", "%r11", "%r12", "%r13", "%r14", "%r15",
+];
+
+// A piece of an asm template, the text between operand references is
+// copied as it is
+#[derive(Debug, Clone, PartialEq)]
+pub enum asmPiece {
+    Text(String),
+    // %N, or %bN, %wN, %kN and %qN for the 8, 16, 32 or 64 bit register
+    Operand(usize, Option<char>),
+    // %=, a number no other asm statement of the file gets, for labels
+    Unique,
+}
+
+pub fn asm_is_output(constraint: &str) -> bool {
+    constraint.starts_with('=') || constraint.starts_with('+')
+}
+
+// Inputs and '+' outputs have their value in place when the template runs
+pub fn asm_is_read(constraint: &str) -> bool {
+    !constraint.starts_with('=')
+}
+
+pub fn asm_is_memory(constraint: &str) -> bool {
+    constraint.ends_with('m')
+}
+
+// The register a constraint names, none for 'r' and 'm'
+pub fn asm_fixed_register(constraint: &str) -> Option<&'static str> {
+    match constraint.chars().last() {
+        Some('a') => Some("%rax"),
+        Some('b') => Some("%rbx"),
+        Some('c') => Some("%rcx"),
+        Some('d') => Some("%rdx"),
+        Some('S') => Some("%rsi"),
+        Some('D') => Some("%rdi"),
+        _ => None,
+    }
+}
+
+// The register a clobber names, written with or without the '%'
+pub fn asm_register(name: &str) -> Option<&'static str> {
+    let name = name.strip_prefix('%').unwrap_or(name);
+    ASM_REGISTERS.iter().copied().find(|register| register[1..] == *name)
+}
+
+// The registers an 'r' operand can be given, the caller saved ones first,
+// then those the prologue has to save for the caller
+pub const ASM_FREE_REGISTERS: [&str; 14] = [
+    "%rax", "%rcx", "%rdx", "%rsi", "%rdi", "%r8", "%r9", "%r10", "%r11", "%rbx", "%r12", "%r13", "%r14", "%r15",
+];
+
+// The free registers an asm leaves for its operands, in the order they
+// are handed out
+pub fn asm_unbound_registers(clobbers: &[String], operands: &[&RNode]) -> Vec<&'static str> {
+    ASM_FREE_REGISTERS
+        .iter()
+        .copied()
+        .filter(|register| !clobbers.iter().any(|clobber| clobber == register))
+        .filter(|register| !operands.iter().any(|operand| asm_fixed_register(&operand.constraint) == Some(*register)))
+        .collect()
+}
+
+// How many of them the asm may take, one per 'r' operand and one per 'm'
+// operand, in case it is a global reached through the GOT
+pub fn asm_register_demand(operands: &[&RNode]) -> usize {
+    operands.iter().filter(|operand| asm_fixed_register(&operand.constraint).is_none()).count()
+}
+
+/*
+***********************************************************************
+  FUNCTION TO SPLIT AN ASM TEMPLATE INTO TEXT AND OPERAND REFERENCES.
+  '%%' IS A '%', SO REGISTERS ARE WRITTEN %%rax.
+************************************************************************
+*/
+pub fn asm_template(template: &str) -> Result<Vec<asmPiece>, String> {
+    let mut pieces: Vec<asmPiece> = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+
+        let modifier = match chars.peek() {
+            Some('%') => {
+                chars.next();
+                text.push('%');
+                continue;
+            }
+            Some('=') => {
+                chars.next();
+                pieces.push(asmPiece::Text(std::mem::take(&mut text)));
+                pieces.push(asmPiece::Unique);
+                continue;
+            }
+            Some(&modifier) if "bwkq".contains(modifier) => {
+                chars.next();
+                Some(modifier)
+            }
+            _ => None,
+        };
+
+        let mut digits = String::new();
+        while let Some(digit) = chars.peek().filter(|digit| digit.is_ascii_digit()) {
+            digits.push(*digit);
+            chars.next();
+        }
+        match digits.parse::<usize>() {
+            Ok(index) => {
+                pieces.push(asmPiece::Text(std::mem::take(&mut text)));
+                pieces.push(asmPiece::Operand(index, modifier));
+            }
+            Err(_) => {
+                let written: String = ['%'].into_iter().chain(modifier).chain(chars.peek().copied()).collect();
+                return Err(format!("{} in an asm template is not an operand, write %% for a '%'", written));
+            }
+        }
+    }
+    pieces.push(asmPiece::Text(text));
+
+    Ok(pieces)
+}
+
+/*
+***********************************************************************
+  FUNCTIONS TO PRINT A LIST OF NODES AS AN INDENTED TREE (--emit=ast)
+************************************************************************
+*/
+pub fn dump_ast(list: &RList) -> String {
+    let mut out = String::new();
+    dump_list(list, 0, &mut out);
+    out
+}
+
+fn dump_list(list: &RList, depth: usize, out: &mut String) {
+    let mut current = Some(list);
+
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            dump_node(node, depth, out);
+        }
+        current = item.next.as_deref();
+    }
+}
+
+// 'static hidden ' for the storage class and visibility written in a declaration,
+// an EXTERNDECL is always extern
+fn specifiers(node: &RNode) -> String {
+    let storage = match node.storage {
+        StorageClass::EXTERN if node.type_ != NodeType::EXTERNDECL => "extern ",
+        StorageClass::STATIC => "static ",
+        _ => "",
+    };
+    let visibility = match node.visibility {
+        Visibility::DEFAULT => "default ",
+        Visibility::HIDDEN => "hidden ",
+        Visibility::V_NONE => "",
+    };
+    format!("{}{}", storage, visibility)
+}
+
+fn dump_node(node: &RNode, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+
+    let label = match node.type_ {
+        NodeType::FUNCTIONDECL => format!("FUNCTIONDECL {}{} {}", specifiers(node), node.dataType.type_name(), node.name),
+        NodeType::VARDECL => format!("VARDECL {}{} {}", specifiers(node), node.dataType.type_name(), node.name),
+        NodeType::STRUCTDECL => {
+            let fields: Vec<String> = match &node.dataType {
+                DataType::STRUCT(layout) => layout
+                    .fields
+                    .borrow()
+                    .iter()
+                    .map(|field| format!("{} {}+{}", field.dataType.type_name(), field.name, field.offset))
+                    .collect(),
+                _ => vec![],
+            };
+            format!("STRUCTDECL {} {{{}}} size {}", node.name, fields.join(", "), node.dataType.size())
+        }
+        NodeType::EXTERNDECL => {
+            let variadic = if node.is_variadic { " ..." } else { "" };
+            format!("EXTERNDECL {}{} {}{}", specifiers(node), node.dataType.type_name(), node.name, variadic)
+        }
+        NodeType::IMPORTDECL => format!("IMPORTDECL {:?}", node.name),
+        NodeType::STATEMENT => match node.stmtCode {
+            StmtType::ASSIGN => format!("ASSIGN {}", node.name),
+            StmtType::DECLARE => format!("DECLARE {} {}", node.dataType.type_name(), node.name),
+            StmtType::ASM => format!("ASM {:?} clobbers [{}]", node.name, node.clobbers.join(", ")),
+            other => format!("{:?}", other),
+        },
+        NodeType::EXPRESSION => match node.exprCode {
+            ExprType::VARIABLE if !node.constraint.is_empty() => format!("VARIABLE {} {:?}", node.name, node.constraint),
+            ExprType::VARIABLE => format!("VARIABLE {}", node.name),
+            ExprType::CONSTANT if node.dataType == DataType::ULONG => format!("CONSTANT {}", node.value as u64),
+            ExprType::CONSTANT => format!("CONSTANT {}", node.value),
+            ExprType::FCONSTANT => format!("FCONSTANT {:?}", node.fvalue),
+            ExprType::STRING => format!("STRING {:?}", node.name),
+            ExprType::OPERATION if node.opCode == OpType::FIELD => format!("OPERATION FIELD {}", node.name),
+            ExprType::OPERATION => format!("OPERATION {:?}", node.opCode),
+            other => format!("{:?}", other),
+        },
+    };
+    let value_type = node.valueType.as_ref().map_or(String::new(), |dataType| format!(" : {}", dataType.type_name()));
+    out.push_str(&format!("{}{}{} @{}:{}\n", indent, label, value_type, node.line, node.col));
+
+    if let Some(left) = node.left.as_ref() {
+        dump_node(left, depth + 1, out);
+    }
+    if let Some(right) = node.right.as_ref() {
+        dump_node(right, depth + 1, out);
+    }
+    if let Some(arguments) = node.arguments.as_ref() {
+        out.push_str(&format!("{}  arguments:\n", indent));
+        dump_list(arguments, depth + 2, out);
+    }
+    if let Some(statements) = node.statements.as_ref() {
+        out.push_str(&format!("{}  body:\n", indent));
+        dump_list(statements, depth + 2, out);
+    }
+}
@@ -0,0 +1,502 @@
+/*
+***********************************************************************
+  LEXER.RS : SPLIT THE SOURCE TEXT INTO TOKENS
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+#![allow(clippy::upper_case_acronyms)]
+#![allow(dead_code)]
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    IDENT,
+    NUMBER,
+    // text holds the literal as written
+    FLOAT,
+    // text holds the contents with the escapes decoded
+    STRING,
+    // keywords
+    KW_LONG,
+    KW_INT,
+    KW_SHORT,
+    KW_CHAR,
+    KW_SIGNED,
+    KW_UNSIGNED,
+    KW_DOUBLE,
+    KW_STRUCT,
+    KW_RETURN,
+    KW_EXTERN,
+    KW_STATIC,
+    KW_ATTRIBUTE,
+    KW_IMPORT,
+    KW_ASM,
+    KW_VOLATILE,
+    // punctuation
+    LPAREN,
+    RPAREN,
+    LBRACE,
+    RBRACE,
+    LBRACKET,
+    RBRACKET,
+    COMMA,
+    DOT,
+    ARROW,
+    ELLIPSIS,
+    SEMI,
+    COLON,
+    ASSIGN,
+    // compound assignments
+    PLUS_ASSIGN,
+    MINUS_ASSIGN,
+    STAR_ASSIGN,
+    SLASH_ASSIGN,
+    PERCENT_ASSIGN,
+    PIPE_ASSIGN,
+    AMP_ASSIGN,
+    CARET_ASSIGN,
+    SHL_ASSIGN,
+    SHR_ASSIGN,
+    INC,
+    DEC,
+    // operators
+    PLUS,
+    MINUS,
+    STAR,
+    SLASH,
+    PERCENT,
+    PIPE,
+    AMP,
+    CARET,
+    TILDE,
+    BANG,
+    SHL,
+    SHR,
+    EOF,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub text: String,
+    pub value: i64,
+    pub line: usize,
+    pub col: usize,
+}
+
+/*
+***********************************************************************
+  FUNCTION TO MAP AN IDENTIFIER TO ITS KEYWORD, IF IT IS ONE
+************************************************************************
+*/
+fn keyword(text: &str) -> Option<TokenKind> {
+    match text {
+        "long" => Some(TokenKind::KW_LONG),
+        "int" => Some(TokenKind::KW_INT),
+        "short" => Some(TokenKind::KW_SHORT),
+        "char" => Some(TokenKind::KW_CHAR),
+        "double" => Some(TokenKind::KW_DOUBLE),
+        "signed" => Some(TokenKind::KW_SIGNED),
+        "unsigned" => Some(TokenKind::KW_UNSIGNED),
+        "struct" => Some(TokenKind::KW_STRUCT),
+        "return" => Some(TokenKind::KW_RETURN),
+        "extern" => Some(TokenKind::KW_EXTERN),
+        "static" => Some(TokenKind::KW_STATIC),
+        "__attribute__" => Some(TokenKind::KW_ATTRIBUTE),
+        "import" => Some(TokenKind::KW_IMPORT),
+        "asm" | "__asm__" => Some(TokenKind::KW_ASM),
+        "volatile" | "__volatile__" => Some(TokenKind::KW_VOLATILE),
+        _ => None,
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO TOKENIZE A SOURCE FILE. THE TOKEN LIST ALWAYS ENDS WITH
+  AN EOF TOKEN, EVEN WHEN ERRORS WERE REPORTED.
+************************************************************************
+*/
+pub fn tokenize(source: &str, diagnostics: &mut Vec<Diagnostic>) -> Vec<Token> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens: Vec<Token> = vec![];
+    let mut pos = 0;
+    let mut line = 1;
+    let mut col = 1;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let next = if pos + 1 < chars.len() { chars[pos + 1] } else { '\0' };
+        let (start_line, start_col) = (line, col);
+
+        // whitespace
+        if c == '\n' {
+            pos += 1;
+            line += 1;
+            col = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            pos += 1;
+            col += 1;
+            continue;
+        }
+
+        // comments
+        if c == '/' && next == '/' {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+                col += 1;
+            }
+            continue;
+        }
+        if c == '/' && next == '*' {
+            pos += 2;
+            col += 2;
+            let mut closed = false;
+            while pos < chars.len() {
+                if chars[pos] == '*' && pos + 1 < chars.len() && chars[pos + 1] == '/' {
+                    pos += 2;
+                    col += 2;
+                    closed = true;
+                    break;
+                }
+                if chars[pos] == '\n' {
+                    line += 1;
+                    col = 1;
+                } else {
+                    col += 1;
+                }
+                pos += 1;
+            }
+            if !closed {
+                diagnostics.push(Diagnostic::new(start_line, start_col, "unterminated comment".to_string()));
+            }
+            continue;
+        }
+
+        // identifiers and keywords
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+                col += 1;
+            }
+            let text: String = chars[start..pos].iter().collect();
+            let kind = keyword(&text).unwrap_or(TokenKind::IDENT);
+            tokens.push(Token { kind, text, value: 0, line: start_line, col: start_col });
+            continue;
+        }
+
+        // integer and floating point constants
+        if c.is_ascii_digit() {
+            let start = pos;
+            while pos < chars.len() && chars[pos].is_ascii_digit() {
+                pos += 1;
+                col += 1;
+            }
+
+            // a fraction and/or an exponent make it a double
+            let mut is_float = false;
+            if pos < chars.len() && chars[pos] == '.' && !(pos + 1 < chars.len() && chars[pos + 1] == '.') {
+                is_float = true;
+                pos += 1;
+                col += 1;
+                while pos < chars.len() && chars[pos].is_ascii_digit() {
+                    pos += 1;
+                    col += 1;
+                }
+            }
+            if pos < chars.len() && (chars[pos] == 'e' || chars[pos] == 'E') {
+                let mut end = pos + 1;
+                if end < chars.len() && (chars[end] == '+' || chars[end] == '-') {
+                    end += 1;
+                }
+                if end < chars.len() && chars[end].is_ascii_digit() {
+                    is_float = true;
+                    while end < chars.len() && chars[end].is_ascii_digit() {
+                        end += 1;
+                    }
+                    col += end - pos;
+                    pos = end;
+                }
+            }
+
+            let text: String = chars[start..pos].iter().collect();
+            if is_float {
+                tokens.push(Token { kind: TokenKind::FLOAT, text, value: 0, line: start_line, col: start_col });
+                continue;
+            }
+            // A constant above the largest long keeps its bits, the parser
+            // types it unsigned long
+            let value = match text.parse::<u64>() {
+                Ok(value) => value as i64,
+                Err(_) => {
+                    diagnostics.push(Diagnostic::new(start_line, start_col, format!("integer constant {} is too large", text)));
+                    0
+                }
+            };
+            tokens.push(Token { kind: TokenKind::NUMBER, text, value, line: start_line, col: start_col });
+            continue;
+        }
+
+        // string literals
+        if c == '"' {
+            pos += 1;
+            col += 1;
+            let mut text = String::new();
+            let mut closed = false;
+
+            while pos < chars.len() && chars[pos] != '\n' {
+                let ch = chars[pos];
+                pos += 1;
+                col += 1;
+                if ch == '"' {
+                    closed = true;
+                    break;
+                }
+                if ch != '\\' || pos == chars.len() {
+                    text.push(ch);
+                    continue;
+                }
+
+                let escaped = chars[pos];
+                pos += 1;
+                col += 1;
+                match escaped {
+                    'n' => text.push('\n'),
+                    't' => text.push('\t'),
+                    'r' => text.push('\r'),
+                    '0' => text.push('\0'),
+                    '\\' | '"' | '\'' => text.push(escaped),
+                    _ => diagnostics.push(Diagnostic::new(line, col - 2, format!("unknown escape sequence \\{}", escaped))),
+                }
+            }
+
+            if !closed {
+                diagnostics.push(Diagnostic::new(start_line, start_col, "unterminated string literal".to_string()));
+            }
+            tokens.push(Token { kind: TokenKind::STRING, text, value: 0, line: start_line, col: start_col });
+            continue;
+        }
+
+        // operators and punctuation, longest first
+        if c == '.' && next == '.' && pos + 2 < chars.len() && chars[pos + 2] == '.' {
+            tokens.push(Token { kind: TokenKind::ELLIPSIS, text: "...".to_string(), value: 0, line: start_line, col: start_col });
+            pos += 3;
+            col += 3;
+            continue;
+        }
+        let third = if pos + 2 < chars.len() { chars[pos + 2] } else { '\0' };
+        let (kind, width) = match (c, next) {
+            ('<', '<') if third == '=' => (Some(TokenKind::SHL_ASSIGN), 3),
+            ('>', '>') if third == '=' => (Some(TokenKind::SHR_ASSIGN), 3),
+            ('+', '=') => (Some(TokenKind::PLUS_ASSIGN), 2),
+            ('-', '=') => (Some(TokenKind::MINUS_ASSIGN), 2),
+            ('*', '=') => (Some(TokenKind::STAR_ASSIGN), 2),
+            ('/', '=') => (Some(TokenKind::SLASH_ASSIGN), 2),
+            ('%', '=') => (Some(TokenKind::PERCENT_ASSIGN), 2),
+            ('|', '=') => (Some(TokenKind::PIPE_ASSIGN), 2),
+            ('&', '=') => (Some(TokenKind::AMP_ASSIGN), 2),
+            ('^', '=') => (Some(TokenKind::CARET_ASSIGN), 2),
+            ('+', '+') => (Some(TokenKind::INC), 2),
+            ('-', '-') => (Some(TokenKind::DEC), 2),
+            ('<', '<') => (Some(TokenKind::SHL), 2),
+            ('>', '>') => (Some(TokenKind::SHR), 2),
+            ('-', '>') => (Some(TokenKind::ARROW), 2),
+            ('(', _) => (Some(TokenKind::LPAREN), 1),
+            (')', _) => (Some(TokenKind::RPAREN), 1),
+            ('{', _) => (Some(TokenKind::LBRACE), 1),
+            ('}', _) => (Some(TokenKind::RBRACE), 1),
+            ('[', _) => (Some(TokenKind::LBRACKET), 1),
+            (']', _) => (Some(TokenKind::RBRACKET), 1),
+            (',', _) => (Some(TokenKind::COMMA), 1),
+            ('.', _) => (Some(TokenKind::DOT), 1),
+            (';', _) => (Some(TokenKind::SEMI), 1),
+            (':', _) => (Some(TokenKind::COLON), 1),
+            ('=', _) => (Some(TokenKind::ASSIGN), 1),
+            ('+', _) => (Some(TokenKind::PLUS), 1),
+            ('-', _) => (Some(TokenKind::MINUS), 1),
+            ('*', _) => (Some(TokenKind::STAR), 1),
+            ('/', _) => (Some(TokenKind::SLASH), 1),
+            ('%', _) => (Some(TokenKind::PERCENT), 1),
+            ('|', _) => (Some(TokenKind::PIPE), 1),
+            ('&', _) => (Some(TokenKind::AMP), 1),
+            ('^', _) => (Some(TokenKind::CARET), 1),
+            ('~', _) => (Some(TokenKind::TILDE), 1),
+            ('!', _) => (Some(TokenKind::BANG), 1),
+            _ => (None, 1),
+        };
+
+        match kind {
+            Some(kind) => {
+                let text: String = chars[pos..pos + width].iter().collect();
+                tokens.push(Token { kind, text, value: 0, line: start_line, col: start_col });
+            }
+            None => {
+                diagnostics.push(Diagnostic::new(start_line, start_col, format!("unexpected character '{}'", c)));
+            }
+        }
+        pos += width;
+        col += width;
+    }
+
+    tokens.push(Token { kind: TokenKind::EOF, text: "".to_string(), value: 0, line, col });
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+        let mut diagnostics: Vec<Diagnostic> = vec![];
+        let tokens = tokenize(source, &mut diagnostics);
+        (tokens, diagnostics)
+    }
+
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        lex(source).0.iter().map(|token| token.kind).collect()
+    }
+
+    #[test]
+    fn tokens_know_their_line_and_column() {
+        let (tokens, diagnostics) = lex("long x = 42;\n  x <<= 1; // done\n\treturn x;");
+        assert!(diagnostics.is_empty());
+
+        let positions: Vec<(TokenKind, &str, usize, usize)> =
+            tokens.iter().map(|token| (token.kind, token.text.as_str(), token.line, token.col)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (TokenKind::KW_LONG, "long", 1, 1),
+                (TokenKind::IDENT, "x", 1, 6),
+                (TokenKind::ASSIGN, "=", 1, 8),
+                (TokenKind::NUMBER, "42", 1, 10),
+                (TokenKind::SEMI, ";", 1, 12),
+                (TokenKind::IDENT, "x", 2, 3),
+                (TokenKind::SHL_ASSIGN, "<<=", 2, 5),
+                (TokenKind::NUMBER, "1", 2, 9),
+                (TokenKind::SEMI, ";", 2, 10),
+                (TokenKind::KW_RETURN, "return", 3, 2),
+                (TokenKind::IDENT, "x", 3, 9),
+                (TokenKind::SEMI, ";", 3, 10),
+                (TokenKind::EOF, "", 3, 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_comments_keep_the_position() {
+        let (tokens, diagnostics) = lex("a /* one\n two */ b\n/* three */c");
+        assert!(diagnostics.is_empty());
+        assert_eq!((tokens[1].line, tokens[1].col), (2, 9));
+        assert_eq!((tokens[2].line, tokens[2].col), (3, 12));
+
+        let (_, diagnostics) = lex("a /* open");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].col), (1, 3));
+    }
+
+    #[test]
+    fn longest_operator_wins() {
+        assert_eq!(
+            kinds("a<<=b>>c->d...e++--f>>=g-h"),
+            vec![
+                TokenKind::IDENT,
+                TokenKind::SHL_ASSIGN,
+                TokenKind::IDENT,
+                TokenKind::SHR,
+                TokenKind::IDENT,
+                TokenKind::ARROW,
+                TokenKind::IDENT,
+                TokenKind::ELLIPSIS,
+                TokenKind::IDENT,
+                TokenKind::INC,
+                TokenKind::DEC,
+                TokenKind::IDENT,
+                TokenKind::SHR_ASSIGN,
+                TokenKind::IDENT,
+                TokenKind::MINUS,
+                TokenKind::IDENT,
+                TokenKind::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn keywords_and_their_spellings() {
+        assert_eq!(
+            kinds("asm __asm__ volatile __volatile__ unsigned longer"),
+            vec![
+                TokenKind::KW_ASM,
+                TokenKind::KW_ASM,
+                TokenKind::KW_VOLATILE,
+                TokenKind::KW_VOLATILE,
+                TokenKind::KW_UNSIGNED,
+                TokenKind::IDENT,
+                TokenKind::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn string_escapes_are_decoded() {
+        let (tokens, diagnostics) = lex(r#""a\tb\n\"q\"\\\0\'\r" x"#);
+        assert!(diagnostics.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::STRING);
+        assert_eq!(tokens[0].text, "a\tb\n\"q\"\\\0'\r");
+        // The column after a string counts the escapes as written
+        assert_eq!(tokens[1].col, 23);
+    }
+
+    #[test]
+    fn bad_strings_are_reported_where_they_are() {
+        let (tokens, diagnostics) = lex("x = \"ab\\qc\";\ny = \"open\nz");
+        let messages: Vec<(usize, usize, &str)> =
+            diagnostics.iter().map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.message.as_str())).collect();
+        assert_eq!(
+            messages,
+            vec![(1, 8, "unknown escape sequence \\q"), (2, 5, "unterminated string literal")]
+        );
+        // Lexing goes on after both
+        assert_eq!(tokens[tokens.len() - 2].text, "z");
+        assert_eq!(tokens[tokens.len() - 2].line, 3);
+    }
+
+    #[test]
+    fn numbers() {
+        let (tokens, diagnostics) = lex("0 42 3.5 1e3 2.5e-2 9223372036854775807 18446744073709551615");
+        assert!(diagnostics.is_empty());
+        let values: Vec<(TokenKind, i64)> = tokens.iter().map(|token| (token.kind, token.value)).collect();
+        assert_eq!(
+            values,
+            vec![
+                (TokenKind::NUMBER, 0),
+                (TokenKind::NUMBER, 42),
+                (TokenKind::FLOAT, 0),
+                (TokenKind::FLOAT, 0),
+                (TokenKind::FLOAT, 0),
+                (TokenKind::NUMBER, i64::MAX),
+                // Above the largest long the bits are kept
+                (TokenKind::NUMBER, -1),
+                (TokenKind::EOF, 0),
+            ]
+        );
+        assert_eq!(tokens[4].text, "2.5e-2");
+
+        let (_, diagnostics) = lex("x = 18446744073709551616;");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].col), (1, 5));
+        assert_eq!(diagnostics[0].message, "integer constant 18446744073709551616 is too large");
+    }
+
+    #[test]
+    fn unexpected_characters_are_skipped() {
+        let (tokens, diagnostics) = lex("a @ b $");
+        assert_eq!(kinds("a @ b $"), vec![TokenKind::IDENT, TokenKind::IDENT, TokenKind::EOF]);
+        assert_eq!(tokens[1].col, 5);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!((diagnostics[0].col, diagnostics[1].col), (3, 7));
+        assert_eq!(diagnostics[0].message, "unexpected character '@'");
+    }
+}
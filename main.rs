@@ -0,0 +1,635 @@
+/*
+***********************************************************************
+  MAIN.RS : THE COMPILE COMMAND LINE DRIVER
+
+  compile [options] <source file>... [<object file>...]
+    -o <file>            name of the output file
+    -S                   stop after writing the assembly (<stem>.s)
+    -c                   stop after assembling an object file (<stem>.o)
+    -O0, -O1, -O2        optimization level (default -O2)
+    --emit=ast,ir,asm    also keep <stem>.ast, <stem>.ir and/or <stem>.s
+    --print-after=<pass> dump every function after a codegen pass
+    --time-passes        report how long every codegen pass took
+    --inline-threshold=N inline leaf callees of at most N nodes (default 16
+                         at -O2, 0 turns inlining off)
+    -fomit-frame-pointer address the locals of leaf functions from %rsp
+                         (-O1, -O2)
+    --bounds-check       check array indices at run time
+    --bounds-trap=<name> routine called on a bad index (default __bounds_trap)
+    --checked-arith      check signed overflow and division at run time
+    --arith-trap=<name>  routine called when a check fails (default __arith_trap)
+    -fPIC                position independent code, for a shared library
+    -fvisibility=<v>     visibility of symbols without an attribute, default
+                         or hidden (default default)
+    --shared             link a shared library (lib<stem>.so), implies -fPIC
+    -v                   trace what codegen does on stderr
+
+  Every source file is compiled on its own into <stem>.s and <stem>.o,
+  kept in a temporary directory unless -S, -c or --emit=asm asks for them.
+  Without -S or -c the objects, and any object files or archives on the
+  command line, are linked into an executable (a.out). A file can use
+  what another one defines by importing it: import "other.c";
+
+  EXIT CODES: 0 success, 1 errors in the source, 2 bad usage,
+  unreadable input or unwritable output, 3 the assembler or linker
+  failed.
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+mod codegen;
+mod diagnostic;
+mod expression;
+mod lexer;
+mod module;
+mod parser;
+mod semantic;
+mod typecheck;
+
+use codegen::{CodegenOptions, CodegenWithOptions};
+use expression::{RList, Visibility};
+use module::fileDiagnostic;
+use std::env;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+const EXIT_OK: i32 = 0;
+const EXIT_DIAGNOSTICS: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_TOOL_FAILED: i32 = 3;
+
+const USAGE: &str = "usage: compile [-o <file>] [-S | -c] [-O0|-O1|-O2] [--emit=ast,ir,asm] \
+                     [--print-after=<pass>] [--time-passes] [--inline-threshold=N] [-fomit-frame-pointer] [--bounds-check] [--bounds-trap=<name>] \
+                     [--checked-arith] [--arith-trap=<name>] [-fPIC] [-fvisibility=default|hidden] \
+                     [--shared] [-v] <source file>... [<object file>...]";
+
+struct driverOptions {
+    inputs: Vec<String>,
+    // object files and archives handed to the linker as they are
+    objects: Vec<String>,
+    output: Option<String>,
+    // -S
+    stop_at_asm: bool,
+    // -c
+    stop_at_object: bool,
+    // --shared
+    shared: bool,
+    emit: Vec<String>,
+    codegen: CodegenOptions,
+}
+
+/*
+***********************************************************************
+  FUNCTION TO READ THE COMMAND LINE
+************************************************************************
+*/
+fn parse_args(args: &[String]) -> Result<driverOptions, String> {
+    let mut inputs: Vec<String> = vec![];
+    let mut objects: Vec<String> = vec![];
+    let mut output: Option<String> = None;
+    let mut stop_at_asm = false;
+    let mut stop_at_object = false;
+    let mut emit: Vec<String> = vec![];
+    let mut level = 2;
+    let mut print_after: Vec<String> = vec![];
+    let mut time_passes = false;
+    let mut inline_threshold: Option<usize> = None;
+    let mut omit_frame_pointer = false;
+    let mut bounds_check = false;
+    let mut bounds_trap: Option<String> = None;
+    let mut checked_arith = false;
+    let mut arith_trap: Option<String> = None;
+    let mut pic = false;
+    let mut visibility = Visibility::DEFAULT;
+    let mut shared = false;
+    let mut verbose = false;
+
+    let mut index = 0;
+    while index < args.len() {
+        let arg = args[index].as_str();
+
+        if arg == "-o" {
+            index += 1;
+            match args.get(index) {
+                Some(path) => output = Some(path.clone()),
+                None => return Err("-o needs a file name".to_string()),
+            }
+        } else if arg == "-S" {
+            stop_at_asm = true;
+        } else if arg == "-c" {
+            stop_at_object = true;
+        } else if let Some(digit) = arg.strip_prefix("-O") {
+            level = match digit {
+                "0" => 0,
+                "1" => 1,
+                "2" => 2,
+                _ => return Err(format!("unknown optimization level {}", arg)),
+            };
+        } else if let Some(kinds) = arg.strip_prefix("--emit=") {
+            for kind in kinds.split(',') {
+                if kind != "ast" && kind != "ir" && kind != "asm" {
+                    return Err(format!("unknown --emit kind {}", kind));
+                }
+                emit.push(kind.to_string());
+            }
+        } else if let Some(pass) = arg.strip_prefix("--print-after=") {
+            print_after.push(pass.to_string());
+        } else if arg == "--time-passes" {
+            time_passes = true;
+        } else if let Some(count) = arg.strip_prefix("--inline-threshold=") {
+            inline_threshold = match count.parse::<usize>() {
+                Ok(count) => Some(count),
+                Err(_) => return Err(format!("bad inline threshold {}", count)),
+            };
+        } else if arg == "-fomit-frame-pointer" {
+            omit_frame_pointer = true;
+        } else if arg == "--bounds-check" {
+            bounds_check = true;
+        } else if let Some(name) = arg.strip_prefix("--bounds-trap=") {
+            bounds_trap = Some(name.to_string());
+        } else if arg == "--checked-arith" {
+            checked_arith = true;
+        } else if let Some(name) = arg.strip_prefix("--arith-trap=") {
+            arith_trap = Some(name.to_string());
+        } else if arg == "-fPIC" {
+            pic = true;
+        } else if let Some(name) = arg.strip_prefix("-fvisibility=") {
+            visibility = match name {
+                "default" => Visibility::DEFAULT,
+                "hidden" => Visibility::HIDDEN,
+                _ => return Err(format!("unknown visibility {}", name)),
+            };
+        } else if arg == "--shared" {
+            shared = true;
+        } else if arg == "-v" {
+            verbose = true;
+        } else if arg.starts_with('-') {
+            return Err(format!("unknown option {}", arg));
+        } else if arg.ends_with(".o") || arg.ends_with(".a") || arg.ends_with(".so") {
+            objects.push(arg.to_string());
+        } else {
+            inputs.push(arg.to_string());
+        }
+
+        index += 1;
+    }
+
+    if stop_at_asm && stop_at_object {
+        return Err("-S and -c can't be used together".to_string());
+    }
+    if inputs.is_empty() {
+        return Err("no source file given".to_string());
+    }
+    if output.is_some() && (stop_at_asm || stop_at_object) && inputs.len() > 1 {
+        return Err("-o can't name the output of -S or -c for several source files".to_string());
+    }
+
+    let mut codegen = CodegenOptions::with_level(level);
+    for pass in print_after.iter() {
+        if !codegen.passes.contains(pass) {
+            return Err(format!("pass {} doesn't run at -O{}", pass, level));
+        }
+    }
+    codegen.print_after = print_after;
+    codegen.time_passes = time_passes;
+    if let Some(count) = inline_threshold {
+        codegen.inline_threshold = count;
+    }
+    codegen.omit_frame_pointer = omit_frame_pointer;
+    codegen.bounds_check = bounds_check;
+    if let Some(name) = bounds_trap {
+        codegen.bounds_trap = name;
+    }
+    codegen.checked_arith = checked_arith;
+    if let Some(name) = arith_trap {
+        codegen.arith_trap = name;
+    }
+    codegen.pic = pic || shared;
+    codegen.visibility = visibility;
+    codegen.verbose = verbose;
+
+    Ok(driverOptions {
+        inputs,
+        objects,
+        output,
+        stop_at_asm,
+        stop_at_object,
+        shared,
+        emit,
+        codegen,
+    })
+}
+
+/*
+***********************************************************************
+  FUNCTION TO RUN AN EXTERNAL TOOL (THE ASSEMBLER OR THE LINKER)
+************************************************************************
+*/
+fn run_tool(program: &str, args: &[&str]) -> Result<(), String> {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} failed ({})", program, status)),
+        Err(err) => Err(format!("unable to run {}: {}", program, err)),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match parse_args(&args) {
+        Ok(opts) => opts,
+        Err(message) => {
+            eprintln!("compile: {}", message);
+            eprintln!("{}", USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    process::exit(compile(opts));
+}
+
+fn file_stem(input: &str) -> String {
+    match Path::new(input).file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => "a".to_string(),
+    }
+}
+
+// Opens the file for writing without truncating it, a file that didn't exist is removed again
+fn check_writable(path: &str) -> std::io::Result<()> {
+    let existed = Path::new(path).exists();
+    fs::OpenOptions::new().append(true).create(true).open(path)?;
+    if !existed {
+        let _ = fs::remove_file(path);
+    }
+    Ok(())
+}
+
+fn print_diagnostics(diagnostics: &[fileDiagnostic]) {
+    for (input, diagnostic) in diagnostics.iter() {
+        eprintln!("{}:{}", input, diagnostic);
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO TAKE EVERY SOURCE FILE THROUGH PARSING, THE CHECKS,
+  CODEGEN AND THE ASSEMBLER, AND THE OBJECTS THROUGH THE LINKER.
+  RETURNS THE EXIT CODE.
+************************************************************************
+*/
+fn compile(opts: driverOptions) -> i32 {
+    // Each file's outputs are named after it, two files can't share a name
+    for (index, input) in opts.inputs.iter().enumerate() {
+        if let Some(other) = opts.inputs[..index].iter().find(|other| file_stem(other) == file_stem(input)) {
+            eprintln!("compile: {} and {} would both be compiled to {}.o", other, input, file_stem(input));
+            return EXIT_USAGE;
+        }
+    }
+
+    // Every file is checked before any code is written, so a symbol two
+    // files define is reported with the other problems
+    let mut units: Vec<(String, RList)> = vec![];
+    let mut failed = false;
+    for input in opts.inputs.iter() {
+        match check_unit(input, &opts) {
+            Ok(worklist) => units.push((input.clone(), worklist)),
+            Err(EXIT_DIAGNOSTICS) => failed = true,
+            Err(code) => return code,
+        }
+    }
+    if failed {
+        return EXIT_DIAGNOSTICS;
+    }
+
+    let unit_lists: Vec<(String, &RList)> = units.iter().map(|(input, worklist)| (input.clone(), worklist)).collect();
+    let diagnostics = module::check_duplicate_symbols(&unit_lists);
+    if !diagnostics.is_empty() {
+        print_diagnostics(&diagnostics);
+        return EXIT_DIAGNOSTICS;
+    }
+
+    // An output that can't be written is bad usage, not a failure of the
+    // tool that would write it
+    if let Some(output) = opts.output.as_ref() {
+        if let Err(err) = check_writable(output) {
+            eprintln!("compile: unable to write {}: {}", output, err);
+            return EXIT_USAGE;
+        }
+    }
+
+    // Files the user didn't ask for are written to a directory of their own,
+    // so they can't overwrite the user's files of the same name
+    let temp_dir = match create_temp_dir() {
+        Ok(temp_dir) => temp_dir,
+        Err(err) => {
+            eprintln!("compile: unable to create a directory in {}: {}", env::temp_dir().display(), err);
+            return EXIT_USAGE;
+        }
+    };
+
+    let mut object_paths: Vec<String> = vec![];
+    let mut result = Ok(());
+    for (input, worklist) in units.iter() {
+        match generate_unit(input, worklist, &opts, &temp_dir) {
+            Ok(Some(object_path)) => object_paths.push(object_path),
+            Ok(None) => {}
+            Err(code) => {
+                result = Err(code);
+                break;
+            }
+        }
+    }
+
+    // Link
+    if result.is_ok() && !opts.stop_at_asm && !opts.stop_at_object {
+        let default_output = if opts.shared { format!("lib{}.so", file_stem(&opts.inputs[0])) } else { "a.out".to_string() };
+        let exe_path = opts.output.clone().unwrap_or(default_output);
+        let mut args: Vec<&str> = vec![];
+        if opts.shared {
+            args.push("-shared");
+        }
+        args.extend(object_paths.iter().map(|path| path.as_str()));
+        args.extend(opts.objects.iter().map(|path| path.as_str()));
+        args.extend(["-o", exe_path.as_str()]);
+        if let Err(message) = run_tool("cc", &args) {
+            eprintln!("compile: {}", message);
+            result = Err(EXIT_TOOL_FAILED);
+        }
+    }
+    let _ = fs::remove_dir_all(&temp_dir);
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(code) => code,
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO PARSE AND CHECK ONE SOURCE FILE, ITS IMPORTS REPLACED BY
+  THE DECLARATIONS THEY STAND FOR. PROBLEMS ARE PRINTED, THE ERROR IS
+  THE EXIT CODE.
+************************************************************************
+*/
+fn check_unit(input: &str, opts: &driverOptions) -> Result<RList, i32> {
+    let source = match fs::read_to_string(input) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("compile: unable to read {}: {}", input, err);
+            return Err(EXIT_USAGE);
+        }
+    };
+
+    // Parse
+    let worklist = match parser::parse_program(&source) {
+        Ok(worklist) => worklist,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                eprintln!("{}:{}", input, diagnostic);
+            }
+            return Err(EXIT_DIAGNOSTICS);
+        }
+    };
+    let mut worklist = match module::resolve_imports(worklist, input) {
+        Ok(worklist) => worklist,
+        Err(diagnostics) => {
+            print_diagnostics(&diagnostics);
+            return Err(EXIT_DIAGNOSTICS);
+        }
+    };
+
+    // Check
+    let diagnostics = semantic::check_program(&worklist);
+    if !diagnostics.is_empty() {
+        for diagnostic in diagnostics.iter() {
+            eprintln!("{}:{}", input, diagnostic);
+        }
+        return Err(EXIT_DIAGNOSTICS);
+    }
+
+    // Types, every expression is annotated for codegen
+    let diagnostics = typecheck::check_types(&mut worklist);
+    if !diagnostics.is_empty() {
+        for diagnostic in diagnostics.iter() {
+            eprintln!("{}:{}", input, diagnostic);
+        }
+        return Err(EXIT_DIAGNOSTICS);
+    }
+
+    let stem = file_stem(input);
+    if opts.emit.iter().any(|kind| kind == "ast") {
+        if let Err(err) = fs::write(format!("{}.ast", stem), expression::dump_ast(&worklist)) {
+            eprintln!("compile: unable to write {}.ast: {}", stem, err);
+            return Err(EXIT_USAGE);
+        }
+    }
+
+    Ok(worklist)
+}
+
+/*
+***********************************************************************
+  FUNCTION TO CREATE THE DIRECTORY OF THE INTERMEDIATE FILES. THE NAME
+  IS RANDOM AND THE DIRECTORY MUST NOT EXIST YET, SO NO ONE ELSE CAN HAVE
+  CREATED IT OR A LINK IN ITS PLACE. ONLY THE USER CAN READ OR WRITE IT.
+************************************************************************
+*/
+fn create_temp_dir() -> std::io::Result<PathBuf> {
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u32(process::id());
+        let path = env::temp_dir().join(format!("compile-{:016x}", hasher.finish()));
+        match fs::DirBuilder::new().mode(0o700).create(&path) {
+            Ok(()) => return Ok(path),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO WRITE THE ASSEMBLY OF ONE CHECKED FILE AND ASSEMBLE IT.
+  GIVES THE OBJECT FILE, NONE WITH -S. THE INTERMEDIATE FILES GO TO
+  TEMP_DIR. PROBLEMS ARE PRINTED, THE ERROR IS THE EXIT CODE.
+************************************************************************
+*/
+fn generate_unit(input: &str, worklist: &RList, opts: &driverOptions, temp_dir: &Path) -> Result<Option<String>, i32> {
+    let stem = file_stem(input);
+    let keep_asm = opts.stop_at_asm || opts.emit.iter().any(|kind| kind == "asm");
+
+    // Codegen
+    let mut codegen = opts.codegen.clone();
+    let asm_path = match (&opts.output, opts.stop_at_asm, keep_asm) {
+        (Some(output), true, _) => output.clone(),
+        (_, _, true) => format!("{}.s", stem),
+        _ => temp_dir.join(format!("{}.s", stem)).to_string_lossy().to_string(),
+    };
+    codegen.output_path = asm_path.clone();
+    if opts.emit.iter().any(|kind| kind == "ir") {
+        codegen.ir_path = Some(format!("{}.ir", stem));
+    }
+    if let Err(message) = CodegenWithOptions(worklist, &codegen) {
+        eprintln!("compile: {}", message);
+        return Err(EXIT_USAGE);
+    }
+
+    if opts.stop_at_asm {
+        return Ok(None);
+    }
+
+    // Assemble
+    let object_path = match (&opts.output, opts.stop_at_object) {
+        (Some(output), true) => output.clone(),
+        (None, true) => format!("{}.o", stem),
+        _ => temp_dir.join(format!("{}.o", stem)).to_string_lossy().to_string(),
+    };
+    match run_tool("as", &[asm_path.as_str(), "-o", object_path.as_str()]) {
+        Ok(()) => Ok(Some(object_path)),
+        Err(message) => {
+            eprintln!("compile: {}", message);
+            Err(EXIT_TOOL_FAILED)
+        }
+    }
+}
+
+/*
+***********************************************************************
+  EXECUTION TESTS: A PROGRAM OF tests/programs IS COMPILED, RUN AND
+  WHAT IT PRINTS COMPARED WITH ITS .expected FILE
+************************************************************************
+*/
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Tests run at the same time, each program gets an executable of its own
+    static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+    fn exe_path(name: &str) -> PathBuf {
+        let count = PROGRAMS.fetch_add(1, Ordering::Relaxed);
+        env::temp_dir().join(format!("compile-test-{}-{}-{}", process::id(), name, count))
+    }
+
+    fn program_path(file: &str) -> PathBuf {
+        Path::new(option_env!("CARGO_MANIFEST_DIR").unwrap_or(".")).join("tests").join("programs").join(file)
+    }
+
+    // Compiles tests/programs/<name>.c for each name with the options into one executable
+    fn compile_programs(names: &[&str], options: &[&str], exe: &Path) -> i32 {
+        let mut args: Vec<String> = options.iter().map(|option| option.to_string()).collect();
+        for name in names {
+            args.push(program_path(&format!("{}.c", name)).to_string_lossy().to_string());
+        }
+        args.push("-o".to_string());
+        args.push(exe.to_string_lossy().to_string());
+        compile(parse_args(&args).expect("bad test options"))
+    }
+
+    // Compiles the programs with the options, runs them and gives what they printed
+    fn run_programs(names: &[&str], options: &[&str]) -> String {
+        let exe = exe_path(names[0]);
+        let code = compile_programs(names, options, &exe);
+        assert_eq!(code, EXIT_OK, "{:?} don't compile with {:?}", names, options);
+
+        let output = Command::new(&exe).output().expect("unable to run the test program");
+        let _ = fs::remove_file(&exe);
+        assert!(output.status.success(), "{:?} with {:?} exited with {}", names, options, output.status);
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    // The output must be tests/programs/<name>.expected, for the first name
+    fn check_programs(names: &[&str], options: &[&str]) {
+        let expected = fs::read_to_string(program_path(&format!("{}.expected", names[0]))).expect("no expected output");
+        assert_eq!(run_programs(names, options), expected, "{:?} with {:?}", names, options);
+    }
+
+    fn check_program(name: &str, options: &[&str]) {
+        check_programs(&[name], options);
+    }
+
+    // %, ~, !, unary plus, the compound assignments and ++/-- on
+    // variables, elements, fields and through pointers
+    #[test]
+    fn operators() {
+        for level in ["-O0", "-O1", "-O2"] {
+            check_program("operators", &[level]);
+        }
+    }
+
+    // Global initializers above LONG_MAX are folded as unsigned long, like
+    // the same expressions in a function
+    #[test]
+    fn unsigned_constants() {
+        for level in ["-O0", "-O2"] {
+            check_program("unsigned", &[level]);
+        }
+    }
+
+    // Leaf functions taking arguments on the stack, with and without the frame pointer
+    #[test]
+    fn omitted_frame_pointer() {
+        check_program("frame", &["-O2"]);
+        check_program("frame", &["-O1", "-fomit-frame-pointer"]);
+        check_program("frame", &["-O2", "-fomit-frame-pointer"]);
+        check_program("frame", &["-O2", "--inline-threshold=0", "-fomit-frame-pointer"]);
+    }
+
+    // Register operands of an asm that clobbers the caller saved registers
+    // are given callee saved ones, and it is an error when none is left.
+    // The asm's own moves are the same with and without the peephole pass.
+    #[test]
+    fn asm_operands() {
+        for level in ["-O0", "-O2"] {
+            check_program("asm", &[level]);
+            check_program("asm", &[level, "-fPIC"]);
+        }
+
+        assert_eq!(compile_programs(&["asm_registers"], &[], &exe_path("asm_registers")), EXIT_DIAGNOSTICS);
+    }
+
+    // Each program ends in the arith trap of traps.c, which prints the
+    // function, the line and column of the operator and the reason
+    #[test]
+    fn arith_traps() {
+        for level in ["-O0", "-O2"] {
+            for name in ["trap_overflow", "trap_divide", "trap_zero"] {
+                check_programs(&[name, "traps"], &[level, "--checked-arith"]);
+            }
+        }
+    }
+
+    // An index past the end, a negative one and one past the end of an
+    // array field each end in the bounds trap of traps.c
+    #[test]
+    fn bounds_traps() {
+        for level in ["-O0", "-O2"] {
+            for name in ["bounds_index", "bounds_negative", "bounds_field"] {
+                check_programs(&[name, "traps"], &[level, "--bounds-check"]);
+            }
+        }
+    }
+
+    // A file using what the file it imports defines, linked into one
+    // program, and a function defined by two files of the program
+    #[test]
+    fn imports() {
+        for level in ["-O0", "-O2"] {
+            check_programs(&["imports", "counter"], &[level]);
+            check_programs(&["imports", "counter"], &[level, "-fPIC"]);
+        }
+        assert_eq!(compile_programs(&["imports", "counter", "duplicate"], &[], &exe_path("duplicate")), EXIT_DIAGNOSTICS);
+    }
+
+    // The same results whether small callees are inlined or called
+    #[test]
+    fn inline_threshold() {
+        for threshold in ["--inline-threshold=0", "--inline-threshold=4", "--inline-threshold=64"] {
+            check_program("frame", &["-O2", threshold]);
+            check_program("operators", &["-O2", threshold]);
+        }
+    }
+}
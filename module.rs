@@ -0,0 +1,120 @@
+/*
+***********************************************************************
+  MODULE.RS : IMPORTS BETWEEN SOURCE FILES AND THE CHECKS ACROSS THE
+  FILES OF ONE PROGRAM. AN IMPORTED FILE IS PARSED AND EVERY FUNCTION
+  AND GLOBAL IT DEFINES THAT ISN'T STATIC IS DECLARED EXTERN IN THE
+  IMPORTING FILE, IN PLACE OF THE IMPORT. WHAT THE IMPORTED FILE ITSELF
+  IMPORTS OR DECLARES EXTERN ISN'T PASSED ON.
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+use crate::diagnostic::Diagnostic;
+use crate::expression::*;
+use crate::parser;
+use std::fs;
+use std::path::Path;
+
+// A problem and the file it is in
+pub type fileDiagnostic = (String, Diagnostic);
+
+/*
+***********************************************************************
+  FUNCTION TO REPLACE THE IMPORTS OF A FILE WITH THE DECLARATIONS THEY
+  STAND FOR, RETURNING EVERY PROBLEM FOUND
+************************************************************************
+*/
+pub fn resolve_imports(worklist: RList, input: &str) -> Result<RList, Vec<fileDiagnostic>> {
+    let directory = Path::new(input).parent().unwrap_or(Path::new(""));
+    let mut declarations: Vec<RNode> = vec![];
+    let mut diagnostics: Vec<fileDiagnostic> = vec![];
+
+    for node in worklist.into_nodes() {
+        if node.type_ != NodeType::IMPORTDECL {
+            declarations.push(node);
+            continue;
+        }
+
+        let path = directory.join(&node.name).to_string_lossy().to_string();
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                let message = format!("unable to import {}: {}", path, err);
+                diagnostics.push((input.to_string(), Diagnostic::new(node.line, node.col, message)));
+                continue;
+            }
+        };
+        match parser::parse_program(&source) {
+            Ok(imported) => declarations.extend(exported_declarations(&imported, &node)),
+            Err(errors) => diagnostics.extend(errors.into_iter().map(|diagnostic| (path.clone(), diagnostic))),
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(RList::from_nodes(declarations))
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// The extern declarations of what a file defines for other files, placed
+// where the import is so conflicts are reported there
+fn exported_declarations(imported: &RList, import: &RNode) -> Vec<RNode> {
+    let mut declarations: Vec<RNode> = vec![];
+    let mut current = Some(imported);
+
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref().filter(|node| is_exported(node)) {
+            let type_ = if node.type_ == NodeType::FUNCTIONDECL { NodeType::EXTERNDECL } else { NodeType::VARDECL };
+            let mut declaration = RNode::new(type_, import.line, import.col);
+            declaration.name = node.name.clone();
+            declaration.dataType = node.dataType.clone();
+            declaration.arguments = node.arguments.clone();
+            declaration.storage = StorageClass::EXTERN;
+            declaration.visibility = node.visibility;
+            declarations.push(declaration);
+        }
+        current = item.next.as_deref();
+    }
+
+    declarations
+}
+
+// A function or global defined in its file that other files can see
+fn is_exported(node: &RNode) -> bool {
+    match node.type_ {
+        NodeType::FUNCTIONDECL => node.storage != StorageClass::STATIC,
+        NodeType::VARDECL => node.storage == StorageClass::SC_NONE,
+        _ => false,
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO CHECK THAT NO TWO FILES OF A PROGRAM DEFINE THE SAME
+  SYMBOL, THE LINKER WOULD REFUSE THEM. STATIC ONES ARE LOCAL TO THEIR
+  FILE AND CAN SHARE A NAME.
+************************************************************************
+*/
+pub fn check_duplicate_symbols(units: &[(String, &RList)]) -> Vec<fileDiagnostic> {
+    let mut defined: Vec<(String, &RNode)> = vec![];
+    let mut diagnostics: Vec<fileDiagnostic> = vec![];
+
+    for (input, worklist) in units.iter() {
+        let mut current = Some(*worklist);
+        while let Some(item) = current {
+            if let Some(node) = item.node.as_ref().filter(|node| is_exported(node)) {
+                match defined.iter().find(|(_, previous)| previous.name == node.name) {
+                    Some((file, previous)) => {
+                        let message = format!("{} is already defined at {}:{}:{}", node.name, file, previous.line, previous.col);
+                        diagnostics.push((input.clone(), Diagnostic::new(node.line, node.col, message)));
+                    }
+                    None => defined.push((input.clone(), node)),
+                }
+            }
+            current = item.next.as_deref();
+        }
+    }
+
+    diagnostics
+}
@@ -0,0 +1,1233 @@
+/*
+***********************************************************************
+  PARSER.RS : RECURSIVE DESCENT PARSER BUILDING THE RLIST OF FUNCTIONS
+  AND GLOBAL VARIABLES THAT CODEGEN WALKS.
+
+  program    := ([specifiers] (function | global) | extern | struct | import)*
+  import     := 'import' STRING ';', declares what another file defines
+  specifiers := ('static' | attribute) {'static' | attribute}
+  attribute  := '__attribute__' '(' '(' 'visibility' '(' STRING ')' ')' ')',
+              the visibility is "default" or "hidden"
+  function   := type IDENT '(' [param {',' param}] ')' '{' statement* '}'
+  global     := type IDENT '[' NUMBER ']' ';' | type declarator ['=' expr] ';'
+  extern     := {attribute} 'extern' {attribute} type IDENT
+                '(' [param {',' param} [',' '...']] ')' ';'
+              | {attribute} 'extern' {attribute} type declarator
+                ['[' NUMBER ']'] ';' (a variable)
+  struct     := 'struct' IDENT '{' (type declarator ['[' NUMBER ']'] ';')+ '}' ';'
+  param      := type declarator (the name is optional in an extern)
+  type       := (['signed' | 'unsigned'] ('char' | 'short' ['int'] | 'int'
+              | 'long' ['int']) | 'double' | 'struct' IDENT) {'*'}, 'signed'
+              or 'unsigned' alone is an int
+  declarator := IDENT | '(' '*' IDENT ')' '(' [param {',' param} [',' '...']] ')'
+              (a pointer to a function returning the type)
+  statement  := 'return' expr ';' | type IDENT '[' NUMBER ']' ';'
+              | type declarator '=' expr ';' | type IDENT ';' (a struct)
+              | lvalue assignop expr ';' | lvalue ('++' | '--') ';'
+              | ('++' | '--') lvalue ';' | '{' statement* '}' | asm | ';'
+  asm        := 'asm' ['volatile'] '(' STRING {STRING} [':' [operands]
+                [':' [operands] [':' [STRING {',' STRING}]]]] ')' ';', the
+                template, the outputs, the inputs and the clobbered registers
+  operands   := STRING '(' IDENT ')' {',' STRING '(' IDENT ')'}, a constraint
+                and the variable it binds
+  lvalue     := postfix (a variable, an element or a field) | '*' unary
+  assignop   := '=' | '+=' | '-=' | '*=' | '/=' | '%=' | '|=' | '&=' | '^='
+              | '<<=' | '>>=', 'x op= e' and 'x++' are 'x = x op e' and
+              'x = x + 1' so x is evaluated twice and can't contain a call
+  expr       := bor
+  bor        := bxor {'|' bxor}
+  bxor       := band {'^' band}
+  band       := shift {'&' shift}
+  shift      := additive {('<<' | '>>') additive}
+  additive   := term {('+' | '-') term}
+  term       := unary {('*' | '/' | '%') unary}
+  unary      := ('-' | '+' | '~' | '!' | '*' | '&') unary | postfix
+  postfix    := primary {'.' IDENT | '->' IDENT}
+  primary    := NUMBER | FLOAT | STRING | IDENT | IDENT '(' [expr {',' expr}] ')'
+              | IDENT '[' expr ']' | '(' expr ')', IDENT '(' ... ')' calls a
+              function or the function pointer held by a variable
+************************************************************************
+*/
+#![allow(dead_code)]
+#![allow(non_snake_case)]
+use crate::diagnostic::Diagnostic;
+use crate::expression::*;
+use crate::lexer::*;
+use std::rc::Rc;
+
+// Errors are recorded in the parser's diagnostics, the Err only unwinds
+type PResult<T> = Result<T, ()>;
+
+// Binary operators from the loosest to the tightest binding level
+const BINARY_LEVELS: [&[(TokenKind, OpType)]; 6] = [
+    &[(TokenKind::PIPE, OpType::BOR)],
+    &[(TokenKind::CARET, OpType::BXOR)],
+    &[(TokenKind::AMP, OpType::BAND)],
+    &[(TokenKind::SHL, OpType::BSHL), (TokenKind::SHR, OpType::BSHR)],
+    &[(TokenKind::PLUS, OpType::ADD), (TokenKind::MINUS, OpType::SUBTRACT)],
+    &[(TokenKind::STAR, OpType::MULTIPLY), (TokenKind::SLASH, OpType::DIVIDE), (TokenKind::PERCENT, OpType::MOD)],
+];
+
+// Compound assignments and the operator they apply
+const COMPOUND_ASSIGNMENTS: [(TokenKind, OpType); 10] = [
+    (TokenKind::PLUS_ASSIGN, OpType::ADD),
+    (TokenKind::MINUS_ASSIGN, OpType::SUBTRACT),
+    (TokenKind::STAR_ASSIGN, OpType::MULTIPLY),
+    (TokenKind::SLASH_ASSIGN, OpType::DIVIDE),
+    (TokenKind::PERCENT_ASSIGN, OpType::MOD),
+    (TokenKind::PIPE_ASSIGN, OpType::BOR),
+    (TokenKind::AMP_ASSIGN, OpType::BAND),
+    (TokenKind::CARET_ASSIGN, OpType::BXOR),
+    (TokenKind::SHL_ASSIGN, OpType::BSHL),
+    (TokenKind::SHR_ASSIGN, OpType::BSHR),
+];
+
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    pub diagnostics: Vec<Diagnostic>,
+    // every struct named so far, defined or not yet
+    structs: Vec<Rc<structLayout>>,
+}
+
+/*
+***********************************************************************
+  FUNCTION TO PARSE A WHOLE SOURCE FILE. EVERY PROBLEM FOUND BY THE
+  LEXER AND THE PARSER IS RETURNED, NOT JUST THE FIRST ONE.
+************************************************************************
+*/
+pub fn parse_program(source: &str) -> Result<RList, Vec<Diagnostic>> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let tokens = tokenize(source, &mut diagnostics);
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        diagnostics,
+        structs: vec![],
+    };
+    let declarations = parser.parse_declarations();
+
+    if parser.diagnostics.is_empty() {
+        Ok(RList::from_nodes(declarations))
+    } else {
+        Err(parser.diagnostics)
+    }
+}
+
+/*
+***********************************************************************
+  FUNCTION TO BUILD AN OPERATION NODE
+************************************************************************
+*/
+fn operation_node(op: OpType, left: RNode, right: Option<RNode>, at: &Token) -> RNode {
+    let mut node = RNode::new(NodeType::EXPRESSION, at.line, at.col);
+    node.exprCode = ExprType::OPERATION;
+    node.opCode = op;
+    node.left = Some(Box::new(left));
+    node.right = right.map(Box::new);
+    node
+}
+
+fn contains_call(expr: &RNode) -> bool {
+    expr.opCode == OpType::FUNCTIONCALL
+        || expr.left.as_ref().is_some_and(|left| contains_call(left))
+        || expr.right.as_ref().is_some_and(|right| contains_call(right))
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.peek().kind == kind
+    }
+
+    // Kind of the token the given number of tokens past the current one
+    fn check_ahead(&self, ahead: usize, kind: TokenKind) -> bool {
+        let pos = (self.pos + ahead).min(self.tokens.len() - 1);
+        self.tokens[pos].kind == kind
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if token.kind != TokenKind::EOF {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn accept(&mut self, kind: TokenKind) -> bool {
+        if self.check(kind) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn error_here(&mut self, message: String) {
+        let (line, col) = (self.peek().line, self.peek().col);
+        self.diagnostics.push(Diagnostic::new(line, col, message));
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> PResult<Token> {
+        if self.check(kind) {
+            return Ok(self.advance());
+        }
+
+        let found = if self.check(TokenKind::EOF) { "end of file".to_string() } else { format!("'{}'", self.peek().text) };
+        self.error_here(format!("expected {} but found {}", what, found));
+        Err(())
+    }
+
+    fn is_type(&self) -> bool {
+        matches!(
+            self.peek().kind,
+            TokenKind::KW_LONG
+                | TokenKind::KW_INT
+                | TokenKind::KW_SHORT
+                | TokenKind::KW_CHAR
+                | TokenKind::KW_SIGNED
+                | TokenKind::KW_UNSIGNED
+                | TokenKind::KW_DOUBLE
+                | TokenKind::KW_STRUCT
+        )
+    }
+
+    // The layout of the named struct, created empty the first time it is named
+    fn struct_layout(&mut self, name: &str) -> Rc<structLayout> {
+        if let Some(layout) = self.structs.iter().find(|layout| layout.name == name) {
+            return layout.clone();
+        }
+
+        let layout = Rc::new(structLayout::new(name));
+        self.structs.push(layout.clone());
+        layout
+    }
+
+    // Variables, fields, parameters and return values need the struct body,
+    // only a pointer can be declared to a struct that isn't defined yet
+    fn check_complete(&mut self, dataType: &DataType, at: &Token) -> PResult<()> {
+        match dataType {
+            DataType::ARRAY(element, _) => self.check_complete(element, at),
+            DataType::STRUCT(layout) if !layout.complete.get() => {
+                let message = format!("struct {} is not defined", layout.name);
+                self.diagnostics.push(Diagnostic::new(at.line, at.col, message));
+                Err(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn expect_type(&mut self) -> PResult<DataType> {
+        if !self.is_type() {
+            self.expect(TokenKind::KW_LONG, "a type")?;
+        }
+
+        if self.check(TokenKind::KW_DOUBLE) || self.check(TokenKind::KW_STRUCT) {
+            let mut dataType = if self.accept(TokenKind::KW_DOUBLE) {
+                DataType::DOUBLE
+            } else {
+                self.advance();
+                let name = self.expect(TokenKind::IDENT, "a struct name")?;
+                DataType::STRUCT(self.struct_layout(&name.text))
+            };
+            while self.accept(TokenKind::STAR) {
+                dataType = DataType::POINTER(Box::new(dataType));
+            }
+            return Ok(dataType);
+        }
+
+        let is_unsigned = self.check(TokenKind::KW_UNSIGNED);
+        if !self.accept(TokenKind::KW_SIGNED) {
+            self.accept(TokenKind::KW_UNSIGNED);
+        }
+
+        let base = match self.peek().kind {
+            TokenKind::KW_CHAR => Some(DataType::CHAR),
+            TokenKind::KW_SHORT => Some(DataType::SHORT),
+            TokenKind::KW_INT => Some(DataType::INT),
+            TokenKind::KW_LONG => Some(DataType::LONG),
+            _ => None,
+        };
+        let mut dataType = match base {
+            Some(dataType) => {
+                let kind = self.advance().kind;
+                // 'short int' and 'long int'
+                if kind == TokenKind::KW_SHORT || kind == TokenKind::KW_LONG {
+                    self.accept(TokenKind::KW_INT);
+                }
+                dataType
+            }
+            // 'signed' and 'unsigned' on their own
+            None => DataType::INT,
+        };
+
+        if is_unsigned {
+            dataType = match dataType {
+                DataType::CHAR => DataType::UCHAR,
+                DataType::SHORT => DataType::USHORT,
+                DataType::INT => DataType::UINT,
+                _ => DataType::ULONG,
+            };
+        }
+        while self.accept(TokenKind::STAR) {
+            dataType = DataType::POINTER(Box::new(dataType));
+        }
+        Ok(dataType)
+    }
+
+    // The optional '[' NUMBER ']' after a declared name
+    fn parse_array_suffix(&mut self, element: DataType) -> PResult<DataType> {
+        if !self.accept(TokenKind::LBRACKET) {
+            return Ok(element);
+        }
+
+        let size = self.expect(TokenKind::NUMBER, "the array size")?;
+        if size.value <= 0 {
+            self.diagnostics.push(Diagnostic::new(size.line, size.col, "array size must be positive".to_string()));
+            return Err(());
+        }
+        self.expect(TokenKind::RBRACKET, "']'")?;
+
+        Ok(DataType::ARRAY(Box::new(element), size.value))
+    }
+
+    fn parse_declared_name(&mut self, what: Option<&str>) -> PResult<Option<Token>> {
+        match what {
+            Some(what) => Ok(Some(self.expect(TokenKind::IDENT, what)?)),
+            None if self.check(TokenKind::IDENT) => Ok(Some(self.advance())),
+            None => Ok(None),
+        }
+    }
+
+    // The name declared with the type, and the type itself once a function
+    // pointer declarator wrapped it. 'what' names a required name.
+    fn parse_declarator(&mut self, dataType: DataType, what: Option<&str>) -> PResult<(Option<Token>, DataType)> {
+        if !(self.check(TokenKind::LPAREN) && self.check_ahead(1, TokenKind::STAR)) {
+            let name = self.parse_declared_name(what)?;
+            return Ok((name, dataType));
+        }
+
+        self.advance();
+        self.advance();
+        let name = self.parse_declared_name(what)?;
+        self.expect(TokenKind::RPAREN, "')'")?;
+        let (params, is_variadic) = self.parse_params(false)?;
+
+        let params = params.iter().map(|param| param.dataType.clone()).collect();
+        let function = DataType::FUNCTION(Box::new(dataType), params, is_variadic);
+        Ok((name, DataType::POINTER(Box::new(function))))
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTIONS TO SKIP PAST A BROKEN STATEMENT OR DECLARATION SO PARSING
+      CAN CONTINUE AND REPORT LATER PROBLEMS TOO
+    ************************************************************************
+    */
+    fn skip_statement(&mut self) {
+        while !self.check(TokenKind::EOF) && !self.check(TokenKind::RBRACE) {
+            if self.advance().kind == TokenKind::SEMI {
+                return;
+            }
+        }
+    }
+
+    // A declaration ends at a ';' outside of braces or at the brace closing its body
+    fn skip_declaration(&mut self) {
+        let mut depth = 0;
+
+        while !self.check(TokenKind::EOF) {
+            match self.advance().kind {
+                TokenKind::LBRACE => depth += 1,
+                TokenKind::RBRACE => {
+                    if depth <= 1 {
+                        return;
+                    }
+                    depth -= 1;
+                }
+                TokenKind::SEMI if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    /*
+    ***********************************************************************
+      DECLARATIONS: FUNCTIONS WITH THEIR PARAMETERS, GLOBAL VARIABLES,
+      FUNCTIONS DEFINED ELSEWHERE AND STRUCTS
+    ************************************************************************
+    */
+    fn parse_declarations(&mut self) -> Vec<RNode> {
+        let mut declarations: Vec<RNode> = vec![];
+
+        while !self.check(TokenKind::EOF) {
+            match self.parse_declaration() {
+                Ok(declaration) => declarations.push(declaration),
+                Err(()) => self.skip_declaration(),
+            }
+        }
+
+        declarations
+    }
+
+    fn parse_declaration(&mut self) -> PResult<RNode> {
+        if self.check(TokenKind::KW_STRUCT) && self.check_ahead(2, TokenKind::LBRACE) {
+            return self.parse_struct();
+        }
+        if self.check(TokenKind::KW_IMPORT) {
+            return self.parse_import();
+        }
+
+        let (storage, visibility) = self.parse_specifiers()?;
+        let mut declaration = if storage == StorageClass::EXTERN {
+            self.parse_extern()?
+        } else {
+            let dataType = self.expect_type()?;
+            let (name, dataType) = self.parse_declarator(dataType, Some("a function or variable name"))?;
+            let name = name.ok_or(())?;
+            self.check_complete(&dataType, &name)?;
+
+            if self.check(TokenKind::LPAREN) && !dataType.is_function_pointer() {
+                self.parse_function(name, dataType)?
+            } else {
+                self.parse_global(name, dataType)?
+            }
+        };
+        declaration.storage = storage;
+        declaration.visibility = visibility;
+
+        Ok(declaration)
+    }
+
+    // 'static', 'extern' and visibility attributes in front of a declaration
+    fn parse_specifiers(&mut self) -> PResult<(StorageClass, Visibility)> {
+        let mut storage = StorageClass::SC_NONE;
+        let mut visibility = Visibility::V_NONE;
+
+        loop {
+            let given = match self.peek().kind {
+                TokenKind::KW_STATIC => StorageClass::STATIC,
+                TokenKind::KW_EXTERN => StorageClass::EXTERN,
+                TokenKind::KW_ATTRIBUTE => {
+                    visibility = self.parse_attribute()?;
+                    continue;
+                }
+                _ => return Ok((storage, visibility)),
+            };
+            if storage != StorageClass::SC_NONE && storage != given {
+                self.error_here("a declaration can't be both static and extern".to_string());
+                return Err(());
+            }
+            storage = given;
+            self.advance();
+        }
+    }
+
+    fn parse_attribute(&mut self) -> PResult<Visibility> {
+        self.expect(TokenKind::KW_ATTRIBUTE, "'__attribute__'")?;
+        self.expect(TokenKind::LPAREN, "'('")?;
+        self.expect(TokenKind::LPAREN, "'('")?;
+        let attribute = self.expect(TokenKind::IDENT, "an attribute")?;
+        if attribute.text != "visibility" {
+            self.diagnostics.push(Diagnostic::new(attribute.line, attribute.col, format!("unknown attribute {}", attribute.text)));
+            return Err(());
+        }
+        self.expect(TokenKind::LPAREN, "'('")?;
+        let value = self.expect(TokenKind::STRING, "a visibility")?;
+        let visibility = match value.text.as_str() {
+            "default" => Visibility::DEFAULT,
+            "hidden" => Visibility::HIDDEN,
+            other => {
+                let message = format!("visibility must be \"default\" or \"hidden\", not \"{}\"", other);
+                self.diagnostics.push(Diagnostic::new(value.line, value.col, message));
+                return Err(());
+            }
+        };
+        self.expect(TokenKind::RPAREN, "')'")?;
+        self.expect(TokenKind::RPAREN, "')'")?;
+        self.expect(TokenKind::RPAREN, "')'")?;
+
+        Ok(visibility)
+    }
+
+    fn parse_struct(&mut self) -> PResult<RNode> {
+        self.expect(TokenKind::KW_STRUCT, "'struct'")?;
+        let name = self.expect(TokenKind::IDENT, "a struct name")?;
+        self.expect(TokenKind::LBRACE, "'{'")?;
+
+        // A second body is still parsed so the errors after it make sense
+        let layout = self.struct_layout(&name.text);
+        if layout.complete.get() {
+            self.diagnostics.push(Diagnostic::new(name.line, name.col, format!("struct {} is already defined", name.text)));
+        }
+
+        // A broken field is skipped like a broken statement
+        let mut fields: Vec<(String, DataType)> = vec![];
+        while !self.check(TokenKind::RBRACE) && !self.check(TokenKind::EOF) {
+            match self.parse_field(&fields) {
+                Ok(field) => fields.push(field),
+                Err(()) => self.skip_statement(),
+            }
+        }
+        self.expect(TokenKind::RBRACE, "'}'")?;
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        if fields.is_empty() {
+            self.diagnostics.push(Diagnostic::new(name.line, name.col, format!("struct {} has no fields", name.text)));
+        } else if !layout.complete.get() {
+            layout.complete(fields);
+        }
+
+        let mut node = RNode::new(NodeType::STRUCTDECL, name.line, name.col);
+        node.name = name.text.clone();
+        node.dataType = DataType::STRUCT(layout);
+        Ok(node)
+    }
+
+    fn parse_field(&mut self, fields: &[(String, DataType)]) -> PResult<(String, DataType)> {
+        let dataType = self.expect_type()?;
+        let (name, dataType) = self.parse_declarator(dataType, Some("a field name"))?;
+        let name = name.ok_or(())?;
+        let dataType = self.parse_array_suffix(dataType)?;
+        self.check_complete(&dataType, &name)?;
+        if fields.iter().any(|(field, _)| *field == name.text) {
+            self.diagnostics.push(Diagnostic::new(name.line, name.col, format!("field {} is declared twice", name.text)));
+            return Err(());
+        }
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        Ok((name.text.clone(), dataType))
+    }
+
+    fn parse_global(&mut self, name: Token, dataType: DataType) -> PResult<RNode> {
+        let mut global = RNode::new(NodeType::VARDECL, name.line, name.col);
+        global.name = name.text.clone();
+        global.dataType = self.parse_array_suffix(dataType)?;
+
+        if global.dataType.is_array() {
+            if self.check(TokenKind::ASSIGN) {
+                self.error_here(format!("array {} can't have an initializer", name.text));
+                return Err(());
+            }
+        } else if self.accept(TokenKind::ASSIGN) {
+            global.right = Some(Box::new(self.parse_expression()?));
+        }
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        Ok(global)
+    }
+
+    fn parse_import(&mut self) -> PResult<RNode> {
+        let keyword = self.expect(TokenKind::KW_IMPORT, "'import'")?;
+        let path = self.expect(TokenKind::STRING, "the path of a file")?;
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        let mut import = RNode::new(NodeType::IMPORTDECL, keyword.line, keyword.col);
+        import.name = path.text;
+        Ok(import)
+    }
+
+    // The specifiers, 'extern' among them, were already read
+    fn parse_extern(&mut self) -> PResult<RNode> {
+        let dataType = self.expect_type()?;
+        let (name, dataType) = self.parse_declarator(dataType, Some("a function or variable name"))?;
+        let name = name.ok_or(())?;
+        self.check_complete(&dataType, &name)?;
+
+        // The storage of a variable is in the file that defines it
+        if !self.check(TokenKind::LPAREN) || dataType.is_function_pointer() {
+            let mut variable = RNode::new(NodeType::VARDECL, name.line, name.col);
+            variable.name = name.text.clone();
+            variable.dataType = self.parse_array_suffix(dataType)?;
+            variable.storage = StorageClass::EXTERN;
+            if self.check(TokenKind::ASSIGN) {
+                self.error_here(format!("extern variable {} can't have an initializer", name.text));
+                return Err(());
+            }
+            self.expect(TokenKind::SEMI, "';'")?;
+            return Ok(variable);
+        }
+
+        let mut function = RNode::new(NodeType::EXTERNDECL, name.line, name.col);
+        function.name = name.text.clone();
+        function.dataType = dataType;
+        function.storage = StorageClass::EXTERN;
+
+        let (params, is_variadic) = self.parse_params(false)?;
+        function.is_variadic = is_variadic;
+        if !params.is_empty() {
+            function.arguments = Some(Box::new(RList::from_nodes(params)));
+        }
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        Ok(function)
+    }
+
+    // Parses the parenthesized parameter list, returning if it ends with '...'
+    fn parse_params(&mut self, is_definition: bool) -> PResult<(Vec<RNode>, bool)> {
+        self.expect(TokenKind::LPAREN, "'('")?;
+        let mut params: Vec<RNode> = vec![];
+        let mut is_variadic = false;
+
+        if !self.check(TokenKind::RPAREN) {
+            loop {
+                if self.check(TokenKind::ELLIPSIS) {
+                    if is_definition {
+                        self.error_here("only extern functions can take a variable number of arguments".to_string());
+                        return Err(());
+                    }
+                    self.advance();
+                    is_variadic = true;
+                    break;
+                }
+
+                let at = self.peek().clone();
+                let dataType = self.expect_type()?;
+                self.check_complete(&dataType, &at)?;
+                let mut node = RNode::new(NodeType::EXPRESSION, self.peek().line, self.peek().col);
+                node.exprCode = ExprType::VARIABLE;
+                let what = if is_definition { Some("a parameter name") } else { None };
+                let (name, dataType) = self.parse_declarator(dataType, what)?;
+                node.dataType = dataType;
+                if let Some(name) = name {
+                    node.name = name.text;
+                }
+                params.push(node);
+
+                if !self.accept(TokenKind::COMMA) {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenKind::RPAREN, "')'")?;
+
+        Ok((params, is_variadic))
+    }
+
+    fn parse_function(&mut self, name: Token, dataType: DataType) -> PResult<RNode> {
+        let mut function = RNode::new(NodeType::FUNCTIONDECL, name.line, name.col);
+        function.name = name.text.clone();
+        function.dataType = dataType;
+
+        let (params, _) = self.parse_params(true)?;
+        if !params.is_empty() {
+            function.arguments = Some(Box::new(RList::from_nodes(params)));
+        }
+
+        self.expect(TokenKind::LBRACE, "'{'")?;
+        let statements = self.parse_block_body()?;
+        if !statements.is_empty() {
+            function.statements = Some(Box::new(RList::from_nodes(statements)));
+        }
+
+        Ok(function)
+    }
+
+    /*
+    ***********************************************************************
+      STATEMENTS
+    ************************************************************************
+    */
+    // Parses the statements up to and including the closing brace
+    fn parse_block_body(&mut self) -> PResult<Vec<RNode>> {
+        let mut statements: Vec<RNode> = vec![];
+
+        while !self.check(TokenKind::RBRACE) && !self.check(TokenKind::EOF) {
+            match self.parse_statement() {
+                Ok(statement) => statements.push(statement),
+                Err(()) => self.skip_statement(),
+            }
+        }
+        self.expect(TokenKind::RBRACE, "'}'")?;
+
+        Ok(statements)
+    }
+
+    fn parse_statement(&mut self) -> PResult<RNode> {
+        let start = self.peek().clone();
+
+        if self.accept(TokenKind::SEMI) {
+            let mut node = RNode::new(NodeType::STATEMENT, start.line, start.col);
+            node.stmtCode = StmtType::S_NONE;
+            return Ok(node);
+        }
+
+        if self.accept(TokenKind::KW_RETURN) {
+            let value = self.parse_expression()?;
+            self.expect(TokenKind::SEMI, "';'")?;
+
+            let mut node = RNode::new(NodeType::STATEMENT, start.line, start.col);
+            node.stmtCode = StmtType::RETURN;
+            node.left = Some(Box::new(value));
+            return Ok(node);
+        }
+
+        if self.check(TokenKind::KW_ASM) {
+            return self.parse_asm();
+        }
+
+        if self.accept(TokenKind::LBRACE) {
+            let statements = self.parse_block_body()?;
+
+            let mut node = RNode::new(NodeType::STATEMENT, start.line, start.col);
+            node.stmtCode = StmtType::BLOCK;
+            if !statements.is_empty() {
+                node.statements = Some(Box::new(RList::from_nodes(statements)));
+            }
+            return Ok(node);
+        }
+
+        // A type in front makes it a declaration in the current scope
+        if self.is_type() {
+            let dataType = self.expect_type()?;
+            let (target, dataType) = self.parse_declarator(dataType, Some("a variable name"))?;
+            let target = target.ok_or(())?;
+
+            let mut node = RNode::new(NodeType::STATEMENT, target.line, target.col);
+            node.stmtCode = StmtType::DECLARE;
+            node.name = target.text.clone();
+            node.dataType = self.parse_array_suffix(dataType)?;
+            self.check_complete(&node.dataType, &target)?;
+
+            // Arrays start out with unspecified contents, structs may too
+            if node.dataType.is_array() {
+                if self.check(TokenKind::ASSIGN) {
+                    self.error_here(format!("array {} can't have an initializer", target.text));
+                    return Err(());
+                }
+            } else if !node.dataType.is_struct() || self.check(TokenKind::ASSIGN) {
+                self.expect(TokenKind::ASSIGN, "'='")?;
+                node.right = Some(Box::new(self.parse_expression()?));
+            }
+            self.expect(TokenKind::SEMI, "';'")?;
+            return Ok(node);
+        }
+
+        // '++x;' and '--x;'
+        if self.check(TokenKind::INC) || self.check(TokenKind::DEC) {
+            let operator = self.advance();
+            let target = self.parse_unary()?;
+            let mut node = self.assignment_node(target, &start)?;
+            node.right = Some(Box::new(self.step_value(&node, &operator)?));
+            self.expect(TokenKind::SEMI, "';'")?;
+            return Ok(node);
+        }
+
+        // A store to a variable, an element, a field or through a pointer
+        if self.check(TokenKind::STAR) || self.check(TokenKind::IDENT) {
+            let target = if self.check(TokenKind::STAR) { self.parse_unary()? } else { self.parse_postfix()? };
+            let mut node = self.assignment_node(target, &start)?;
+
+            let kind = self.peek().kind;
+            let compound = COMPOUND_ASSIGNMENTS.iter().find(|(token_kind, _)| *token_kind == kind).map(|(_, op)| *op);
+            let value = if kind == TokenKind::INC || kind == TokenKind::DEC {
+                let operator = self.advance();
+                self.step_value(&node, &operator)?
+            } else if let Some(op) = compound {
+                let operator = self.advance();
+                let value = self.parse_expression()?;
+                self.compound_value(&node, op, value, &operator)?
+            } else {
+                self.expect(TokenKind::ASSIGN, "'='")?;
+                self.parse_expression()?
+            };
+            node.right = Some(Box::new(value));
+            self.expect(TokenKind::SEMI, "';'")?;
+            return Ok(node);
+        }
+
+        let found = self.peek().text.clone();
+        self.error_here(format!("expected a statement but found '{}'", found));
+        Err(())
+    }
+
+    fn parse_asm(&mut self) -> PResult<RNode> {
+        let keyword = self.expect(TokenKind::KW_ASM, "'asm'")?;
+        // Asm statements are never moved or dropped anyway
+        self.accept(TokenKind::KW_VOLATILE);
+        self.expect(TokenKind::LPAREN, "'('")?;
+
+        let mut node = RNode::new(NodeType::STATEMENT, keyword.line, keyword.col);
+        node.stmtCode = StmtType::ASM;
+        node.name = self.expect(TokenKind::STRING, "an asm template")?.text;
+        while self.check(TokenKind::STRING) {
+            node.name.push_str(&self.advance().text);
+        }
+
+        let mut operands: Vec<RNode> = vec![];
+        if self.accept(TokenKind::COLON) {
+            operands.extend(self.parse_asm_operands(true)?);
+            if self.accept(TokenKind::COLON) {
+                operands.extend(self.parse_asm_operands(false)?);
+                if self.accept(TokenKind::COLON) {
+                    node.clobbers = self.parse_clobbers()?;
+                }
+            }
+        }
+        self.expect(TokenKind::RPAREN, "')'")?;
+        self.expect(TokenKind::SEMI, "';'")?;
+
+        if !operands.is_empty() {
+            node.arguments = Some(Box::new(RList::from_nodes(operands)));
+        }
+        Ok(node)
+    }
+
+    fn parse_asm_operands(&mut self, outputs: bool) -> PResult<Vec<RNode>> {
+        let mut operands: Vec<RNode> = vec![];
+        if !self.check(TokenKind::STRING) {
+            return Ok(operands);
+        }
+
+        loop {
+            let constraint = self.expect(TokenKind::STRING, "an operand constraint")?;
+            let letter = constraint.text.trim_start_matches(['=', '+', '&']);
+            let modifiers = &constraint.text[..constraint.text.len() - letter.len()];
+            let message = if outputs && !asm_is_output(&constraint.text) {
+                Some(format!("output constraint \"{}\" must start with '=' or '+'", constraint.text))
+            } else if !outputs && asm_is_output(&constraint.text) {
+                Some(format!("input constraint \"{}\" can't start with '=' or '+'", constraint.text))
+            } else if !["", "=", "+", "=&", "+&"].contains(&modifiers) || letter.len() != 1 || !"rmabcdSD".contains(letter) {
+                Some(format!("unknown constraint \"{}\", use r, m or one of a, b, c, d, S and D", constraint.text))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                self.diagnostics.push(Diagnostic::new(constraint.line, constraint.col, message));
+                return Err(());
+            }
+
+            self.expect(TokenKind::LPAREN, "'('")?;
+            let name = self.expect(TokenKind::IDENT, "a variable name")?;
+            self.expect(TokenKind::RPAREN, "')'")?;
+
+            let mut operand = RNode::new(NodeType::EXPRESSION, name.line, name.col);
+            operand.exprCode = ExprType::VARIABLE;
+            operand.name = name.text.clone();
+            operand.constraint = constraint.text.clone();
+            operands.push(operand);
+
+            if !self.accept(TokenKind::COMMA) {
+                return Ok(operands);
+            }
+        }
+    }
+
+    fn parse_clobbers(&mut self) -> PResult<Vec<String>> {
+        let mut clobbers: Vec<String> = vec![];
+        if !self.check(TokenKind::STRING) {
+            return Ok(clobbers);
+        }
+
+        loop {
+            let clobber = self.expect(TokenKind::STRING, "a clobbered register")?;
+            let register = match clobber.text.as_str() {
+                "memory" | "cc" => clobber.text.clone(),
+                name => match asm_register(name) {
+                    Some(register @ ("%rsp" | "%rbp")) => {
+                        let message = format!("asm can't clobber {}, the frame depends on it", register);
+                        self.diagnostics.push(Diagnostic::new(clobber.line, clobber.col, message));
+                        return Err(());
+                    }
+                    Some(register) => register.to_string(),
+                    None => {
+                        let message = format!("unknown register \"{}\" in the clobber list", name);
+                        self.diagnostics.push(Diagnostic::new(clobber.line, clobber.col, message));
+                        return Err(());
+                    }
+                },
+            };
+            clobbers.push(register);
+
+            if !self.accept(TokenKind::COMMA) {
+                return Ok(clobbers);
+            }
+        }
+    }
+
+    // The ASSIGN statement storing to the target, a variable is stored by name
+    fn assignment_node(&mut self, target: RNode, start: &Token) -> PResult<RNode> {
+        let mut node = RNode::new(NodeType::STATEMENT, start.line, start.col);
+        node.stmtCode = StmtType::ASSIGN;
+
+        match (target.exprCode, target.opCode) {
+            (ExprType::VARIABLE, _) => node.name = target.name.clone(),
+            (ExprType::OPERATION, OpType::INDEX) | (ExprType::OPERATION, OpType::FIELD) | (ExprType::OPERATION, OpType::DEREF) => {
+                node.left = Some(Box::new(target))
+            }
+            _ => {
+                self.diagnostics.push(Diagnostic::new(start.line, start.col, "can't assign to this expression".to_string()));
+                return Err(());
+            }
+        }
+        Ok(node)
+    }
+
+    // The value stored by a compound assignment: the target read back and
+    // combined with the right hand side
+    fn compound_value(&mut self, assignment: &RNode, op: OpType, value: RNode, operator: &Token) -> PResult<RNode> {
+        let target = match assignment.left.as_ref() {
+            Some(target) => (**target).clone(),
+            None => {
+                let mut variable = RNode::new(NodeType::EXPRESSION, assignment.line, assignment.col);
+                variable.exprCode = ExprType::VARIABLE;
+                variable.name = assignment.name.clone();
+                variable
+            }
+        };
+
+        if contains_call(&target) {
+            let message = format!("the target of '{}' can't contain a call, it is evaluated twice", operator.text);
+            self.diagnostics.push(Diagnostic::new(operator.line, operator.col, message));
+            return Err(());
+        }
+        Ok(operation_node(op, target, Some(value), operator))
+    }
+
+    // '++' adds one and '--' subtracts one
+    fn step_value(&mut self, assignment: &RNode, operator: &Token) -> PResult<RNode> {
+        let mut one = RNode::new(NodeType::EXPRESSION, operator.line, operator.col);
+        one.exprCode = ExprType::CONSTANT;
+        one.value = 1;
+
+        let op = if operator.kind == TokenKind::INC { OpType::ADD } else { OpType::SUBTRACT };
+        self.compound_value(assignment, op, one, operator)
+    }
+
+    /*
+    ***********************************************************************
+      EXPRESSIONS
+    ************************************************************************
+    */
+    fn parse_expression(&mut self) -> PResult<RNode> {
+        self.parse_binary(0)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> PResult<RNode> {
+        if level == BINARY_LEVELS.len() {
+            return self.parse_unary();
+        }
+
+        let mut left = self.parse_binary(level + 1)?;
+        loop {
+            let kind = self.peek().kind;
+            let op = match BINARY_LEVELS[level].iter().find(|(token_kind, _)| *token_kind == kind) {
+                Some((_, op)) => *op,
+                None => break,
+            };
+
+            let operator = self.advance();
+            let right = self.parse_binary(level + 1)?;
+            left = operation_node(op, left, Some(right), &operator);
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> PResult<RNode> {
+        // '+x' is x itself
+        if self.accept(TokenKind::PLUS) {
+            return self.parse_unary();
+        }
+
+        let op = match self.peek().kind {
+            TokenKind::MINUS => Some(OpType::NEGATE),
+            TokenKind::TILDE => Some(OpType::BNOT),
+            TokenKind::BANG => Some(OpType::LNOT),
+            TokenKind::STAR => Some(OpType::DEREF),
+            TokenKind::AMP => Some(OpType::ADDRESS),
+            _ => None,
+        };
+
+        if let Some(op) = op {
+            let operator = self.advance();
+            let operand = self.parse_unary()?;
+            return Ok(operation_node(op, operand, None, &operator));
+        }
+
+        self.parse_postfix()
+    }
+
+    // A field is selected from a struct with '.' and through a pointer with
+    // '->', an element of what they give with '[' ']'
+    fn parse_postfix(&mut self) -> PResult<RNode> {
+        let mut node = self.parse_primary()?;
+
+        while self.check(TokenKind::DOT) || self.check(TokenKind::ARROW) || self.check(TokenKind::LBRACKET) {
+            let operator = self.advance();
+            // An element of an array field, like s.a[i] or p->a[i]
+            if operator.kind == TokenKind::LBRACKET {
+                let index = self.parse_expression()?;
+                self.expect(TokenKind::RBRACKET, "']'")?;
+                node = operation_node(OpType::INDEX, node, Some(index), &operator);
+                continue;
+            }
+            if operator.kind == TokenKind::ARROW {
+                node = operation_node(OpType::DEREF, node, None, &operator);
+            }
+            let field = self.expect(TokenKind::IDENT, "a field name")?;
+
+            node = operation_node(OpType::FIELD, node, None, &operator);
+            node.name = field.text.clone();
+        }
+
+        Ok(node)
+    }
+
+    // Parses '[' expr ']' after the array name, which was already consumed
+    fn parse_index(&mut self, array: &Token) -> PResult<RNode> {
+        let open = self.expect(TokenKind::LBRACKET, "'['")?;
+        let index = self.parse_expression()?;
+        self.expect(TokenKind::RBRACKET, "']'")?;
+
+        let mut node = RNode::new(NodeType::EXPRESSION, array.line, array.col);
+        node.exprCode = ExprType::VARIABLE;
+        node.name = array.text.clone();
+
+        Ok(operation_node(OpType::INDEX, node, Some(index), &open))
+    }
+
+    fn parse_primary(&mut self) -> PResult<RNode> {
+        let token = self.peek().clone();
+
+        match token.kind {
+            TokenKind::STRING => {
+                self.advance();
+                let mut node = RNode::new(NodeType::EXPRESSION, token.line, token.col);
+                node.exprCode = ExprType::STRING;
+                node.name = token.text.clone();
+                Ok(node)
+            }
+
+            TokenKind::NUMBER => {
+                self.advance();
+                let mut node = RNode::new(NodeType::EXPRESSION, token.line, token.col);
+                node.exprCode = ExprType::CONSTANT;
+                node.value = token.value;
+                // Too large for a long, the lexer kept the bits of the unsigned value
+                if token.value < 0 {
+                    node.dataType = DataType::ULONG;
+                }
+                Ok(node)
+            }
+
+            TokenKind::FLOAT => {
+                self.advance();
+                let mut node = RNode::new(NodeType::EXPRESSION, token.line, token.col);
+                node.exprCode = ExprType::FCONSTANT;
+                node.fvalue = token.text.parse::<f64>().unwrap_or(0.0);
+                Ok(node)
+            }
+
+            TokenKind::IDENT => {
+                self.advance();
+                let mut node = RNode::new(NodeType::EXPRESSION, token.line, token.col);
+                node.exprCode = ExprType::VARIABLE;
+                node.name = token.text.clone();
+
+                if self.check(TokenKind::LBRACKET) {
+                    return self.parse_index(&token);
+                }
+                if !self.accept(TokenKind::LPAREN) {
+                    return Ok(node);
+                }
+
+                // A call: the callee goes in left and the arguments in their own list
+                let mut args: Vec<RNode> = vec![];
+                if !self.check(TokenKind::RPAREN) {
+                    loop {
+                        args.push(self.parse_expression()?);
+                        if !self.accept(TokenKind::COMMA) {
+                            break;
+                        }
+                    }
+                }
+                self.expect(TokenKind::RPAREN, "')'")?;
+
+                let mut call = operation_node(OpType::FUNCTIONCALL, node, None, &token);
+                call.name = token.text.clone();
+                if !args.is_empty() {
+                    call.arguments = Some(Box::new(RList::from_nodes(args)));
+                }
+                Ok(call)
+            }
+
+            TokenKind::LPAREN => {
+                self.advance();
+                let inner = self.parse_expression()?;
+                self.expect(TokenKind::RPAREN, "')'")?;
+                Ok(inner)
+            }
+
+            _ => {
+                let found = if token.kind == TokenKind::EOF { "end of file".to_string() } else { format!("'{}'", token.text) };
+                self.error_here(format!("expected an expression but found {}", found));
+                Err(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<(usize, usize, String)> {
+        match parse_program(source) {
+            Ok(_) => vec![],
+            Err(diagnostics) => diagnostics.into_iter().map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.message)).collect(),
+        }
+    }
+
+    // The statements of the first function of the source
+    fn statements(source: &str) -> Vec<RNode> {
+        let program = parse_program(source).expect("the source doesn't parse");
+        let function = program.node.expect("no function");
+        let mut statements: Vec<RNode> = vec![];
+        let mut current = function.statements.as_deref();
+        while let Some(item) = current {
+            if let Some(node) = item.node.as_ref() {
+                statements.push((**node).clone());
+            }
+            current = item.next.as_deref();
+        }
+        statements
+    }
+
+    // An expression written out with its operations parenthesized
+    fn shape(expr: &RNode) -> String {
+        match expr.exprCode {
+            ExprType::VARIABLE | ExprType::STRING => expr.name.clone(),
+            ExprType::CONSTANT => expr.value.to_string(),
+            ExprType::FCONSTANT => expr.fvalue.to_string(),
+            ExprType::OPERATION => {
+                let mut text = format!("({:?}", expr.opCode);
+                if expr.opCode == OpType::FIELD {
+                    text.push_str(&format!(" {}", expr.name));
+                }
+                for operand in [expr.left.as_ref(), expr.right.as_ref()].into_iter().flatten() {
+                    text.push_str(&format!(" {}", shape(operand)));
+                }
+                text + ")"
+            }
+            ExprType::E_NONE => "?".to_string(),
+        }
+    }
+
+    // The shape of the value returned by 'return <expr>;'
+    fn expression(expr: &str) -> String {
+        let source = format!("long f(long a, long b, long c, long *p) {{ return {}; }}", expr);
+        let statements = statements(&source);
+        shape(statements[0].left.as_ref().expect("no returned value"))
+    }
+
+    #[test]
+    fn every_precedence_level() {
+        assert_eq!(expression("a | b ^ c"), "(BOR a (BXOR b c))");
+        assert_eq!(expression("a ^ b & c"), "(BXOR a (BAND b c))");
+        assert_eq!(expression("a & b << c"), "(BAND a (BSHL b c))");
+        assert_eq!(expression("a >> b + c"), "(BSHR a (ADD b c))");
+        assert_eq!(expression("a - b * c"), "(SUBTRACT a (MULTIPLY b c))");
+        assert_eq!(expression("a % b / c"), "(DIVIDE (MOD a b) c)");
+        assert_eq!(expression("-a * b"), "(MULTIPLY (NEGATE a) b)");
+        assert_eq!(expression("a * b | c & a + b"), "(BOR (MULTIPLY a b) (BAND c (ADD a b)))");
+    }
+
+    #[test]
+    fn binary_operators_group_to_the_left() {
+        assert_eq!(expression("a - b - c"), "(SUBTRACT (SUBTRACT a b) c)");
+        assert_eq!(expression("a << b >> c"), "(BSHR (BSHL a b) c)");
+        assert_eq!(expression("a - (b - c)"), "(SUBTRACT a (SUBTRACT b c))");
+    }
+
+    #[test]
+    fn unary_and_postfix_operators() {
+        assert_eq!(expression("+a"), "a");
+        assert_eq!(expression("~!-a"), "(BNOT (LNOT (NEGATE a)))");
+        assert_eq!(expression("*p + 1"), "(ADD (DEREF p) 1)");
+        assert_eq!(expression("-*&a"), "(NEGATE (DEREF (ADDRESS a)))");
+        assert_eq!(expression("p[a + 1] * 2"), "(MULTIPLY (INDEX p (ADD a 1)) 2)");
+        assert_eq!(expression("&p->x.y[2]"), "(ADDRESS (INDEX (FIELD y (FIELD x (DEREF p))) 2))");
+    }
+
+    #[test]
+    fn assignments_become_stores() {
+        let statements = statements("long f(long a, long *p) { a <<= 2; p[1] += a; *p = 3; a++; --a; return a; }");
+        assert_eq!(statements[0].name, "a");
+        assert_eq!(shape(statements[0].right.as_ref().unwrap()), "(BSHL a 2)");
+        assert_eq!(shape(statements[1].left.as_ref().unwrap()), "(INDEX p 1)");
+        assert_eq!(shape(statements[1].right.as_ref().unwrap()), "(ADD (INDEX p 1) a)");
+        assert_eq!(shape(statements[2].left.as_ref().unwrap()), "(DEREF p)");
+        assert_eq!(shape(statements[3].right.as_ref().unwrap()), "(ADD a 1)");
+        assert_eq!(shape(statements[4].right.as_ref().unwrap()), "(SUBTRACT a 1)");
+    }
+
+    #[test]
+    fn nodes_know_where_they_were_written() {
+        // A declaration is where its name is, an operation where its operator is
+        let statements = statements("long f(long a) {\n  long x = a +\n    2;\n  return x;\n}");
+        assert_eq!((statements[0].line, statements[0].col), (2, 8));
+        let value = statements[0].right.as_ref().unwrap();
+        assert_eq!((value.line, value.col), (2, 14));
+        assert_eq!((value.right.as_ref().unwrap().line, value.right.as_ref().unwrap().col), (3, 5));
+        assert_eq!((statements[1].line, statements[1].col), (4, 3));
+    }
+
+    #[test]
+    fn a_broken_statement_is_skipped_to_its_semicolon() {
+        let source = "long f(long a) {\n  long x = ;\n  a = a + * ;\n  long y = 1;\n  return a b;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (2, 12, "expected an expression but found ';'".to_string()),
+                (3, 13, "expected an expression but found ';'".to_string()),
+                (5, 12, "expected ';' but found 'b'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_broken_statement_stops_at_the_closing_brace() {
+        // The missing ';' doesn't swallow the end of the function, so g is parsed and checked too
+        let source = "long f() { long x = 1 }\nlong g() { return +; }";
+        assert_eq!(
+            errors(source),
+            vec![
+                (1, 23, "expected ';' but found '}'".to_string()),
+                (2, 20, "expected an expression but found ';'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_broken_declaration_is_skipped_whole() {
+        // The body of f is skipped with its nested braces, the global ends at its ';'
+        let source = "long f(long) { { long x = 1; } }\nlong g = ;\nlong h() { return 1 }\nlong k = 2;";
+        assert_eq!(
+            errors(source),
+            vec![
+                (1, 12, "expected a parameter name but found ')'".to_string()),
+                (2, 10, "expected an expression but found ';'".to_string()),
+                (3, 21, "expected ';' but found '}'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexer_and_parser_problems_are_reported_together() {
+        let source = "long f() { long x = 1 @ 2; return \"a\\q\"; }\nlong g( { }";
+        let messages: Vec<String> = errors(source).into_iter().map(|(_, _, message)| message).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unexpected character '@'".to_string(),
+                "unknown escape sequence \\q".to_string(),
+                "expected ';' but found '2'".to_string(),
+                "expected a type but found '{'".to_string(),
+            ]
+        );
+    }
+}
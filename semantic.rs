@@ -0,0 +1,668 @@
+/*
+***********************************************************************
+  SEMANTIC.RS : CHECKS RUN OVER THE RLIST OF FUNCTIONS BEFORE CODEGEN.
+  EVERY PROBLEM IS REPORTED, CODEGEN ONLY RUNS ON A CLEAN PROGRAM.
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+use crate::diagnostic::Diagnostic;
+use crate::expression::*;
+
+struct funcInfo {
+    name: String,
+    arity: usize,
+    // calls can pass more arguments than the arity
+    is_variadic: bool,
+    // every declaration of the function must have the same type
+    signature: DataType,
+    storage: StorageClass,
+    line: usize,
+    col: usize,
+}
+
+struct globalInfo {
+    name: String,
+    dataType: DataType,
+    storage: StorageClass,
+    line: usize,
+    col: usize,
+}
+
+struct checker {
+    functions: Vec<funcInfo>,
+    globals: Vec<globalInfo>,
+    diagnostics: Vec<Diagnostic>,
+    // Bytes of the locals declared so far in the function being checked
+    frame_bytes: i64,
+}
+
+// Slots are addressed with 32 bit displacements from the frame base, this
+// leaves room for the temporaries and saved registers next to the locals
+const MAX_LOCAL_BYTES: i64 = 1 << 30;
+
+/*
+***********************************************************************
+  FUNCTION TO CHECK A WHOLE PROGRAM, RETURNING EVERY PROBLEM FOUND
+************************************************************************
+*/
+pub fn check_program(worklist: &RList) -> Vec<Diagnostic> {
+    let mut check = checker {
+        functions: vec![],
+        globals: vec![],
+        diagnostics: vec![],
+        frame_bytes: 0,
+    };
+
+    // Build the function and global tables first so functions can use
+    // anything declared later in the file
+    let mut current = Some(worklist);
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            match node.type_ {
+                NodeType::FUNCTIONDECL | NodeType::EXTERNDECL => check.declare_function(node),
+                NodeType::VARDECL => check.declare_global(node),
+                _ => {}
+            }
+        }
+        current = item.next.as_deref();
+    }
+
+    let mut current = Some(worklist);
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            match node.type_ {
+                NodeType::FUNCTIONDECL => check.check_function(node),
+                NodeType::VARDECL => check.check_global_address(node),
+                _ => {}
+            }
+        }
+        current = item.next.as_deref();
+    }
+
+    check.diagnostics
+}
+
+fn list_nodes(list: Option<&RList>) -> Vec<&RNode> {
+    let mut nodes: Vec<&RNode> = vec![];
+    let mut current = list;
+
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            nodes.push(node);
+        }
+        current = item.next.as_deref();
+    }
+
+    nodes
+}
+
+fn collect_defined(statements: Option<&RList>, defined: &mut Vec<String>) {
+    for statement in list_nodes(statements) {
+        match statement.stmtCode {
+            StmtType::ASSIGN | StmtType::DECLARE if !statement.name.is_empty() && !defined.contains(&statement.name) => {
+                defined.push(statement.name.clone());
+            }
+            StmtType::BLOCK => collect_defined(statement.statements.as_deref(), defined),
+            _ => {}
+        }
+    }
+}
+
+impl checker {
+    fn error(&mut self, node: &RNode, message: String) {
+        self.diagnostics.push(Diagnostic::new(node.line, node.col, message));
+    }
+
+    fn lookup_function(&self, name: &str) -> Option<&funcInfo> {
+        self.functions.iter().find(|func| func.name == name)
+    }
+
+    // Functions and globals share one namespace. Gives the position of the
+    // earlier declaration, its type and whether it was an extern one.
+    fn previous_definition(&self, name: &str) -> Option<(usize, usize, DataType, bool)> {
+        if let Some(func) = self.lookup_function(name) {
+            return Some((func.line, func.col, func.signature.clone(), func.storage == StorageClass::EXTERN));
+        }
+        self.globals
+            .iter()
+            .find(|global| global.name == name)
+            .map(|global| (global.line, global.col, global.dataType.clone(), global.storage == StorageClass::EXTERN))
+    }
+
+    // A name may be declared extern any number of times and defined once,
+    // always with the same type. Only the first declaration can make it
+    // static. Returns if the name was declared before.
+    fn redeclared(&mut self, node: &RNode, what: &str, dataType: &DataType) -> bool {
+        let (line, col, previous, was_extern) = match self.previous_definition(&node.name) {
+            Some(previous) => previous,
+            None => return false,
+        };
+
+        if !was_extern && node.storage != StorageClass::EXTERN {
+            let message = format!("{} {} is already defined at {}:{}", what, node.name, line, col);
+            self.error(node, message);
+        } else if node.storage == StorageClass::STATIC {
+            let message = format!("{} {} can't be static, it was declared extern at {}:{}", what, node.name, line, col);
+            self.error(node, message);
+        } else if previous != *dataType {
+            let message = format!(
+                "{} {} is declared as {} but was {} at {}:{}",
+                what,
+                node.name,
+                dataType.type_name(),
+                previous.type_name(),
+                line,
+                col
+            );
+            self.error(node, message);
+        }
+        true
+    }
+
+    fn declare_function(&mut self, node: &RNode) {
+        let signature = function_type(node);
+        if self.redeclared(node, "function", &signature) {
+            // Later conflicts are reported against the definition
+            if node.storage != StorageClass::EXTERN {
+                if let Some(func) = self.functions.iter_mut().find(|func| func.name == node.name) {
+                    func.storage = node.storage;
+                    func.line = node.line;
+                    func.col = node.col;
+                }
+            }
+            return;
+        }
+
+        self.functions.push(funcInfo {
+            name: node.name.clone(),
+            arity: list_nodes(node.arguments.as_deref()).len(),
+            is_variadic: node.is_variadic,
+            signature,
+            storage: node.storage,
+            line: node.line,
+            col: node.col,
+        });
+    }
+
+    fn declare_global(&mut self, node: &RNode) {
+        let redeclared = self.redeclared(node, "global", &node.dataType);
+
+        // The initial value is written into .data, so it must be known now
+        if let Some(right) = node.right.as_ref() {
+            if node.dataType.is_struct() {
+                let message = format!("struct global {} can't have an initializer", node.name);
+                self.error(right, message);
+            } else if right.exprCode == ExprType::STRING || address_constant(right).is_some() {
+                // A pointer the assembler or the linker fills in
+            } else if has_double_constant(right) {
+                if fold_double(right).is_none() {
+                    let message = format!("initializer of global {} is not a constant expression", node.name);
+                    self.error(right, message);
+                }
+            } else {
+                let message = match fold_integer(right) {
+                    Ok(_) => None,
+                    Err(foldError::NotConstant) => Some(format!("initializer of global {} is not a constant expression", node.name)),
+                    Err(foldError::DivisionByZero) => Some(format!("division by zero in the initializer of global {}", node.name)),
+                    Err(foldError::Overflow) => Some(format!("initializer of global {} overflows", node.name)),
+                };
+                if let Some(message) = message {
+                    self.error(right, message);
+                }
+            }
+        }
+
+        if redeclared {
+            if node.storage != StorageClass::EXTERN {
+                if let Some(global) = self.globals.iter_mut().find(|global| global.name == node.name) {
+                    global.storage = node.storage;
+                    global.line = node.line;
+                    global.col = node.col;
+                }
+            }
+            return;
+        }
+
+        self.globals.push(globalInfo {
+            name: node.name.clone(),
+            dataType: node.dataType.clone(),
+            storage: node.storage,
+            line: node.line,
+            col: node.col,
+        });
+    }
+
+    // Functions may come later in the file than a global holding their address
+    fn check_global_address(&mut self, node: &RNode) {
+        if let Some(right) = node.right.as_ref() {
+            let name = address_constant(right);
+            if name.is_some_and(|name| self.lookup_function(name).is_none()) {
+                let message = format!("initializer of global {} is not a constant expression", node.name);
+                self.error(right, message);
+            }
+        }
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO CHECK THE PARAMETERS AND BODY OF A FUNCTION
+    ************************************************************************
+    */
+    fn check_function(&mut self, function: &RNode) {
+        // Variables visible so far, one list per open scope. Globals are the
+        // outermost scope, parameters are assigned on entry and live in the
+        // function's outermost scope where they shadow globals.
+        let global_scope = self.globals.iter().map(|global| (global.name.clone(), global.dataType.clone())).collect();
+        let mut scopes: scopeList = vec![global_scope, vec![]];
+        self.frame_bytes = 0;
+
+        for param in list_nodes(function.arguments.as_deref()) {
+            if scopes[1].iter().any(|(name, _)| *name == param.name) {
+                let message = format!("parameter {} of {} is declared twice", param.name, function.name);
+                self.error(param, message);
+            } else {
+                scopes[1].push((param.name.clone(), param.dataType.clone()));
+            }
+        }
+
+        // Every variable assigned anywhere, to tell "used too early" from "never defined"
+        let mut defined: Vec<String> = scopes.concat().into_iter().map(|(name, _)| name).collect();
+        collect_defined(function.statements.as_deref(), &mut defined);
+
+        let returns = self.check_statements(function.statements.as_deref(), &mut scopes, &defined);
+        if !returns {
+            let message = format!("function {} does not end with a return", function.name);
+            self.error(function, message);
+        }
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO CHECK A LIST OF STATEMENTS IN THE INNERMOST SCOPE.
+      RETURNS TRUE IF THE LAST STATEMENT RETURNS.
+    ************************************************************************
+    */
+    fn check_statements(&mut self, statements: Option<&RList>, scopes: &mut scopeList, defined: &[String]) -> bool {
+        let mut returns = false;
+
+        for statement in list_nodes(statements) {
+            match statement.stmtCode {
+                StmtType::ASSIGN => {
+                    if let Some(right) = statement.right.as_ref() {
+                        self.check_expression(right, scopes, defined);
+                    }
+
+                    if let Some(target) = statement.left.as_ref() {
+                        // A store into an array element or through a pointer
+                        self.check_expression(target, scopes, defined);
+                    } else {
+                        match lookup_visible(scopes, &statement.name) {
+                            Some(dataType) if dataType.is_array() => {
+                                let message = format!("array {} can't be assigned as a whole", statement.name);
+                                self.error(statement, message);
+                            }
+                            Some(_) => {}
+                            // Assigning a name that isn't visible declares it in the current scope
+                            None => {
+                                if let Some(scope) = scopes.last_mut() {
+                                    scope.push((statement.name.clone(), DataType::LONG));
+                                }
+                            }
+                        }
+                    }
+                    returns = false;
+                }
+
+                StmtType::DECLARE => {
+                    if let Some(right) = statement.right.as_ref() {
+                        self.check_expression(right, scopes, defined);
+                    }
+                    let declared = scopes.last().is_some_and(|scope| scope.iter().any(|(name, _)| *name == statement.name));
+                    if declared {
+                        let message = format!("variable {} is already declared in this scope", statement.name);
+                        self.error(statement, message);
+                    } else if let Some(scope) = scopes.last_mut() {
+                        scope.push((statement.name.clone(), statement.dataType.clone()));
+                    }
+
+                    let before = self.frame_bytes;
+                    self.frame_bytes = before.saturating_add(statement.dataType.size());
+                    if before <= MAX_LOCAL_BYTES && self.frame_bytes > MAX_LOCAL_BYTES {
+                        let message = format!("local {} makes the stack frame larger than {} bytes", statement.name, MAX_LOCAL_BYTES);
+                        self.error(statement, message);
+                    }
+                    returns = false;
+                }
+
+                StmtType::RETURN => {
+                    if let Some(left) = statement.left.as_ref() {
+                        self.check_expression(left, scopes, defined);
+                    }
+                    returns = true;
+                }
+
+                StmtType::BLOCK => {
+                    scopes.push(vec![]);
+                    returns = self.check_statements(statement.statements.as_deref(), scopes, defined);
+                    scopes.pop();
+                }
+
+                StmtType::ASM => {
+                    self.check_asm(statement, scopes, defined);
+                    returns = false;
+                }
+
+                StmtType::S_NONE => {}
+            }
+        }
+
+        returns
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO CHECK THE OPERANDS OF AN ASM, THE OPERANDS ITS TEMPLATE
+      REFERS TO AND THE REGISTERS ITS CONSTRAINTS AND CLOBBERS NAME
+    ************************************************************************
+    */
+    fn check_asm(&mut self, statement: &RNode, scopes: &scopeList, defined: &[String]) {
+        let operands = list_nodes(statement.arguments.as_deref());
+
+        for operand in operands.iter() {
+            if lookup_visible(scopes, &operand.name).is_some() {
+                continue;
+            }
+            if self.lookup_function(&operand.name).is_some() {
+                self.error(operand, format!("asm operand {} must be a variable", operand.name));
+            } else {
+                self.missing_variable(operand, defined);
+            }
+        }
+
+        match asm_template(&statement.name) {
+            Ok(pieces) => {
+                for piece in pieces {
+                    if let asmPiece::Operand(index, _) = piece {
+                        if index >= operands.len() {
+                            let message = format!("asm template refers to operand %{} but there are {} operands", index, operands.len());
+                            self.error(statement, message);
+                        }
+                    }
+                }
+            }
+            Err(message) => self.error(statement, message),
+        }
+
+        // An output and an input can share a register, the inputs are read
+        // before the outputs are written
+        let mut written: Vec<&str> = vec![];
+        let mut read: Vec<&str> = vec![];
+        for operand in operands.iter() {
+            let register = match asm_fixed_register(&operand.constraint) {
+                Some(register) => register,
+                None => continue,
+            };
+            if statement.clobbers.iter().any(|clobber| clobber == register) {
+                self.error(operand, format!("{} is bound to an operand of the asm and can't be clobbered", register));
+            }
+            if asm_is_output(&operand.constraint) && written.contains(&register) {
+                self.error(operand, format!("{} is bound to two outputs of the asm", register));
+            }
+            if asm_is_read(&operand.constraint) && read.contains(&register) {
+                self.error(operand, format!("{} is bound to two inputs of the asm", register));
+            }
+            if asm_is_output(&operand.constraint) {
+                written.push(register);
+            }
+            if asm_is_read(&operand.constraint) {
+                read.push(register);
+            }
+        }
+
+        let free = asm_unbound_registers(&statement.clobbers, &operands).len();
+        let demand = asm_register_demand(&operands);
+        if demand > free {
+            let message = format!("not enough free registers for the operands of the asm, it needs {} and has {}", demand, free);
+            self.error(statement, message);
+        }
+    }
+
+    fn missing_variable(&mut self, expr: &RNode, defined: &[String]) {
+        let message = if defined.contains(&expr.name) {
+            format!("variable {} is used before it is assigned or outside its scope", expr.name)
+        } else {
+            format!("undefined variable {}", expr.name)
+        };
+        self.error(expr, message);
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO CHECK THE VARIABLES AND CALLS OF AN EXPRESSION
+    ************************************************************************
+    */
+    fn check_expression(&mut self, expr: &RNode, scopes: &scopeList, defined: &[String]) {
+        match expr.exprCode {
+            // An array used as a value is the address of its first element
+            // A function name used as a value is its address
+            ExprType::VARIABLE => {
+                if lookup_visible(scopes, &expr.name).is_none() && self.lookup_function(&expr.name).is_none() {
+                    self.missing_variable(expr, defined);
+                }
+            }
+
+            ExprType::OPERATION if expr.opCode == OpType::INDEX => {
+                // An array field or anything else that isn't a variable is
+                // checked like any expression, the type checker checks it
+                // can be indexed
+                if let Some(array) = expr.left.as_ref().filter(|array| array.exprCode != ExprType::VARIABLE) {
+                    self.check_expression(array, scopes, defined);
+                } else if let Some(array) = expr.left.as_ref() {
+                    match lookup_visible(scopes, &array.name) {
+                        Some(DataType::ARRAY(_, count)) => {
+                            // A constant index can be checked right away
+                            let index = expr.right.as_ref().and_then(|right| fold_constant(right));
+                            if let Some(index) = index {
+                                if index < 0 || index >= *count {
+                                    let message = format!(
+                                        "index {} is out of range for array {} of {} elements",
+                                        index, array.name, count
+                                    );
+                                    self.error(expr, message);
+                                }
+                            }
+                        }
+                        Some(DataType::POINTER(_)) => {}
+                        Some(_) => {
+                            let message = format!("{} is not an array or a pointer", array.name);
+                            self.error(array, message);
+                        }
+                        None => self.missing_variable(array, defined),
+                    }
+                }
+
+                if let Some(right) = expr.right.as_ref() {
+                    self.check_expression(right, scopes, defined);
+                }
+            }
+
+            ExprType::OPERATION if expr.opCode == OpType::ADDRESS || expr.opCode == OpType::DEREF => {
+                if let Some(operand) = expr.left.as_ref() {
+                    if expr.opCode == OpType::ADDRESS {
+                        let in_memory = operand.exprCode == ExprType::VARIABLE
+                            || operand.opCode == OpType::INDEX
+                            || operand.opCode == OpType::DEREF
+                            || operand.opCode == OpType::FIELD;
+                        if !in_memory {
+                            self.error(expr, "can only take the address of a variable or an element".to_string());
+                        }
+                    } else if operand.exprCode == ExprType::VARIABLE {
+                        let dataType = lookup_visible(scopes, &operand.name).cloned();
+                        if dataType.is_some_and(|dataType| !dataType.is_pointer() && !dataType.is_array()) {
+                            self.error(operand, format!("{} is not a pointer", operand.name));
+                        }
+                    }
+                    self.check_expression(operand, scopes, defined);
+                }
+            }
+
+            ExprType::OPERATION if expr.opCode == OpType::FUNCTIONCALL => {
+                let callee = match expr.left.as_ref() {
+                    Some(left) => left.name.clone(),
+                    None => expr.name.clone(),
+                };
+                let args = list_nodes(expr.arguments.as_deref());
+
+                // A variable shadows a function of the same name, the call goes
+                // through the function pointer it holds
+                let signature = match lookup_visible(scopes, &callee) {
+                    Some(DataType::POINTER(target)) => match &**target {
+                        DataType::FUNCTION(_, params, is_variadic) => Ok((params.len(), *is_variadic)),
+                        _ => Err(format!("{} is not a function or a function pointer", callee)),
+                    },
+                    Some(_) => Err(format!("{} is not a function or a function pointer", callee)),
+                    None => match self.lookup_function(&callee) {
+                        Some(func) => Ok((func.arity, func.is_variadic)),
+                        None => Err(format!("call to undefined function {}", callee)),
+                    },
+                };
+                match signature {
+                    Err(message) => {
+                        self.error(expr, message);
+                    }
+                    Ok((arity, true)) if args.len() < arity => {
+                        let message = format!(
+                            "{} expects at least {} argument(s) but is called with {}",
+                            callee,
+                            arity,
+                            args.len()
+                        );
+                        self.error(expr, message);
+                    }
+                    Ok((arity, false)) if arity != args.len() => {
+                        let message = format!(
+                            "{} expects {} argument(s) but is called with {}",
+                            callee,
+                            arity,
+                            args.len()
+                        );
+                        self.error(expr, message);
+                    }
+                    Ok(_) => {}
+                }
+
+                for arg in args {
+                    self.check_expression(arg, scopes, defined);
+                }
+            }
+
+            _ => {
+                if let Some(left) = expr.left.as_ref() {
+                    self.check_expression(left, scopes, defined);
+                }
+                if let Some(right) = expr.right.as_ref() {
+                    self.check_expression(right, scopes, defined);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn errors(source: &str) -> Vec<(usize, usize, String)> {
+        let program = parse_program(source).expect("the source doesn't parse");
+        check_program(&program).into_iter().map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.message)).collect()
+    }
+
+    #[test]
+    fn functions_and_parameters_are_declared_once() {
+        let source = "long f(long a) { return a; }\nlong f(long b) { return b; }\nlong g(long a, long b, long a) { return a + b; }";
+        assert_eq!(
+            errors(source),
+            vec![
+                (2, 6, "function f is already defined at 1:6".to_string()),
+                (3, 29, "parameter a of g is declared twice".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_must_be_assigned_before_they_are_used() {
+        // d is assigned too late and e only in a block that has ended
+        let source = "long f() {\n  long a = b;\n  long c = d;\n  d = 1;\n  {\n    long e = 2;\n  }\n  return a + c + d + e;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (2, 12, "undefined variable b".to_string()),
+                (3, 12, "variable d is used before it is assigned or outside its scope".to_string()),
+                (8, 22, "variable e is used before it is assigned or outside its scope".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn calls_match_the_arity_of_their_function() {
+        let source = "extern int printf(char *fmt, ...);\nlong two(long a, long b) { return a + b; }\nlong f() {\n  long x = two(1);\n  \
+                      long y = two(1, 2, 3);\n  int r = printf();\n  r = printf(\"%ld\", x + y);\n  return missing(x);\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (4, 12, "two expects 2 argument(s) but is called with 1".to_string()),
+                (5, 12, "two expects 2 argument(s) but is called with 3".to_string()),
+                (6, 11, "printf expects at least 1 argument(s) but is called with 0".to_string()),
+                (8, 10, "call to undefined function missing".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_end_with_a_return() {
+        // A return at the end of a block counts, one followed by another statement doesn't
+        let source = "long f(long a) {\n  long b = a;\n}\nlong g(long a) {\n  {\n    return a;\n  }\n}\nlong h(long a) {\n  return a;\n  a = 2;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (1, 6, "function f does not end with a return".to_string()),
+                (9, 6, "function h does not end with a return".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn integer_initializers_are_folded_exactly() {
+        // Unsigned long wraps around, a double constant makes the initializer a double
+        let source = "long x = 1 / 0;\nlong v = 9223372036854775807 + 1;\nlong m = 5 % (2 - 2);\nlong n = -(-9223372036854775807 - 1);\n\
+                      long s = 1 << 64;\nlong k = x + 1;\ndouble d = 7 / 2;\nlong f = 2.5 * 2;\nunsigned long u = 18446744073709551615 + 1;";
+        assert_eq!(
+            errors(source),
+            vec![
+                (1, 12, "division by zero in the initializer of global x".to_string()),
+                (2, 30, "initializer of global v overflows".to_string()),
+                (3, 12, "division by zero in the initializer of global m".to_string()),
+                (4, 10, "initializer of global n overflows".to_string()),
+                (5, 12, "initializer of global s overflows".to_string()),
+                (6, 12, "initializer of global k is not a constant expression".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_must_fit_the_stack_frame() {
+        // Only the declaration that crosses the limit is reported, and each function has its own frame
+        let source = "long f() {\n  long small[10];\n  long a[99999999999999];\n  long b[3];\n  return 0;\n}\n\
+                      long g() {\n  char c[1073741824];\n  char d[1];\n  return 0;\n}\n\
+                      long h() {\n  char e[1073741824];\n  return 0;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (3, 8, "local a makes the stack frame larger than 1073741824 bytes".to_string()),
+                (9, 8, "local d makes the stack frame larger than 1073741824 bytes".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+extern int printf(char *fmt, ...);
+
+long total;
+
+// Every caller saved register is clobbered, the operands get callee saved ones
+long mix(long a, long b, long c) {
+  long out = 0;
+  asm("movq %1, %0\n imulq %2, %0\n addq %3, %0\n movq $1, %%rax\n movq $2, %%r11"
+      : "=r"(out)
+      : "r"(a), "r"(b), "r"(c)
+      : "rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11");
+  return out;
+}
+
+// Some left to the operands, the rest from the callee saved ones
+long count(long n) {
+  long steps = 0;
+  asm(".Lcount%=: addq %3, %0\n addq $1, %1\n cmpq $10, %1\n jl .Lcount%=\n addq %0, %2"
+      : "+r"(steps), "+r"(n), "+m"(total)
+      : "r"(n)
+      : "rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9");
+  return steps;
+}
+
+// Moves written in an asm are kept, even those that look redundant
+long kept(long a, long b) {
+  long target = 0;
+  long *inner = &target;
+  long **p = &inner;
+  asm("movq (%0), %0\nmovq %0, (%0)" : "+r"(p));
+  asm("movq %0, %1\n movq %1, %0" : "+r"(a), "+r"(b));
+  return (*p - inner) * 100 + a * 10 + b;
+}
+
+int main() {
+  long m = mix(6, 7, 8);
+  total = 100;
+  long c = count(4);
+  int r = printf("%ld %ld %ld %ld\n", m, c, total, mix(c, 2, m));
+  r = printf("%ld\n", kept(3, 4));
+  return 0;
+}
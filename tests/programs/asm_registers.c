@@ -0,0 +1,13 @@
+// Nothing is left for the "r" operand once every free register is clobbered
+long spill(long a) {
+  long out = 0;
+  asm("movq %1, %0"
+      : "=r"(out)
+      : "r"(a)
+      : "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13", "r14");
+  return out;
+}
+
+int main() {
+  return spill(1);
+}
@@ -0,0 +1,18 @@
+extern int printf(char *fmt, ...);
+
+struct buffer {
+  long used;
+  long a[3];
+};
+
+int main() {
+  struct buffer s;
+  s.used = 3;
+  long i = 2;
+  s.a[i] = 7;
+  int r = printf("%ld\n", s.a[i]);
+  i = 3;
+  s.a[i] = 8;
+  r = printf("%ld\n", s.a[i]);
+  return 1;
+}
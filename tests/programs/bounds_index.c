@@ -0,0 +1,14 @@
+extern int printf(char *fmt, ...);
+
+long pick(long i) {
+  long values[4];
+  values[0] = 10;
+  values[3] = 13;
+  return values[i];
+}
+
+int main() {
+  int r = printf("%ld\n", pick(3));
+  r = printf("%ld\n", pick(4));
+  return 1;
+}
@@ -0,0 +1,15 @@
+extern int printf(char *fmt, ...);
+
+long pick(long i) {
+  long values[4];
+  values[0] = 10;
+  values[3] = 13;
+  return values[i];
+}
+
+// A negative index is a huge unsigned one, above every length
+int main() {
+  int r = printf("%ld\n", pick(0));
+  r = printf("%ld\n", pick(-1));
+  return 1;
+}
@@ -0,0 +1,8 @@
+// Imported by imports.c, compiled and linked with it
+long count = 10;
+static long step = 3;
+
+long bump(long times) {
+  count = count + times * step;
+  return count;
+}
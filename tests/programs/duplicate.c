@@ -0,0 +1,4 @@
+// Defines bump again, linking it with counter.c is an error
+long bump(long times) {
+  return times;
+}
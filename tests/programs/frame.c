@@ -0,0 +1,31 @@
+extern int printf(char *fmt, ...);
+
+// A leaf function with arguments on the stack, read from above the return
+// address when there is no frame pointer
+long weigh(long a, long b, long c, long d, long e, long f, long g, int h, char i) {
+  long local = a * 1 + b * 2 + c * 3 + d * 4;
+  long more = e * 5 + f * 6 + g * 7 + h * 8;
+  return local + more + i * 9;
+}
+
+long spread(long a, long b, long c, long d, long e, long f, long g, long h) {
+  long first = g - h;
+  long arr[4];
+  arr[0] = a;
+  arr[3] = h;
+  return first * 100 + arr[0] + arr[3];
+}
+
+// Not a leaf, keeps its frame pointer
+long twice_weighed(long x) {
+  long w = weigh(x, x, x, x, x, x, x, 1, 2);
+  return w * 2;
+}
+
+int main() {
+  long w = weigh(1, 2, 3, 4, 5, 6, 7, 8, -1);
+  long s = spread(1, 2, 3, 4, 5, 6, 70, 8);
+  long t = twice_weighed(3);
+  int r = printf("%ld %ld %ld\n", w, s, t);
+  return 0;
+}
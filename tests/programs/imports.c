@@ -0,0 +1,10 @@
+import "counter.c";
+
+extern int printf(char *fmt, ...);
+
+int main() {
+  long first = bump(1);
+  long second = bump(2);
+  int r = printf("%ld %ld %ld\n", first, second, count);
+  return 0;
+}
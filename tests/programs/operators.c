@@ -0,0 +1,90 @@
+extern int printf(char *fmt, ...);
+
+struct counter { long hits; int misses; unsigned char small; };
+
+long seven() { return 7; }
+
+int main() {
+  // % takes the sign of the dividend, unsigned operands wrap first
+  long a = -17;
+  long b = 5;
+  unsigned long ua = 17;
+  unsigned int ub = 5;
+  unsigned long big = 18446744073709551615;
+  int r = printf("mod %ld %d %d %d\n", a % b, 17 % -5, -17 % -5, 17 % 5);
+  r = printf("umod %lu %u %lu\n", ua % 5, ub % 3, big % 10);
+
+  // ~, ! and unary plus
+  long zero = 0;
+  unsigned char byte = 5;
+  r = printf("bnot %ld %ld %d\n", ~a, ~zero, ~byte);
+  r = printf("lnot %d %d %d %d\n", !a, !zero, !!b, !seven());
+  r = printf("plus %ld %ld %d\n", +a, -+b, +byte);
+
+  // Compound assignments
+  long c = 100;
+  c += 5;
+  c -= 15;
+  c *= 3;
+  c /= 4;
+  c %= 13;
+  r = printf("compound %ld\n", c);
+  long bits = 6;
+  bits &= 3;
+  bits |= 8;
+  bits ^= 15;
+  r = printf("bitwise %ld\n", bits);
+  long s = 3;
+  s <<= 4;
+  long t = -64;
+  t >>= 3;
+  unsigned long u = 18446744073709551615;
+  u >>= 60;
+  unsigned int narrow = 1;
+  narrow <<= 31;
+  r = printf("shift %ld %ld %lu %u\n", s, t, u, narrow);
+
+  // ++ and -- on variables, elements, fields and through pointers
+  long i = 10;
+  i++;
+  i++;
+  i--;
+  ++i;
+  long arr[3];
+  arr[0] = 1;
+  arr[1] = 2;
+  arr[2] = 3;
+  long k = 2;
+  arr[k]++;
+  arr[0]--;
+  --arr[k];
+  ++arr[k];
+  arr[1] += arr[k];
+  arr[k] <<= 2;
+  r = printf("elements %ld %ld %ld %ld\n", i, arr[0], arr[1], arr[2]);
+
+  struct counter cnt;
+  cnt.hits = 41;
+  cnt.misses = 0;
+  cnt.small = 255;
+  cnt.hits++;
+  cnt.misses--;
+  ++cnt.hits;
+  --cnt.hits;
+  cnt.small++;
+  struct counter *pc = &cnt;
+  pc->hits *= 2;
+  pc->misses -= 2;
+  r = printf("fields %ld %d %d\n", cnt.hits, cnt.misses, cnt.small);
+
+  long v = 5;
+  long *p = &v;
+  *p += 10;
+  ++*p;
+  ++*p;
+  --*p;
+  *p >>= 1;
+  *p %= 5;
+  r = printf("deref %ld\n", v);
+  return 0;
+}
@@ -0,0 +1,12 @@
+extern int printf(char *fmt, ...);
+
+long divide(long a, long b) {
+  return a / b;
+}
+
+int main() {
+  long min = -9223372036854775807 - 1;
+  int r = printf("%ld\n", divide(min, 2));
+  r = printf("%ld\n", divide(min, -1));
+  return 1;
+}
@@ -0,0 +1,12 @@
+extern int printf(char *fmt, ...);
+
+int add(int a, int b) {
+  int sum = a + b;
+  return sum;
+}
+
+int main() {
+  int r = printf("%d\n", add(2147483646, 1));
+  r = printf("%d\n", add(2147483647, 1));
+  return 1;
+}
@@ -0,0 +1,11 @@
+extern int printf(char *fmt, ...);
+
+long remainder(long a, long b) {
+  return a % b;
+}
+
+int main() {
+  int r = printf("%ld\n", remainder(17, 5));
+  r = printf("%ld\n", remainder(17, 0));
+  return 1;
+}
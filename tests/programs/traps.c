@@ -0,0 +1,16 @@
+extern int printf(char *fmt, ...);
+extern int exit(int status);
+
+// The trap routines of the checked programs, they print what they are
+// given and end the program
+long __arith_trap(char *function, long line, long col, char *reason) {
+  int r = printf("%s:%ld:%ld: %s\n", function, line, col, reason);
+  r = exit(0);
+  return 0;
+}
+
+long __bounds_trap(long index, long length) {
+  int r = printf("index %ld out of %ld\n", index, length);
+  r = exit(0);
+  return 0;
+}
@@ -0,0 +1,19 @@
+extern int printf(char *fmt, ...);
+
+// Folded as unsigned long when a constant is above LONG_MAX
+unsigned long half = 18446744073709551615 / 2;
+unsigned long top = 18446744073709551615 >> 60;
+unsigned long digit = 18446744073709551615 % 10;
+unsigned long wrapped = 18446744073709551615 + 2;
+unsigned long negated = -18446744073709551615;
+long mixed = (9223372036854775808 - 1) / 3;
+long arithmetic = -64 >> 3;
+
+int main() {
+  unsigned long h = 18446744073709551615 / 2;
+  unsigned long t = 18446744073709551615 >> 60;
+  int r = printf("%lu %lu %lu %lu %lu\n", half, top, digit, wrapped, negated);
+  r = printf("%ld %ld\n", mixed, arithmetic);
+  r = printf("%lu %lu\n", h, t);
+  return 0;
+}
@@ -0,0 +1,558 @@
+/*
+***********************************************************************
+  TYPECHECK.RS : WORKS OUT THE TYPE OF EVERY EXPRESSION, RECORDS IT IN
+  THE NODE FOR CODEGEN AND REPORTS OPERANDS, ARGUMENTS, VALUES AND
+  RESULTS OF THE WRONG TYPE. IT RUNS ON PROGRAMS THE SEMANTIC CHECKS
+  ACCEPTED, SO EVERY NAME IS KNOWN AND EVERY CALL HAS THE RIGHT ARITY.
+************************************************************************
+*/
+#![allow(non_camel_case_types)]
+#![allow(non_snake_case)]
+use crate::diagnostic::Diagnostic;
+use crate::expression::*;
+
+struct signature {
+    name: String,
+    params: Vec<DataType>,
+    returnType: DataType,
+    is_variadic: bool,
+}
+
+struct typeChecker {
+    functions: Vec<signature>,
+    // return type of the function being checked
+    returnType: DataType,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/*
+***********************************************************************
+  FUNCTION TO CHECK AND ANNOTATE A WHOLE PROGRAM, RETURNING EVERY
+  PROBLEM FOUND
+************************************************************************
+*/
+pub fn check_types(worklist: &mut RList) -> Vec<Diagnostic> {
+    let mut check = typeChecker {
+        functions: vec![],
+        returnType: DataType::LONG,
+        diagnostics: vec![],
+    };
+
+    // Every signature and global first, functions can use anything declared later
+    let mut globals: Vec<(String, DataType)> = vec![];
+    let mut current = Some(&*worklist);
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            match node.type_ {
+                NodeType::FUNCTIONDECL | NodeType::EXTERNDECL => check.functions.push(signature {
+                    name: node.name.clone(),
+                    params: list_types(node.arguments.as_deref()),
+                    returnType: node.dataType.clone(),
+                    is_variadic: node.is_variadic,
+                }),
+                NodeType::VARDECL => globals.push((node.name.clone(), node.dataType.clone())),
+                _ => {}
+            }
+        }
+        current = item.next.as_deref();
+    }
+
+    let mut current = Some(worklist);
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_deref_mut() {
+            match node.type_ {
+                NodeType::FUNCTIONDECL => check.check_function(node, &globals),
+                NodeType::VARDECL => {
+                    let mut scopes: scopeList = vec![globals.clone()];
+                    let message = format!("can't initialize {} {} with", node.dataType.type_name(), node.name);
+                    if let Some(right) = node.right.as_deref_mut() {
+                        check.check_value(right, &node.dataType, &mut scopes, &message);
+                    }
+                }
+                _ => {}
+            }
+        }
+        current = item.next.as_deref_mut();
+    }
+
+    check.diagnostics
+}
+
+fn list_types(list: Option<&RList>) -> Vec<DataType> {
+    let mut types: Vec<DataType> = vec![];
+    let mut current = list;
+
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            types.push(node.dataType.clone());
+        }
+        current = item.next.as_deref();
+    }
+
+    types
+}
+
+fn operator_symbol(op: OpType) -> &'static str {
+    match op {
+        OpType::MULTIPLY => "*",
+        OpType::DIVIDE => "/",
+        OpType::MOD => "%",
+        OpType::ADD => "+",
+        OpType::SUBTRACT | OpType::NEGATE => "-",
+        OpType::BOR => "|",
+        OpType::BAND => "&",
+        OpType::BXOR => "^",
+        OpType::BSHR => ">>",
+        OpType::BSHL => "<<",
+        OpType::BNOT => "~",
+        OpType::LNOT => "!",
+        _ => "?",
+    }
+}
+
+fn is_number(dataType: &DataType) -> bool {
+    dataType.is_integer() || dataType.is_float()
+}
+
+/*
+***********************************************************************
+  FUNCTION TO DECIDE IF A VALUE CAN BE STORED IN A VARIABLE, PASSED OR
+  RETURNED AS THE GIVEN TYPE. NUMBERS CONVERT TO EACH OTHER, A POINTER
+  ONLY TAKES A POINTER TO THE SAME TYPE OR THE CONSTANT 0, A STRUCT
+  ONLY THE SAME STRUCT.
+************************************************************************
+*/
+fn assignable(value: &RNode, from: &DataType, to: &DataType) -> bool {
+    if is_number(from) && is_number(to) {
+        return true;
+    }
+    if to.is_pointer() && from.is_integer() {
+        return fold_constant(value) == Some(0);
+    }
+    from == to
+}
+
+impl typeChecker {
+    fn error(&mut self, node: &RNode, message: String) {
+        self.diagnostics.push(Diagnostic::new(node.line, node.col, message));
+    }
+
+    fn check_function(&mut self, function: &mut RNode, globals: &[(String, DataType)]) {
+        self.returnType = function.dataType.clone();
+
+        let params: Vec<(String, DataType)> = list_types(function.arguments.as_deref())
+            .into_iter()
+            .zip(parameter_names(function.arguments.as_deref()))
+            .map(|(dataType, name)| (name, dataType))
+            .collect();
+        let mut scopes: scopeList = vec![globals.to_vec(), params];
+
+        if let Some(statements) = function.statements.as_deref_mut() {
+            self.check_statements(statements, &mut scopes);
+        }
+    }
+
+    fn check_statements(&mut self, statements: &mut RList, scopes: &mut scopeList) {
+        let mut current = Some(statements);
+
+        while let Some(item) = current {
+            if let Some(statement) = item.node.as_deref_mut() {
+                self.check_statement(statement, scopes);
+            }
+            current = item.next.as_deref_mut();
+        }
+    }
+
+    fn check_statement(&mut self, statement: &mut RNode, scopes: &mut scopeList) {
+        match statement.stmtCode {
+            StmtType::ASSIGN => {
+                let target_type = match statement.left.as_deref_mut() {
+                    // A store into an element, through a pointer or into a field
+                    Some(target) => {
+                        let target_type = self.check_expression(target, scopes);
+                        if target.opCode == OpType::FIELD && self.is_array_field(target) {
+                            self.error(target, format!("array field {} can't be assigned as a whole", target.name));
+                            return;
+                        }
+                        target_type
+                    }
+                    None => match lookup_visible(scopes, &statement.name).cloned() {
+                        Some(dataType) => Some(dataType),
+                        // Assigning a name that isn't visible declares a long in the current scope
+                        None => {
+                            if let Some(scope) = scopes.last_mut() {
+                                scope.push((statement.name.clone(), DataType::LONG));
+                            }
+                            Some(DataType::LONG)
+                        }
+                    },
+                };
+
+                if let (Some(target_type), Some(right)) = (target_type, statement.right.as_deref_mut()) {
+                    let message = format!("can't assign to {} with", target_type.type_name());
+                    self.check_value(right, &target_type, scopes, &message);
+                }
+            }
+
+            StmtType::DECLARE => {
+                // The value is checked before the variable is in scope
+                if let Some(right) = statement.right.as_deref_mut() {
+                    let message = format!("can't initialize {} {} with", statement.dataType.type_name(), statement.name);
+                    self.check_value(right, &statement.dataType, scopes, &message);
+                }
+                if let Some(scope) = scopes.last_mut() {
+                    scope.push((statement.name.clone(), statement.dataType.clone()));
+                }
+            }
+
+            StmtType::RETURN => {
+                if let Some(left) = statement.left.as_deref_mut() {
+                    let returnType = self.returnType.clone();
+                    let message = format!("can't return from a function returning {} with", returnType.type_name());
+                    self.check_value(left, &returnType, scopes, &message);
+                }
+            }
+
+            StmtType::BLOCK => {
+                if let Some(body) = statement.statements.as_deref_mut() {
+                    scopes.push(vec![]);
+                    self.check_statements(body, scopes);
+                    scopes.pop();
+                }
+            }
+
+            // Only an integer or a pointer fits in a general purpose register
+            StmtType::ASM => {
+                let mut current = statement.arguments.as_deref_mut();
+                while let Some(item) = current {
+                    if let Some(operand) = item.node.as_deref_mut() {
+                        let dataType = lookup_visible(scopes, &operand.name).cloned().unwrap_or(DataType::LONG);
+                        if !asm_is_memory(&operand.constraint) && !dataType.is_integer() && !dataType.is_pointer() {
+                            let message = format!("asm operand {} of type {} can't be in a register", operand.name, dataType.type_name());
+                            self.error(operand, message);
+                        }
+                        operand.valueType = Some(dataType);
+                    }
+                    current = item.next.as_deref_mut();
+                }
+            }
+
+            StmtType::S_NONE => {}
+        }
+    }
+
+    // Checks an expression whose value ends up as the given type, the
+    // message says where and is completed with the value's type
+    fn check_value(&mut self, value: &mut RNode, to: &DataType, scopes: &mut scopeList, message: &str) {
+        if let Some(from) = self.check_expression(value, scopes) {
+            if !assignable(value, &from, to) {
+                self.error(value, format!("{} {}", message, from.type_name()));
+            }
+        }
+    }
+
+    fn is_array_field(&self, field: &RNode) -> bool {
+        let base_type = field.left.as_ref().and_then(|base| base.valueType.clone());
+        base_type
+            .and_then(|base_type| base_type.field(&field.name))
+            .is_some_and(|field| field.dataType.is_array())
+    }
+
+    /*
+    ***********************************************************************
+      FUNCTION TO WORK OUT THE TYPE OF AN EXPRESSION AND RECORD IT IN THE
+      NODE. NONE IF THE EXPRESSION HAS AN ERROR, WHICH WAS REPORTED.
+    ************************************************************************
+    */
+    fn check_expression(&mut self, expr: &mut RNode, scopes: &mut scopeList) -> Option<DataType> {
+        let dataType = match expr.exprCode {
+            // An array used as a value is a pointer to its first element,
+            // a function name is a pointer to the function
+            ExprType::VARIABLE => match lookup_visible(scopes, &expr.name) {
+                Some(dataType) => dataType.decay(),
+                None => self.function_pointer(&expr.name)?,
+            },
+            // Constants are ints unless they don't fit in one, or in a long
+            ExprType::CONSTANT if expr.dataType == DataType::ULONG => DataType::ULONG,
+            ExprType::CONSTANT if expr.value == expr.value as i32 as i64 => DataType::INT,
+            ExprType::CONSTANT => DataType::LONG,
+            ExprType::FCONSTANT => DataType::DOUBLE,
+            ExprType::STRING => DataType::POINTER(Box::new(DataType::CHAR)),
+            ExprType::OPERATION if expr.opCode == OpType::FUNCTIONCALL => self.check_call(expr, scopes)?,
+            ExprType::OPERATION => self.check_operation(expr, scopes)?,
+            ExprType::E_NONE => return None,
+        };
+
+        expr.valueType = Some(dataType.clone());
+        Some(dataType)
+    }
+
+    fn function_pointer(&self, name: &str) -> Option<DataType> {
+        let func = self.functions.iter().find(|func| func.name == name)?;
+        let function = DataType::FUNCTION(Box::new(func.returnType.clone()), func.params.clone(), func.is_variadic);
+        Some(DataType::POINTER(Box::new(function)))
+    }
+
+    fn check_call(&mut self, call: &mut RNode, scopes: &mut scopeList) -> Option<DataType> {
+        let callee = call.left.as_ref().map_or(call.name.clone(), |left| left.name.clone());
+
+        // A variable of the callee's name holds the address of the function to call
+        let (params, returnType) = match lookup_visible(scopes, &callee).cloned() {
+            Some(pointer) => match pointer.element() {
+                Some(DataType::FUNCTION(returnType, params, _)) => {
+                    let found = (params.clone(), (**returnType).clone());
+                    call.is_indirect = true;
+                    if let Some(left) = call.left.as_deref_mut() {
+                        left.valueType = Some(pointer);
+                    }
+                    found
+                }
+                _ => return None,
+            },
+            None => match self.functions.iter().find(|func| func.name == callee) {
+                Some(func) => (func.params.clone(), func.returnType.clone()),
+                None => return None,
+            },
+        };
+
+        // Arguments past the parameters of a variadic callee keep their own type
+        let mut index = 0;
+        let mut current = call.arguments.as_deref_mut();
+        while let Some(item) = current {
+            if let Some(arg) = item.node.as_deref_mut() {
+                match params.get(index) {
+                    Some(param) => {
+                        let message = format!("argument {} of {} should be {} but is", index + 1, callee, param.type_name());
+                        self.check_value(arg, param, scopes, &message);
+                    }
+                    None => {
+                        self.check_expression(arg, scopes);
+                    }
+                }
+                index += 1;
+            }
+            current = item.next.as_deref_mut();
+        }
+
+        Some(returnType)
+    }
+
+    fn check_operation(&mut self, expr: &mut RNode, scopes: &mut scopeList) -> Option<DataType> {
+        // Both operands are checked even if the first one is wrong
+        let left = expr.left.as_deref_mut().map(|left| self.check_expression(left, scopes));
+        let right = expr.right.as_deref_mut().map(|right| self.check_expression(right, scopes));
+        let (left, right) = match (left, right) {
+            (Some(None), _) | (_, Some(None)) => return None,
+            (left, right) => (left.flatten(), right.flatten()),
+        };
+
+        let op = expr.opCode;
+        let symbol = operator_symbol(op);
+        match op {
+            // The address of an array is taken as the address of its first
+            // element, the address of a function is the function name's value
+            OpType::ADDRESS => {
+                let operand = left?;
+                let is_array = expr.left.as_ref().is_some_and(|operand| {
+                    operand.exprCode == ExprType::VARIABLE
+                        && lookup_visible(scopes, &operand.name).is_none_or(|dataType| dataType.is_array())
+                });
+                if is_array {
+                    Some(operand)
+                } else {
+                    Some(DataType::POINTER(Box::new(operand)))
+                }
+            }
+
+            OpType::DEREF => {
+                let operand = left?;
+                if operand.is_function_pointer() {
+                    self.error(expr, format!("can't dereference {}, call it instead", operand.type_name()));
+                    return None;
+                }
+                match operand.element() {
+                    Some(element) if operand.is_pointer() => Some(element.clone()),
+                    _ => {
+                        self.error(expr, format!("can't dereference {}, it is not a pointer", operand.type_name()));
+                        None
+                    }
+                }
+            }
+
+            OpType::INDEX => {
+                let (array, index) = (left?, right?);
+                if array.is_function_pointer() {
+                    self.error(expr, format!("can't index {}, it is a function pointer", array.type_name()));
+                    return None;
+                }
+                if !index.is_integer() {
+                    self.error(expr, format!("array index must be an integer, not {}", index.type_name()));
+                    return None;
+                }
+                if array.element().is_none() {
+                    self.error(expr, format!("can't index {}, it is not an array or a pointer", array.type_name()));
+                }
+                array.element().cloned()
+            }
+
+            OpType::FIELD => {
+                let base = left?;
+                if !base.is_struct() {
+                    self.error(expr, format!("{} is not a struct, it has no field {}", base.type_name(), expr.name));
+                    return None;
+                }
+                match base.field(&expr.name) {
+                    // An array field is used as a pointer to its first element
+                    Some(field) => Some(field.dataType.decay()),
+                    None => {
+                        self.error(expr, format!("{} has no field {}", base.type_name(), expr.name));
+                        None
+                    }
+                }
+            }
+
+            // The single operand may hang off either side
+            OpType::NEGATE => {
+                let operand = left.or(right)?;
+                if !is_number(&operand) {
+                    self.error(expr, format!("operator - needs a number, not {}", operand.type_name()));
+                    return None;
+                }
+                Some(operand.promote())
+            }
+
+            OpType::BNOT => {
+                let operand = left?;
+                if !operand.is_integer() {
+                    self.error(expr, format!("operator ~ needs an integer, not {}", operand.type_name()));
+                    return None;
+                }
+                Some(operand.promote())
+            }
+
+            // Anything compared with 0 can be negated, the result is 0 or 1
+            OpType::LNOT => {
+                let operand = left?;
+                if !is_number(&operand) && !operand.is_pointer() {
+                    self.error(expr, format!("operator ! needs a number or a pointer, not {}", operand.type_name()));
+                    return None;
+                }
+                Some(DataType::INT)
+            }
+
+            // Functions have no size, there is no arithmetic on their pointers
+            OpType::ADD | OpType::SUBTRACT => {
+                let (left, right) = (left?, right?);
+                if left.is_function_pointer() || right.is_function_pointer() {
+                    let message = format!("operator {} can't take {} and {}", symbol, left.type_name(), right.type_name());
+                    self.error(expr, message);
+                    return None;
+                }
+                match (left.is_pointer(), right.is_pointer()) {
+                    (true, false) if right.is_integer() => Some(left),
+                    (false, true) if op == OpType::ADD && left.is_integer() => Some(right),
+                    (true, true) if op == OpType::SUBTRACT && left == right => Some(DataType::LONG),
+                    (false, false) if is_number(&left) && is_number(&right) => Some(left.common(&right)),
+                    _ => {
+                        let message = format!("operator {} can't take {} and {}", symbol, left.type_name(), right.type_name());
+                        self.error(expr, message);
+                        None
+                    }
+                }
+            }
+
+            OpType::MULTIPLY | OpType::DIVIDE => {
+                let (left, right) = (left?, right?);
+                if !is_number(&left) || !is_number(&right) {
+                    let message = format!("operator {} needs numbers, not {} and {}", symbol, left.type_name(), right.type_name());
+                    self.error(expr, message);
+                    return None;
+                }
+                Some(left.common(&right))
+            }
+
+            OpType::MOD | OpType::BOR | OpType::BAND | OpType::BXOR | OpType::BSHL | OpType::BSHR => {
+                let (left, right) = (left?, right?);
+                if !left.is_integer() || !right.is_integer() {
+                    let message = format!("operator {} needs integers, not {} and {}", symbol, left.type_name(), right.type_name());
+                    self.error(expr, message);
+                    return None;
+                }
+                // A shift has the type of the value shifted
+                if op == OpType::BSHL || op == OpType::BSHR {
+                    Some(left.promote())
+                } else {
+                    Some(left.common(&right))
+                }
+            }
+
+            _ => None,
+        }
+    }
+}
+
+fn parameter_names(list: Option<&RList>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let mut current = list;
+
+    while let Some(item) = current {
+        if let Some(node) = item.node.as_ref() {
+            names.push(node.name.clone());
+        }
+        current = item.next.as_deref();
+    }
+
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn errors(source: &str) -> Vec<(usize, usize, String)> {
+        let mut program = parse_program(source).expect("the source doesn't parse");
+        check_types(&mut program).into_iter().map(|diagnostic| (diagnostic.line, diagnostic.col, diagnostic.message)).collect()
+    }
+
+    #[test]
+    fn arguments_must_have_the_parameter_types() {
+        let source = "struct point { long x; long y; };\nlong take(long *p, double d) { return *p; }\n\
+                      long f(long a, double d, struct point s) {\n  long x = take(a, d);\n  x = take(&a, s);\n  return take(&a, a);\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (4, 17, "argument 1 of take should be long* but is long".to_string()),
+                (5, 16, "argument 2 of take should be double but is struct point".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bitwise_operators_need_integers() {
+        let source = "long f(long a, double d) {\n  long b = a & d;\n  long c = d << 2;\n  double e = ~d;\n  long g = a % d;\n  return a | a;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (2, 14, "operator & needs integers, not long and double".to_string()),
+                (3, 14, "operator << needs integers, not double and int".to_string()),
+                (4, 14, "operator ~ needs an integer, not double".to_string()),
+                (5, 14, "operator % needs integers, not long and double".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stores_and_returns_match_their_target() {
+        let source = "struct point { long x; long y; };\nlong f(long a, double d, struct point s) {\n  long *p = d;\n  \
+                      long x = s.z;\n  return s;\n}";
+        assert_eq!(
+            errors(source),
+            vec![
+                (3, 13, "can't initialize long* p with double".to_string()),
+                (4, 13, "struct point has no field z".to_string()),
+                (5, 10, "can't return from a function returning long with struct point".to_string()),
+            ]
+        );
+    }
+}